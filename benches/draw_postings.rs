@@ -0,0 +1,43 @@
+//! Draw time of a transaction with many postings. Run with `cargo bench`
+
+use std::fs;
+
+use beancount_tui::{app::App, cli::Args, ui};
+use criterion::{criterion_group, criterion_main, Criterion};
+use ratatui::{backend::TestBackend, Terminal};
+
+const N_POSTINGS: usize = 500;
+
+fn draw_many_postings(c: &mut Criterion) {
+    let file = std::env::temp_dir().join(format!(
+        "beancount-tui-bench-{}.beancount",
+        std::process::id()
+    ));
+    let mut content = String::from("2023-01-01 * \"Payroll\" \"Splits\"\n");
+    for i in 0..N_POSTINGS {
+        content.push_str(&format!("    Expenses:Split{}  1.00 EUR\n", i));
+    }
+    content.push_str("    Assets:Bank\n");
+    fs::write(&file, content).unwrap();
+    let mut app = App::new(Args {
+        file: vec![file.clone()],
+        no_session: true,
+        ..Default::default()
+    })
+    .unwrap();
+    fs::remove_file(file).unwrap();
+    app.focus_on_postings = true;
+    app.currently_selected_posting = N_POSTINGS / 2;
+
+    let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+    c.bench_function("draw 500 postings", |b| {
+        b.iter(|| {
+            terminal
+                .draw(|frame| ui::draw(frame, &app).unwrap())
+                .unwrap();
+        })
+    });
+}
+
+criterion_group!(benches, draw_many_postings);
+criterion_main!(benches);
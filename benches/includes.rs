@@ -0,0 +1,60 @@
+//! Startup time on a ledger split into monthly files, parsed on one thread and on
+//! one thread per CPU like `--jobs`. Run with `cargo bench --bench includes`
+
+use std::fs;
+
+use beancount_tui::{app::App, cli::Args};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const N_YEARS: usize = 10;
+const N_PER_MONTH: usize = 300;
+
+fn includes(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join(format!(
+        "beancount-tui-bench-includes-{}",
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    let mut main =
+        String::from("2010-01-01 open Assets:Bank EUR\n2010-01-01 open Expenses:Food EUR\n\n");
+    for year in 2010..2010 + N_YEARS {
+        for month in 1..=12 {
+            let name = format!("{}-{:02}.beancount", year, month);
+            let mut content = String::new();
+            for i in 0..N_PER_MONTH {
+                content.push_str(&format!(
+                    "{}-{:02}-{:02} * \"Payee {i}\" \"Narration\"\n    Assets:Bank  -{i}.50 EUR\n    Expenses:Food\n\n",
+                    year,
+                    month,
+                    i % 28 + 1
+                ));
+            }
+            fs::write(dir.join(&name), content).unwrap();
+            main.push_str(&format!("include \"{}\"\n", name));
+        }
+    }
+    let file = dir.join("main.beancount");
+    fs::write(&file, main).unwrap();
+    let start = || {
+        App::new(Args {
+            file: vec![file.clone()],
+            no_session: true,
+            ..Default::default()
+        })
+        .unwrap()
+    };
+
+    let mut group = c.benchmark_group("includes");
+    group.sample_size(10);
+    let single = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+    group.bench_function("start with --jobs 1", |b| b.iter(|| single.install(start)));
+    group.bench_function("start with one job per CPU", |b| b.iter(start));
+    group.finish();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+criterion_group!(benches, includes);
+criterion_main!(benches);
@@ -0,0 +1,59 @@
+//! Startup time on a generated ledger. Run with `cargo bench --bench large_ledger`
+
+use std::fs;
+
+use beancount_tui::{app::App, beancount::*, cli::Args};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+const N_TRANSACTIONS: usize = 50_000;
+
+fn large_ledger(c: &mut Criterion) {
+    let file = std::env::temp_dir().join(format!(
+        "beancount-tui-bench-large-{}.beancount",
+        std::process::id()
+    ));
+    let mut content = String::new();
+    for i in 0..N_TRANSACTIONS {
+        content.push_str(&format!(
+            "2023-01-01 * \"Payee {i}\" \"Narration\"\n    Assets:Bank  -{i}.50 EUR\n    Expenses:Food\n\n"
+        ));
+    }
+    fs::write(&file, content).unwrap();
+
+    let mut group = c.benchmark_group("large ledger");
+    // a single run takes long enough, the default 100 samples would take minutes
+    group.sample_size(10);
+    group.bench_function("start with 50000 transactions", |b| {
+        b.iter(|| {
+            App::new(Args {
+                file: vec![file.clone()],
+                no_session: true,
+                ..Default::default()
+            })
+            .unwrap()
+        })
+    });
+    // what building every text area up front used to cost
+    let ledger = parse_ledger(&file).unwrap().remove(0);
+    let transactions: Vec<TransactionTui> = filter_transactions(ledger.beancount)
+        .iter()
+        .map(|d| d.try_into().unwrap())
+        .collect();
+    group.bench_function("build the text areas of 50000 transactions", |b| {
+        b.iter_batched(
+            || transactions.clone(),
+            |mut transactions| {
+                for transaction in &mut transactions {
+                    transaction.metadata_textareas_mut();
+                }
+                transactions
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+    fs::remove_file(file).unwrap();
+}
+
+criterion_group!(benches, large_ledger);
+criterion_main!(benches);
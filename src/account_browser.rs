@@ -0,0 +1,194 @@
+use std::collections::BTreeSet;
+
+use crate::beancount::{account_tree, AccountNode};
+
+/// State of the account tree popup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountBrowser {
+    roots: Vec<AccountNode>,    // the tree of all known accounts
+    expanded: BTreeSet<String>, // accounts whose children are shown
+    pub selected: usize,        // index into `rows`
+}
+
+/// A visible line of the tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Row<'a> {
+    pub node: &'a AccountNode,
+    pub depth: usize,   // 0 for the top level accounts
+    pub expanded: bool, // wether the children are shown
+}
+
+impl AccountBrowser {
+    /// The tree of `accounts`, opened up to `account` as far as it exists
+    pub fn new(accounts: &BTreeSet<String>, account: &str) -> Self {
+        let mut browser = Self {
+            roots: account_tree(accounts),
+            expanded: BTreeSet::new(),
+            selected: 0,
+        };
+        browser.reveal(account.trim());
+        browser
+    }
+
+    /// Expands the parents of `account` and selects it, or its deepest existing parent
+    fn reveal(&mut self, account: &str) {
+        let mut path = Vec::new();
+        let mut level = &self.roots;
+        for component in account.split(':') {
+            let Some(node) = level.iter().find(|node| node.name == component) else {
+                break;
+            };
+            path.push(node.account.clone());
+            level = &node.children;
+        }
+        let Some(deepest) = path.pop() else {
+            return;
+        };
+        self.expanded.extend(path);
+        self.select(&deepest);
+    }
+
+    fn select(&mut self, account: &str) {
+        if let Some(index) = self
+            .rows()
+            .iter()
+            .position(|row| row.node.account == account)
+        {
+            self.selected = index;
+        }
+    }
+
+    /// The accounts that are shown, children right after their expanded parent
+    pub fn rows(&self) -> Vec<Row<'_>> {
+        fn visit<'a>(
+            nodes: &'a [AccountNode],
+            depth: usize,
+            expanded: &BTreeSet<String>,
+            rows: &mut Vec<Row<'a>>,
+        ) {
+            for node in nodes {
+                let is_expanded = expanded.contains(&node.account);
+                rows.push(Row {
+                    node,
+                    depth,
+                    expanded: is_expanded,
+                });
+                if is_expanded {
+                    visit(&node.children, depth + 1, expanded, rows);
+                }
+            }
+        }
+        let mut rows = Vec::new();
+        visit(&self.roots, 0, &self.expanded, &mut rows);
+        rows
+    }
+
+    /// Moves the selection one row, it stops at the first and last row
+    pub fn select_next(&mut self, forward: bool) {
+        let last = self.rows().len().saturating_sub(1);
+        self.selected = if forward {
+            (self.selected + 1).min(last)
+        } else {
+            self.selected.saturating_sub(1)
+        };
+    }
+
+    /// Shows the children of the selected account, or moves to the first child if
+    /// they are already shown
+    pub fn expand(&mut self) {
+        let rows = self.rows();
+        let Some(row) = rows.get(self.selected) else {
+            return;
+        };
+        if row.node.children.is_empty() {
+            return;
+        }
+        if row.expanded {
+            self.selected += 1;
+        } else {
+            let account = row.node.account.clone();
+            self.expanded.insert(account);
+        }
+    }
+
+    /// Hides the children of the selected account, or moves to its parent if they
+    /// are hidden
+    pub fn collapse(&mut self) {
+        let rows = self.rows();
+        let Some(row) = rows.get(self.selected) else {
+            return;
+        };
+        let account = row.node.account.clone();
+        if row.expanded {
+            self.expanded.remove(&account);
+        } else if let Some((parent, _)) = account.rsplit_once(':') {
+            let parent = parent.to_string();
+            self.expanded.remove(&parent);
+            self.select(&parent);
+        }
+    }
+
+    /// The full name of the selected account
+    pub fn selected_account(&self) -> Option<String> {
+        self.rows()
+            .get(self.selected)
+            .map(|row| row.node.account.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_browser(account: &str) -> AccountBrowser {
+        let accounts = [
+            "Assets:Bank:Checking",
+            "Assets:Cash",
+            "Expenses:Food:Groceries",
+            "Expenses:Rent",
+        ]
+        .map(String::from)
+        .into();
+        AccountBrowser::new(&accounts, account)
+    }
+
+    fn names(browser: &AccountBrowser) -> Vec<&str> {
+        browser
+            .rows()
+            .into_iter()
+            .map(|row| row.node.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn opens_up_to_the_current_account() {
+        let browser = test_browser("Expenses:Food:Snacks");
+        assert_eq!(names(&browser), ["Assets", "Expenses", "Food", "Rent"]);
+        assert_eq!(browser.selected_account().unwrap(), "Expenses:Food");
+
+        let browser = test_browser("");
+        assert_eq!(names(&browser), ["Assets", "Expenses"]);
+        assert_eq!(browser.selected_account().unwrap(), "Assets");
+    }
+
+    #[test]
+    fn expand_and_collapse() {
+        let mut browser = test_browser("");
+        browser.expand();
+        assert_eq!(names(&browser), ["Assets", "Bank", "Cash", "Expenses"]);
+        browser.expand();
+        assert_eq!(browser.selected_account().unwrap(), "Assets:Bank");
+        browser.select_next(true);
+        browser.select_next(true);
+        browser.select_next(true);
+        assert_eq!(browser.selected_account().unwrap(), "Expenses");
+        // stays at the last row
+        browser.select_next(true);
+        assert_eq!(browser.selected_account().unwrap(), "Expenses");
+
+        browser.selected = 2;
+        browser.collapse();
+        assert_eq!(browser.selected_account().unwrap(), "Assets");
+        assert_eq!(names(&browser), ["Assets", "Expenses"]);
+    }
+}
@@ -1,56 +1,199 @@
-use std::ops::Sub;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
 
-use color_eyre::{eyre::Context, Result};
+use color_eyre::{
+    eyre::{bail, eyre, Context},
+    Result,
+};
 use ratatui::{
-    crossterm::event::{self, Event, KeyEvent, KeyEventKind},
-    style::{Color, Style, Stylize},
-    widgets::{Block, Borders},
+    backend::Backend,
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    style::Style,
+    Terminal,
 };
-use tui_textarea::{Input, Key, TextArea};
+use serde::Deserialize;
+use tui_textarea::{CursorMove, Input, Key, TextArea};
 
 use crate::{
-    beancount::{filter_transactions, parse_beancount_file, PostingField, TransactionTui},
+    account_browser::AccountBrowser,
+    beancount::{
+        balancing_amount, clean_transaction, collect_account_dates, context_directives,
+        extension_warning, filter_transactions, first_invalid_amount, format_transaction_with,
+        format_transactions, format_transactions_by_source, format_transactions_with_context,
+        inactive_postings, is_valid_amount, issue_fields, most_common_currency, negate_amount,
+        normalize_account, open_accounts, parse_amount, parse_ledger_with_progress, payee_accounts,
+        set_textarea_content, split_amount, textarea_content, uninterpreted_directives,
+        unopened_postings, validate_transaction, AccountDates, ContextDirective, PostingField,
+        PostingTui, TransactionField, TransactionTui,
+    },
+    calendar::Calendar,
     cli::Args,
-    terminal, ui,
+    clipboard::SystemClipboard,
+    command::{parse_command, Filter, TypedCommand},
+    completion::{expand_account, Completion, Expansion},
+    config::Config,
+    error::BeancountTuiError,
+    export::write_json,
+    import::import_csv,
+    keymap::{self, Command},
+    save_review::{Problem, SaveReview, Severity},
+    session::{content_hash, session_path, EditedTransaction, Session},
+    terminal,
+    theme::Theme,
+    ui,
+    utils::{
+        format_naive_date, parse_date, parse_relative_date, shift_date, today, write_atomically,
+    },
+    verify::{check_parses, run_check_command, CheckFailure},
+    watch::FileWatcher,
+    writeback::{line_starts, plan_writeback, update_spans, FileChange},
 };
 
-const METAFIELD_ORDER: [InputFieldType; 4] = [
+pub const METAFIELD_ORDER: [InputFieldType; 5] = [
     InputFieldType::Date,
     InputFieldType::Flag,
     InputFieldType::Payee,
     InputFieldType::Narration,
+    InputFieldType::Comment,
 ];
 
-const POSTING_FIELD_ORDER: [PostingField; 3] = [
+pub const POSTING_FIELD_ORDER: [PostingField; 3] = [
     PostingField::Account,
     PostingField::Amount,
     PostingField::Currency,
 ];
 
-#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum InputFieldType {
     Date,
     Flag,
     #[default]
     Payee,
     Narration,
+    Comment,
     Account,
     Amount,
     Currency,
 }
 
+impl InputFieldType {
+    /// The title of the field's border
+    pub fn title(&self) -> &'static str {
+        match self {
+            InputFieldType::Date => "Date",
+            InputFieldType::Flag => "",
+            InputFieldType::Payee => "Payee",
+            InputFieldType::Narration => "Narration",
+            InputFieldType::Comment => "Comment",
+            InputFieldType::Account => "Account",
+            InputFieldType::Amount => "Amount",
+            InputFieldType::Currency => "Currency",
+        }
+    }
+
+    /// Whether the field only makes sense as a single line of text, the narration
+    /// can span lines if `multiline_narration` is enabled
+    pub fn is_single_line(&self) -> bool {
+        *self != InputFieldType::Narration
+    }
+}
+
+impl From<PostingField> for InputFieldType {
+    fn from(field: PostingField) -> Self {
+        match field {
+            PostingField::Account => InputFieldType::Account,
+            PostingField::Amount => InputFieldType::Amount,
+            PostingField::Currency => InputFieldType::Currency,
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct InputField<'t> {
     pub input_type: InputFieldType,
     pub textarea: TextArea<'t>,
 }
 
+/// Minimum time between two automatic saves
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(2);
+/// How often the review progress is stored
+const SESSION_INTERVAL: Duration = Duration::from_secs(30);
+/// How long a status message stays visible
+const STATUS_MESSAGE_DURATION: Duration = Duration::from_secs(4);
+/// How many transactions before and after the current one keep their text areas
+const WIDGET_CACHE_RADIUS: usize = 2;
+/// Status shown when an edit is attempted with `--readonly`
+const READ_ONLY_HINT: &str = "Read-only mode, editing is disabled";
+const NO_POSTINGS: &str = "The transaction has no postings";
+
+/// Everything the main loop reacts to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Key(KeyEvent),
+    Paste(String), // text pasted into the terminal, with bracketed paste
+    Resize,
+    Tick, // no input arrived during the tick interval
+}
+
+#[derive(Debug)]
+pub struct StatusMessage {
+    pub text: String,
+    pub created: Instant,
+}
+
 #[derive(Debug)]
 pub enum InputMode {
     Normal,
     Insert,
 }
 
+/// What happens when a popup is confirmed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PendingAction {
+    #[default]
+    Exit,
+    AcceptRemaining, // flag all transactions from the current one on as reviewed, then quit
+    ResumeSession,   // continue where the last session stopped
+    ReloadFile {
+        keep_edits: bool,
+    }, // the input file was changed by another program
+    DeleteTransaction(usize), // by index
+    DeletePosting(usize, usize), // transaction and posting index
+    FlagAll(char),   // sets the flag of every transaction
+    Dismiss,         // only informs, e.g. about skipped CSV rows
+}
+
+/// The amounts a batch edit like `:negate` changed, undone together with one key
+#[derive(Debug)]
+struct BatchUndo {
+    description: String,
+    amounts: Vec<(usize, usize, String)>, // transaction, posting and the amount before
+    dirty: Vec<(usize, bool)>,            // changed transactions and whether they were dirty
+    unsaved_changes: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Popup {
+    pub active: bool,
+    pub prompt: String,
+    pub on_confirm: PendingAction,
+}
+
+impl Popup {
+    fn new(prompt: impl Into<String>, on_confirm: PendingAction) -> Self {
+        Self {
+            active: true,
+            prompt: prompt.into(),
+            on_confirm,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct App<'t> {
     pub exit: bool,                               // wether we want to exit the program
@@ -62,341 +205,4597 @@ pub struct App<'t> {
     pub current_mode: InputMode,                  // in which editing mode are we in
     pub current_account: usize,                   // which account is currently selected
     pub focus_on_postings: bool, // wether we are currently focused on a posting field or a metadata field
+    pub status_message: Option<StatusMessage>, // message shown in the status bar until it expires
+    clipboard: SystemClipboard,  // lazily opened system clipboard
+    pub popup: Popup,            // confirmation popup shown on top of the transaction
+    pub output: Option<PathBuf>, // the file the transactions are saved to
+    pub export_json: Option<PathBuf>, // the file the JSON export is written to when saving
+    in_place: bool,              // wether to save edits back into their source files
+    dry_run: bool,               // wether in place saves only report the changes
+    pub dry_run_report: Vec<String>, // diffs of the in place saves skipped by `dry_run`
+    check_cmd: Option<String>,   // run on the output before it is written, `{}` is its path
+    force: bool,                 // wether to write output that fails the checks
+    pub check_failure: Option<CheckFailure>, // why the last save was blocked, open until closed
+    pub unsaved_changes: bool,   // wether there are edits that weren't written to the output yet
+    reordered: bool,             // transactions were sorted or deleted since the input was read
+    autosave: bool,              // wether to save when leaving an edited transaction
+    autosave_pending: bool,      // an autosave was requested but debounced
+    pub read_only: bool,         // wether editing is disabled with `--readonly`
+    last_save: Option<Instant>,  // when the output was last written
+    pub config: Config,          // the user configuration
+    pub theme: Theme,            // the styles used to draw the app
+    pub file: PathBuf,           // the first input file, the session is stored for it
+    inputs: Vec<PathBuf>,        // all files given with `--file`, or the CSV file
+    csv_input: bool,             // wether `file` is a CSV file instead of a beancount file
+    file_modified: BTreeMap<PathBuf, Option<SystemTime>>, // modification time of each input when last read
+    pub marks: HashSet<usize>, // indices of the transactions marked to come back to later
+    pub known_accounts: BTreeSet<String>, // accounts opened in the input file
+    account_dates: BTreeMap<String, AccountDates>, // when the accounts were opened and closed
+    pub open_check: bool,      // wether to warn about postings to unopened accounts
+    pub completion: Completion, // account completion popup
+    pub calendar: Option<Calendar>, // date picker, open while the user chooses a date
+    pub account_browser: Option<AccountBrowser>, // account tree, open while the user browses it
+    pub save_review: Option<SaveReview>, // problems found before saving, open until saved or left
+    pub command_line: Option<TextArea<'t>>, // the `:` prompt while it is open
+    pub comment_editor: Option<TextArea<'t>>, // the comment lines above the transaction while edited
+    pub filter: Option<Filter>, // only transactions matching it are visited while navigating
+    payee_accounts: HashMap<String, Vec<String>>, // accounts used with each payee, most frequent first
+    pub context: Vec<ContextDirective>, // `pad`, `balance` and `note` directives, written out unchanged
+    pub show_context: bool, // wether the context directives before the transaction are shown
+    pub rejected: Vec<(usize, TransactionTui<'t>)>, // taken out of the output, with their old index
+    rejected_output: PathBuf, // where the rejected transactions are written when saving
+    undo_reject: bool,      // wether the last rejection can be undone until the next edit
+    pub rejected_list: Option<usize>, // selected entry of the list of rejected transactions
+    batch_undo: Option<BatchUndo>, // the last batch edit, can be undone until the next edit
+    session_path: Option<PathBuf>, // where the review progress is stored, `None` if disabled
+    pending_session: Option<Session>, // stored session the user can choose to resume
+    content_hash: u64,      // hash of the input files when they were read
+    n_parsed: usize,        // number of transactions read from the input files
+    last_session_write: Instant, // when the session was last stored
+    watcher: Option<FileWatcher>, // follows changes of the ledger files with `--watch`
 }
 
 impl<'t> App<'t> {
     pub fn new(args: Args) -> Result<Self> {
+        Self::with_progress(args, &|_| {})
+    }
+
+    /// Like `new`, calling `progress` with the number of directives read so far
+    /// while the input is loaded
+    pub fn with_progress(args: Args, progress: &(dyn Fn(usize) + Sync)) -> Result<Self> {
+        let theme = Theme::new(terminal::color_support(args.color_choice()));
+        let mut config = Config::load(args.config.as_deref())?;
+        if let Some(currency) = args.currency {
+            config.default_currency = Some(currency);
+        }
+        if let Some(indent) = args.indent {
+            config.format.indent = indent;
+        }
+        if let Some(amount_column) = args.amount_column {
+            config.format.amount_column = amount_column;
+        }
+        if args.no_align {
+            config.format.align_currencies = false;
+        }
+        if args.group_by_month {
+            config.format.group_by_month = true;
+        }
+        config.validate()?;
         // handle inputs
-        let beancount = parse_beancount_file(&args.file)?;
-        let transactions: Vec<TransactionTui<'t>> = filter_transactions(beancount)
-            .iter()
-            .map(|t| t.try_into().expect("Couldn't parse trnsaction!"))
-            .collect();
+        let (inputs, csv_input) = match (args.file, args.csv) {
+            (_, Some(csv)) => (vec![csv], true),
+            (files, None) if !files.is_empty() => (files, false),
+            _ => bail!("no input file given, use --file or --csv"),
+        };
+        let file = inputs[0].clone();
+        let rejected_output = args
+            .rejected
+            .unwrap_or_else(|| file.with_extension("rejected.beancount"));
+        let LoadedInput {
+            transactions,
+            known_accounts,
+            account_dates,
+            import_errors,
+            uninterpreted,
+            context,
+        } = load_file(&inputs, csv_input, &config, progress)?;
+        if config.default_currency.is_none() && config.infer_currency {
+            config.default_currency = most_common_currency(&transactions);
+        }
+        let extension_warning = if csv_input {
+            None
+        } else {
+            inputs.iter().find_map(|input| extension_warning(input))
+        };
+        if transactions.is_empty() {
+            let mut message = if inputs.len() == 1 {
+                format!("{} contains no transactions", file.display())
+            } else {
+                format!("none of the {} files contains transactions", inputs.len())
+            };
+            if let Some(warning) = &extension_warning {
+                message.push_str(&format!("\n{}", warning));
+            }
+            for error in &import_errors {
+                message.push_str(&format!("\n{}", error));
+            }
+            bail!(message);
+        }
+        let first_field = metafield_index(first_metadata_field(&transactions));
+        let n_parsed = transactions.len();
         let mut ret = Self {
             exit: false,
             transactions,
             current_index: 0,
-            currently_selected_metadata_field: 2, // payee field
+            currently_selected_metadata_field: first_field,
             currently_selected_posting: 0,
             currently_selected_posting_field: PostingField::Account,
             current_mode: InputMode::Normal,
             current_account: 0,
             focus_on_postings: false,
+            status_message: None,
+            clipboard: SystemClipboard::default(),
+            popup: Popup::default(),
+            output: args.output,
+            export_json: args.export_json,
+            in_place: args.in_place,
+            dry_run: args.dry_run,
+            dry_run_report: Vec::new(),
+            check_cmd: args.check_cmd,
+            force: args.force,
+            check_failure: None,
+            unsaved_changes: false,
+            reordered: false,
+            autosave: args.autosave,
+            autosave_pending: false,
+            read_only: args.readonly,
+            last_save: None,
+            config,
+            theme,
+            file_modified: inputs
+                .iter()
+                .map(|input| (input.clone(), modified_time(input)))
+                .collect(),
+            marks: HashSet::new(),
+            known_accounts,
+            account_dates,
+            // a CSV file has no open directives to check against
+            open_check: !args.no_open_check && !csv_input,
+            completion: Completion::default(),
+            calendar: None,
+            account_browser: None,
+            save_review: None,
+            command_line: None,
+            comment_editor: None,
+            filter: None,
+            batch_undo: None,
+            payee_accounts: HashMap::new(),
+            context,
+            show_context: false,
+            rejected: Vec::new(),
+            rejected_output,
+            undo_reject: false,
+            rejected_list: None,
+            session_path: None,
+            pending_session: None,
+            content_hash: inputs_hash(&inputs)?,
+            n_parsed,
+            last_session_write: Instant::now(),
+            watcher: None,
+            file,
+            inputs,
+            csv_input,
         };
-        ret.update_textareas();
+        ret.payee_accounts = payee_accounts(&ret.transactions);
+        if ret.config.focus_issues {
+            ret.focus_first_issue();
+        }
+        if args.watch {
+            let mut watcher = FileWatcher::new()?;
+            watcher.watch(ret.ledger_files())?;
+            ret.watcher = Some(watcher);
+        }
+        // a script can't answer the resume question
+        if !args.no_session && args.script.is_none() && !args.check {
+            ret.session_path = session_path(&ret.file);
+            if !args.no_resume {
+                ret.offer_session();
+            }
+        }
+        if let Some(warning) = extension_warning.or(ret.config.format.parse_warning()) {
+            ret.set_status(warning);
+        }
+        if !import_errors.is_empty() {
+            ret.show_import_errors(&import_errors);
+        }
+        if !uninterpreted.is_empty() {
+            ret.show_uninterpreted(&uninterpreted);
+        }
         Ok(ret)
     }
     /// runs the application's main loop until the user quits
     pub fn run(&mut self, terminal: &mut terminal::Tui) -> Result<()> {
         while !self.exit {
-            terminal.draw(|frame| ui::draw(frame, &self).expect("Couldn't draw ui!"))?;
+            self.draw(terminal)?;
             self.handle_events().wrap_err("handle events failed")?;
         }
         Ok(())
     }
 
+    /// Runs the main loop like `run`, but over `events` instead of the terminal's input,
+    /// e.g. with a `TestBackend`. Stops early when the app exits, the last frame is
+    /// drawn after the last event.
+    pub fn run_events<B: Backend>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+        events: impl IntoIterator<Item = Event>,
+    ) -> Result<()> {
+        for event in events {
+            if self.exit {
+                return Ok(());
+            }
+            self.draw(terminal)?;
+            self.handle_event(event).wrap_err("handle events failed")?;
+        }
+        self.draw(terminal)
+    }
+
+    fn draw<B: Backend>(&self, terminal: &mut Terminal<B>) -> Result<()> {
+        terminal
+            .draw(|frame| ui::draw(frame, self).expect("Couldn't draw ui!"))
+            .map_err(|err| eyre!("couldn't draw: {}", err))?;
+        Ok(())
+    }
+
     /// updates the application's state based on user input
     fn handle_events(&mut self) -> Result<()> {
-        match event::read()? {
-            // it's important to check that the event is a key press event as
-            // crossterm also emits key release and repeat events on Windows.
-            Event::Key(key_event) if key_event.kind == KeyEventKind::Press => self
-                .handle_key_event(key_event)
-                .wrap_err_with(|| format!("handling key event failed:\n{key_event:#?}")),
-            _ => Ok(()),
+        let tick_rate = Duration::from_millis(self.config.tick_rate_ms);
+        // waits for input for at most one tick, so the app stays responsive while idle
+        if event::poll(tick_rate)? {
+            self.handle_event(event::read()?)
+        } else {
+            self.handle_action(Action::Tick)
         }
     }
 
-    fn handle_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
-        let current_transaction = &mut self.transactions[self.current_index];
-        let current_field = {
-            if self.focus_on_postings {
-                current_transaction.postings_textareas[self.currently_selected_posting]
-                    .get_field_mut(&self.currently_selected_posting_field)
-            } else {
-                &mut current_transaction.metadata_textareas[self.currently_selected_metadata_field]
-            }
+    fn handle_event(&mut self, event: Event) -> Result<()> {
+        let action = match event {
+            Event::Key(key_event) => Action::Key(key_event),
+            Event::Paste(text) => Action::Paste(text),
+            Event::Resize(_, _) => Action::Resize,
+            _ => return Ok(()),
         };
-        match key_event.into() {
-            Input { key: Key::Esc, .. }
-            | Input {
-                key: Key::Char('q'),
-                ctrl: true,
-                ..
-            } => self.exit(),
-            Input {
-                key: Key::Char('n'),
-                ctrl: true,
-                ..
-            } => self.next_transaction()?,
-            Input {
-                key: Key::Char('p'),
-                ctrl: true,
-                ..
-            } => self.prev_transaction()?,
-            // Focus right
-            Input { key: Key::Tab, .. }
-            | Input {
-                key: Key::Right,
-                ctrl: true,
-                ..
+        self.handle_action(action)
+    }
+
+    fn handle_action(&mut self, action: Action) -> Result<()> {
+        match action {
+            // crossterm also emits key release and repeat events on Windows, only the
+            // press may act, in every mode
+            Action::Key(key_event) if key_event.kind != KeyEventKind::Press => Ok(()),
+            Action::Key(key_event) if self.popup.active => {
+                self.handle_popup_key_event(key_event);
+                Ok(())
             }
-            | Input {
-                key: Key::Char('l'),
-                ctrl: true,
-                ..
-            } => {
-                if self.focus_on_postings {
-                    self.navigate_posting_field(true)?;
-                } else {
-                    self.navigate_metadata_field(true)?;
-                }
+            Action::Key(key_event) if self.calendar.is_some() => {
+                self.handle_calendar_key_event(key_event);
+                Ok(())
             }
-            // Focus left
-            Input {
-                key: Key::Left,
-                ctrl: true,
-                ..
+            Action::Key(key_event) if self.account_browser.is_some() => {
+                self.handle_account_browser_key_event(key_event);
+                Ok(())
             }
-            | Input {
-                key: Key::Char('h'),
-                ctrl: true,
-                ..
-            } => {
-                if self.focus_on_postings {
-                    self.navigate_posting_field(false)?;
-                } else {
-                    self.navigate_metadata_field(false)?;
-                }
+            Action::Key(key_event) if self.save_review.is_some() => {
+                self.handle_save_review_key_event(key_event);
+                Ok(())
             }
-            // Focus Down
-            Input {
-                key: Key::Char('j'),
-                ctrl: true,
-                ..
-            } => {
-                if self.focus_on_postings {
-                    self.navigate_posting(true)?;
-                } else {
-                    self.focus_on_postings = true;
-                    self.currently_selected_posting = 0;
-                    self.update_textareas();
+            Action::Key(key_event) if self.rejected_list.is_some() => {
+                self.handle_rejected_list_key_event(key_event);
+                Ok(())
+            }
+            Action::Key(key_event) if self.check_failure.is_some() => {
+                self.handle_check_failure_key_event(key_event);
+                Ok(())
+            }
+            Action::Key(key_event) if self.comment_editor.is_some() => {
+                self.handle_comment_editor_key_event(key_event);
+                Ok(())
+            }
+            Action::Key(key_event) if self.completion.active => self
+                .handle_completion_key_event(key_event)
+                .wrap_err_with(|| format!("handling key event failed:\n{key_event:#?}")),
+            Action::Key(key_event) if self.command_line.is_some() => {
+                self.handle_command_line_key_event(key_event);
+                Ok(())
+            }
+            Action::Key(key_event) => self
+                .handle_key_event(key_event)
+                .wrap_err_with(|| format!("handling key event failed:\n{key_event:#?}")),
+            Action::Paste(_)
+                if self.popup.active
+                    || self.calendar.is_some()
+                    || self.account_browser.is_some()
+                    || self.save_review.is_some() =>
+            {
+                Ok(())
+            }
+            Action::Paste(text) if self.comment_editor.is_some() => {
+                if let Some(editor) = &mut self.comment_editor {
+                    editor.insert_str(text);
                 }
+                Ok(())
             }
-            // Focus Up
-            Input {
-                key: Key::Char('k'),
-                ctrl: true,
-                ..
-            } => {
-                if self.focus_on_postings {
-                    self.navigate_posting(false)?;
+            Action::Paste(_) if self.read_only && self.command_line.is_none() => {
+                self.set_status(READ_ONLY_HINT);
+                Ok(())
+            }
+            Action::Paste(text) => {
+                if let Some(command_line) = &mut self.command_line {
+                    command_line.insert_str(collapse_line_breaks(&text));
                 } else {
-                    self.focus_on_postings = true;
-                    self.currently_selected_posting =
-                        current_transaction.postings_textareas.len() - 1;
-                    self.update_textareas();
+                    self.completion.close();
+                    self.paste_text(text);
                 }
+                Ok(())
             }
-            text_input => {
-                current_field.input(text_input);
+            // returning is enough, the main loop redraws with the new size right away
+            Action::Resize => Ok(()),
+            Action::Tick => {
+                self.tick();
+                Ok(())
             }
         }
-        Ok(())
     }
 
-    fn navigate_metadata_field(&mut self, forward: bool) -> Result<()> {
-        if forward {
-            self.currently_selected_metadata_field =
-                (self.currently_selected_metadata_field + 1) % METAFIELD_ORDER.len();
-        } else {
-            self.currently_selected_metadata_field =
-                (self.currently_selected_metadata_field + METAFIELD_ORDER.len() - 1)
-                    % METAFIELD_ORDER.len();
+    /// Periodic housekeeping that doesn't depend on user input
+    fn tick(&mut self) {
+        if self
+            .status_message
+            .as_ref()
+            .is_some_and(|message| message.created.elapsed() >= STATUS_MESSAGE_DURATION)
+        {
+            self.status_message = None;
+        }
+        if self.autosave_pending
+            && self
+                .last_save
+                .is_none_or(|last_save| last_save.elapsed() >= AUTOSAVE_INTERVAL)
+            // waits for the amount to be fixed instead of pulling the focus to it
+            && self.invalid_amount().is_none()
+        {
+            self.save();
+        }
+        self.check_file_changed();
+        if self.last_session_write.elapsed() >= SESSION_INTERVAL {
+            self.write_session();
         }
-        self.update_textareas();
-        Ok(())
     }
 
-    fn navigate_posting(&mut self, forward: bool) -> Result<()> {
-        let current_transaction = &mut self.transactions[self.current_index];
-        let n_postings = current_transaction.postings_textareas.len();
-        if forward {
-            let next_posting = self.currently_selected_posting + 1;
-            if next_posting >= n_postings {
-                self.focus_on_postings = false
-            } else {
-                self.currently_selected_posting = next_posting
+    /// Offers to resume the stored session if it belongs to the current input
+    fn offer_session(&mut self) {
+        let Some(path) = &self.session_path else {
+            return;
+        };
+        let session = match Session::load(path) {
+            Ok(Some(session)) => session,
+            Ok(None) => return,
+            Err(err) => {
+                self.set_status(format!("{:#}", err));
+                return;
             }
+        };
+        let count_changed = session.n_transactions != 0 && session.n_transactions != self.n_parsed;
+        if session.content_hash != self.content_hash || count_changed {
+            self.set_status("The file changed since the last session, starting fresh");
+            return;
+        }
+        if session.current_index == 0 && session.marks.is_empty() && session.edited.is_empty() {
+            return;
+        }
+        self.popup = Popup::new(
+            format!(
+                "Resume the last session at transaction {} with {} edited transactions?",
+                session.current_index + 1,
+                session.edited.len()
+            ),
+            PendingAction::ResumeSession,
+        );
+        self.pending_session = Some(session);
+    }
+
+    /// Summarizes the CSV rows that were skipped, in a popup unless another one is open
+    fn show_import_errors(&mut self, errors: &[String]) {
+        let mut summary = format!(
+            "Skipped {} rows that couldn't be imported: {}",
+            errors.len(),
+            errors[..errors.len().min(3)].join("; ")
+        );
+        if errors.len() > 3 {
+            summary.push_str(&format!(" and {} more", errors.len() - 3));
+        }
+        if self.popup.active {
+            self.set_status(summary);
         } else {
-            let prev_posting = self.currently_selected_posting.checked_sub(1);
-            if prev_posting.is_none() {
-                self.focus_on_postings = false
-            } else {
-                self.currently_selected_posting = prev_posting.unwrap()
+            self.popup = Popup::new(summary, PendingAction::Dismiss);
+        }
+    }
+
+    /// Warns that the `option` and `plugin` directives are kept but don't affect the review
+    fn show_uninterpreted(&mut self, directives: &[String]) {
+        let plural = if directives.len() == 1 { "" } else { "s" };
+        let mut summary = format!(
+            "{} directive{} kept in the file but not interpreted: {}",
+            directives.len(),
+            plural,
+            directives[..directives.len().min(3)].join(", ")
+        );
+        if directives.len() > 3 {
+            summary.push_str(&format!(" and {} more", directives.len() - 3));
+        }
+        if self.popup.active {
+            self.set_status(summary);
+        } else {
+            self.popup = Popup::new(summary, PendingAction::Dismiss);
+        }
+    }
+
+    /// Restores the position, marks and edits of the stored session
+    fn resume_session(&mut self) {
+        let Some(session) = self.pending_session.take() else {
+            return;
+        };
+        let n_transactions = self.transactions.len();
+        for edited in &session.edited {
+            let target = self
+                .transactions
+                .iter()
+                .position(|t| {
+                    edited.line_number != 0
+                        && t.line_number == edited.line_number
+                        // older sessions don't store the source
+                        && (edited.source.as_os_str().is_empty() || t.source == edited.source)
+                })
+                .or((edited.index < n_transactions).then_some(edited.index));
+            if let Some(target) = target {
+                edited.apply(&mut self.transactions[target]);
+                self.unsaved_changes = true;
             }
         }
-        self.update_textareas();
-        Ok(())
+        self.marks = session
+            .marks
+            .into_iter()
+            .filter(|&index| index < n_transactions)
+            .collect();
+        self.current_index = session.current_index.min(n_transactions.saturating_sub(1));
+        self.clamp_focus();
     }
-    fn navigate_posting_field(&mut self, forward: bool) -> Result<()> {
-        let current_transaction = &mut self.transactions[self.current_index];
-        let current_posting =
-            &mut current_transaction.postings_textareas[self.currently_selected_posting];
-        self.currently_selected_posting_field =
-            current_posting.next_field(&self.currently_selected_posting_field, forward);
-        self.update_textareas();
-        Ok(())
+
+    /// Stores the review progress so it can be resumed later
+    fn write_session(&mut self) {
+        self.last_session_write = Instant::now();
+        let Some(path) = &self.session_path else {
+            return;
+        };
+        let session = Session {
+            content_hash: self.content_hash,
+            n_transactions: self.n_parsed,
+            current_index: self.current_index,
+            marks: self.marks.iter().copied().collect(),
+            edited: self
+                .transactions
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| t.dirty)
+                .map(|(index, t)| EditedTransaction::from_transaction(index, t))
+                .collect(),
+        };
+        if let Err(err) = session.write(path) {
+            self.set_status(format!("{:#}", err));
+        }
     }
 
-    fn update_textareas(&mut self) {
-        let current_transaction = &mut self.transactions[self.current_index];
+    /// Asks the user what to do if the input file was modified by another program
+    fn check_file_changed(&mut self) {
+        if self.popup.active {
+            return;
+        }
+        let changed = match &mut self.watcher {
+            Some(watcher) => watcher.changed_files(),
+            None => {
+                let mut changed = Vec::new();
+                for (file, last_modified) in &mut self.file_modified {
+                    let modified = modified_time(file);
+                    if modified.is_some() && modified != *last_modified {
+                        // only ask once per change, ignoring keeps the current state
+                        *last_modified = modified;
+                        changed.push(file.clone());
+                    }
+                }
+                changed
+            }
+        };
+        if changed.is_empty() {
+            return;
+        }
+        let files = describe_files(&changed);
+        let prompt = if self.unsaved_changes {
+            format!(
+                "{} changed on disk while you have unsaved edits. Reload it?",
+                files
+            )
+        } else {
+            format!("{} was changed on disk. Reload it?", files)
+        };
+        self.popup = Popup::new(prompt, PendingAction::ReloadFile { keep_edits: false });
+    }
 
-        for (index, metadata_field) in current_transaction
-            .metadata_textareas
-            .iter_mut()
-            .enumerate()
-        {
-            let block = metadata_field
-                .block()
-                .expect("Textarea should have a block");
-            if index == self.currently_selected_metadata_field && !self.focus_on_postings {
-                // Highlight the selected TextArea
-                // FIXME this currently overwrites the title of the block
-                metadata_field.set_block(
-                    block
-                        .clone()
-                        .border_style(Style::default().fg(Color::Yellow)), // Highlight with yellow border
-                );
-                metadata_field.set_cursor_style(Style::default().reversed());
-            } else {
-                // Reset style for unselected TextAreas
-                metadata_field.set_block(
-                    block.clone().border_style(Style::default()), // Default border style
-                );
-                metadata_field.set_cursor_style(Style::default().bg(Color::Reset));
+    /// Updates the modification time of `path` if it is an input, so our own
+    /// saves aren't offered for reloading
+    fn remember_own_write(&mut self, path: &Path) {
+        if let Some(modified) = self.file_modified.get_mut(path) {
+            *modified = modified_time(path);
+        }
+    }
+
+    /// The file name of the current transaction's source when several files are reviewed
+    pub fn source_name(&self) -> Option<String> {
+        if self.inputs.len() < 2 {
+            return None;
+        }
+        let source = &self.transactions[self.current_index].source;
+        Some(source.file_name()?.to_string_lossy().into_owned())
+    }
+
+    /// How many transactions were read from each file, shown on exit when
+    /// several files were reviewed
+    pub fn file_summary(&self) -> Vec<String> {
+        if self.inputs.len() < 2 {
+            return Vec::new();
+        }
+        let mut counts: Vec<(&Path, usize)> = Vec::new();
+        for transaction in &self.transactions {
+            match counts
+                .iter_mut()
+                .find(|(path, _)| *path == transaction.source)
+            {
+                Some((_, count)) => *count += 1,
+                None => counts.push((&transaction.source, 1)),
             }
         }
-        for (index, posting) in current_transaction
-            .postings_textareas
-            .iter_mut()
-            .enumerate()
+        counts
+            .into_iter()
+            .map(|(path, count)| {
+                let plural = if count == 1 { "" } else { "s" };
+                format!("{}: {} transaction{}", path.display(), count, plural)
+            })
+            .collect()
+    }
+
+    /// The files the transactions were read from, which `--watch` follows
+    fn ledger_files(&self) -> BTreeSet<PathBuf> {
+        let mut files: BTreeSet<PathBuf> = self.inputs.iter().cloned().collect();
+        files.extend(
+            self.transactions
+                .iter()
+                .filter(|t| !t.source.as_os_str().is_empty())
+                .map(|t| t.source.clone()),
+        );
+        files
+    }
+
+    /// Reads the input file again, optionally keeping the transactions that were edited
+    fn reload_file(&mut self, keep_edits: bool) {
+        let mut transactions = match load_file(&self.inputs, self.csv_input, &self.config, &|_| {})
         {
-            for posting_field in POSTING_FIELD_ORDER {
-                if index == self.currently_selected_posting
-                    && posting_field == self.currently_selected_posting_field
-                    && self.focus_on_postings
-                {
-                    let current_posting_field = posting.get_field_mut(&posting_field);
-                    let block = current_posting_field
-                        .block()
-                        .expect("Textarea should have a block");
-                    // Highlight the selected TextArea
-                    current_posting_field.set_block(
-                        block
-                            .clone()
-                            .border_style(Style::default().fg(Color::Yellow)), // Highlight with yellow border
-                    );
-                    current_posting_field.set_cursor_style(Style::default().reversed());
+            Ok(loaded) if loaded.transactions.is_empty() => {
+                self.set_status("Reloaded file contains no transactions, keeping the old ones");
+                return;
+            }
+            Ok(loaded) => {
+                self.known_accounts = loaded.known_accounts;
+                self.account_dates = loaded.account_dates;
+                self.context = loaded.context;
+                loaded.transactions
+            }
+            Err(err) => {
+                let files = describe_files(&self.inputs);
+                self.set_status(format!("Couldn't reload {}: {}", files, err));
+                return;
+            }
+        };
+        self.batch_undo = None;
+        let mut kept = 0;
+        if keep_edits {
+            let mut taken = vec![false; transactions.len()];
+            for (index, edited) in self.transactions.drain(..).enumerate() {
+                if !edited.dirty {
+                    continue;
+                }
+                let same_origin = |t: &TransactionTui| {
+                    t.directive.payee == edited.directive.payee
+                        && t.directive.narration == edited.directive.narration
+                };
+                // prefer the transaction at the same position, fall back to the first unused match
+                let target = if transactions.get(index).is_some_and(same_origin) && !taken[index] {
+                    Some(index)
                 } else {
-                    let current_posting_field = posting.get_field_mut(&posting_field);
-                    let block = current_posting_field
-                        .block()
-                        .expect("Textarea should have a block");
-                    // Reset style for unselected TextAreas
-                    current_posting_field.set_block(
-                        block.clone().border_style(Style::default()), // Default border style
-                    );
-                    current_posting_field.set_cursor_style(Style::default().bg(Color::Reset));
+                    (0..transactions.len()).find(|&i| !taken[i] && same_origin(&transactions[i]))
+                };
+                if let Some(target) = target {
+                    taken[target] = true;
+                    transactions[target].take_edits(edited);
+                    kept += 1;
                 }
             }
         }
+        self.unsaved_changes = kept > 0;
+        self.reordered = false;
+        // the listed problems and edited comments may belong to other transactions now
+        self.save_review = None;
+        self.comment_editor = None;
+        self.n_parsed = transactions.len();
+        self.transactions = transactions;
+        if let Ok(hash) = inputs_hash(&self.inputs) {
+            self.content_hash = hash;
+        }
+        self.payee_accounts = payee_accounts(&self.transactions);
+        let files = self.ledger_files();
+        if let Some(Err(err)) = self.watcher.as_mut().map(|watcher| watcher.watch(files)) {
+            self.set_status(format!("{:#}", err));
+        }
+        self.current_index = self.current_index.min(self.transactions.len() - 1);
+        let n_transactions = self.transactions.len();
+        self.marks.retain(|&index| index < n_transactions);
+        self.focus_on_postings = false;
+        self.currently_selected_posting = 0;
+        let files = describe_files(&self.inputs);
+        self.set_status(if keep_edits {
+            format!("Reloaded {}, kept {} edited transactions", files, kept)
+        } else {
+            format!("Reloaded {}", files)
+        });
     }
 
-    fn next_transaction(&mut self) -> Result<()> {
-        if self.current_index < self.transactions.len() - 1 {
-            self.current_index = self.current_index.saturating_add(1);
+    /// Shows a message in the status bar
+    fn set_status(&mut self, text: impl Into<String>) {
+        self.status_message = Some(StatusMessage {
+            text: text.into(),
+            created: Instant::now(),
+        });
+    }
+
+    fn handle_popup_key_event(&mut self, key_event: KeyEvent) {
+        let action = match (self.popup.on_confirm, key_event.code) {
+            (_, KeyCode::Esc) => {
+                self.popup.active = false;
+                self.pending_session = None;
+                return;
+            }
+            (PendingAction::ReloadFile { .. }, KeyCode::Char('r')) => {
+                PendingAction::ReloadFile { keep_edits: false }
+            }
+            (PendingAction::ReloadFile { .. }, KeyCode::Char('k')) => {
+                PendingAction::ReloadFile { keep_edits: true }
+            }
+            (action, KeyCode::Enter) => action,
+            _ => return,
+        };
+        self.popup.active = false;
+        self.run_pending_action(action);
+    }
+
+    /// Carries out the action of a confirmed popup
+    fn run_pending_action(&mut self, action: PendingAction) {
+        match action {
+            PendingAction::Exit => self.exit(),
+            PendingAction::AcceptRemaining => {
+                self.accept_remaining();
+                self.request_exit();
+            }
+            PendingAction::ResumeSession => self.resume_session(),
+            PendingAction::ReloadFile { keep_edits } => self.reload_file(keep_edits),
+            PendingAction::DeleteTransaction(index) => self.delete_transaction(index),
+            PendingAction::DeletePosting(index, posting) => self.delete_posting(index, posting),
+            PendingAction::FlagAll(flag) => self.flag_all(flag),
+            PendingAction::Dismiss => {}
         }
-        Ok(())
     }
 
-    fn prev_transaction(&mut self) -> Result<()> {
-        self.current_index = self.current_index.saturating_sub(1);
-        Ok(())
+    fn handle_command_line_key_event(&mut self, key_event: KeyEvent) {
+        let Some(command_line) = &mut self.command_line else {
+            return;
+        };
+        match key_event.into() {
+            Input { key: Key::Esc, .. } => self.command_line = None,
+            Input {
+                key: Key::Enter, ..
+            } => {
+                let input = textarea_content(command_line);
+                self.command_line = None;
+                if let Err(err) = self.execute_command(&input) {
+                    self.set_status(err.to_string());
+                }
+            }
+            // deleting past the start closes the prompt like in vim
+            Input {
+                key: Key::Backspace,
+                ..
+            } if textarea_content(command_line).is_empty() => self.command_line = None,
+            input => {
+                command_line.input(input);
+            }
+        }
     }
 
-    fn toggle_textarea_active(textarea: &mut TextArea) -> Result<()> {
-        textarea.set_cursor_style(textarea.cursor_style().reversed());
+    /// Parses and runs a command typed into the `:` command line
+    pub fn execute_command(&mut self, input: &str) -> Result<(), BeancountTuiError> {
+        let command = parse_command(input)?;
+        if self.read_only && command.is_mutating() {
+            return Err(BeancountTuiError::Command(READ_ONLY_HINT.to_string()));
+        }
+        match command {
+            TypedCommand::Write => self.write_command()?,
+            TypedCommand::Quit => self.request_exit(),
+            TypedCommand::ForceQuit => self.exit(),
+            TypedCommand::WriteQuit => {
+                self.write_command()?;
+                self.request_exit();
+            }
+            TypedCommand::Goto(position) => {
+                if position > self.transactions.len() {
+                    return Err(BeancountTuiError::Command(format!(
+                        "there are only {} transactions",
+                        self.transactions.len()
+                    )));
+                }
+                self.leave_transaction();
+                self.current_index = position - 1;
+                self.clamp_focus();
+            }
+            TypedCommand::Sort => self.sort_transactions(),
+            TypedCommand::Filter(filter) => self.set_filter(filter)?,
+            TypedCommand::Negate(account) => self.negate_account_amounts(&account),
+            TypedCommand::FlagAll(flag) => self.confirm_flag_all(flag)?,
+        }
         Ok(())
     }
 
-    fn exit(&mut self) {
-        self.exit = true;
+    /// Saves for `:w`, failing with the status message if something couldn't be written
+    fn write_command(&mut self) -> Result<(), BeancountTuiError> {
+        if self.save() {
+            return Ok(());
+        }
+        let message = self
+            .status_message
+            .as_ref()
+            .map(|status| status.text.clone())
+            .unwrap_or_default();
+        Err(BeancountTuiError::Command(message))
+    }
+
+    /// Runs the commands of a script, one per line like on the command line, and
+    /// saves the result. Empty lines and lines starting with `#` are skipped.
+    /// Stops at the first failing line.
+    pub fn run_script(&mut self, script: &str) -> Result<()> {
+        for (index, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self.execute_command(line)
+                .map_err(|err| eyre!("line {}: {}", index + 1, err))?;
+            if self.popup.active {
+                // there is nobody to answer, e.g. quitting with unsaved changes
+                bail!("line {}: {}", index + 1, self.popup.prompt);
+            }
+            if self.exit {
+                return Ok(());
+            }
+        }
+        if self.unsaved_changes || self.output.is_some() || self.export_json.is_some() {
+            self.write_command()
+                .map_err(|err| eyre!("couldn't save: {}", err))?;
+        }
+        Ok(())
     }
-}
 
-// #[cfg(test)]
-// mod tests {
-//     use ratatui::style::Style;
-
-//     use super::*;
-
-//     #[test]
-//     fn render() {
-//         let app = App::default();
-//         let mut buf = Buffer::empty(Rect::new(0, 0, 50, 4));
-
-//         app.render(buf.area, &mut buf);
-
-//         let mut expected = Buffer::with_lines(vec![
-//             "┏━━━━━━━━━━━━━ Counter App Tutorial ━━━━━━━━━━━━━┓",
-//             "┃                    Value: 0                    ┃",
-//             "┃                                                ┃",
-//             "┗━ Decrement <Left> Increment <Right> Quit <Q> ━━┛",
-//         ]);
-//         let title_style = Style::new().bold();
-//         let counter_style = Style::new().yellow();
-//         let key_style = Style::new().blue().bold();
-//         expected.set_style(Rect::new(14, 0, 22, 1), title_style);
-//         expected.set_style(Rect::new(28, 1, 1, 1), counter_style);
-//         expected.set_style(Rect::new(13, 3, 6, 1), key_style);
-//         expected.set_style(Rect::new(30, 3, 7, 1), key_style);
-//         expected.set_style(Rect::new(43, 3, 4, 1), key_style);
-
-//         assert_eq!(buf, expected);
-//     }
-
-//     #[test]
-//     fn handle_key_event() {
-//         let mut app = App::default();
-//         app.handle_key_event(KeyCode::Right.into()).unwrap();
-//         assert_eq!(app.current_index, 1);
-
-//         app.handle_key_event(KeyCode::Left.into()).unwrap();
-//         assert_eq!(app.current_index, 0);
-
-//         let mut app = App::default();
-//         app.handle_key_event(KeyCode::Char('q').into()).unwrap();
-//         assert!(app.exit);
-//     }
-
-//     #[test]
-//     #[should_panic(expected = "attempt to subtract with overflow")]
-//     fn handle_key_event_panic() {
-//         let mut app = App::default();
-//         let _ = app.handle_key_event(KeyCode::Left.into());
-//     }
-
-//     #[test]
-//     fn handle_key_event_overflow() {
-//         let mut app = App::default();
-//         assert!(app.handle_key_event(KeyCode::Right.into()).is_ok());
-//         assert!(app.handle_key_event(KeyCode::Right.into()).is_ok());
-//         assert_eq!(
-//             app.handle_key_event(KeyCode::Right.into())
-//                 .unwrap_err()
-//                 .to_string(),
-//             "counter overflow"
-//         );
-//     }
-// }
+    /// Flips the sign of the postings whose account contains `text` in all transactions,
+    /// e.g. for a whole import with the opposite sign convention
+    fn negate_account_amounts(&mut self, text: &str) {
+        let needle = text.to_lowercase();
+        let mut undo = BatchUndo {
+            description: String::new(),
+            amounts: Vec::new(),
+            dirty: Vec::new(),
+            unsaved_changes: self.unsaved_changes,
+        };
+        let mut skipped = 0;
+        for (index, transaction) in self.transactions.iter_mut().enumerate() {
+            let was_dirty = transaction.dirty;
+            let mut changed = false;
+            for (posting_index, posting) in
+                transaction.postings_textareas_mut().iter_mut().enumerate()
+            {
+                let account = textarea_content(&posting.account_textarea).to_lowercase();
+                if !account.contains(&needle) {
+                    continue;
+                }
+                let amount = textarea_content(&posting.amount_textarea);
+                match negate_amount(&amount) {
+                    Some(negated) => {
+                        set_textarea_content(&mut posting.amount_textarea, &negated);
+                        undo.amounts.push((index, posting_index, amount));
+                        changed = true;
+                    }
+                    None => skipped += 1,
+                }
+            }
+            if changed {
+                undo.dirty.push((index, was_dirty));
+            }
+        }
+        let mut message = if undo.amounts.is_empty() {
+            format!("No amounts of accounts matching '{}' to negate", text)
+        } else {
+            format!(
+                "Negated {} postings in {} transactions",
+                undo.amounts.len(),
+                undo.dirty.len()
+            )
+        };
+        if skipped > 0 {
+            message.push_str(&format!(
+                ", skipped {} with an empty or invalid amount",
+                skipped
+            ));
+        }
+        if !undo.amounts.is_empty() {
+            for &(index, _) in &undo.dirty {
+                self.mark_transaction_dirty(index);
+            }
+            undo.description = message.clone();
+            self.batch_undo = Some(undo);
+        }
+        self.set_status(message);
+    }
+
+    /// Asks before setting the flag of every transaction to `flag`
+    fn confirm_flag_all(&mut self, flag: char) -> Result<(), BeancountTuiError> {
+        if !self.config.valid_flags.contains(flag) {
+            return Err(BeancountTuiError::Command(format!(
+                "'{}' is not a valid flag, use one of {}",
+                flag, self.config.valid_flags
+            )));
+        }
+        self.popup = Popup::new(
+            format!(
+                "Set the flag of all {} transactions to '{}'?",
+                self.transactions.len(),
+                flag
+            ),
+            PendingAction::FlagAll(flag),
+        );
+        Ok(())
+    }
+
+    fn flag_all(&mut self, flag: char) {
+        let flag = flag.to_string();
+        let mut changed = 0;
+        for index in 0..self.transactions.len() {
+            let flag_textarea = &mut self.transactions[index].metadata_textareas_mut()[1];
+            if textarea_content(flag_textarea) != flag {
+                set_textarea_content(flag_textarea, &flag);
+                self.mark_transaction_dirty(index);
+                changed += 1;
+            }
+        }
+        self.set_status(format!(
+            "Set the flag of {} transactions to '{}'",
+            changed, flag
+        ));
+    }
+
+    /// Restores the amounts a batch edit changed
+    fn undo_batch(&mut self, undo: BatchUndo) {
+        for (index, posting, amount) in &undo.amounts {
+            let postings = self.transactions[*index].postings_textareas_mut();
+            set_textarea_content(&mut postings[*posting].amount_textarea, amount);
+        }
+        for (index, dirty) in undo.dirty {
+            self.transactions[index].dirty = dirty;
+        }
+        self.unsaved_changes = undo.unsaved_changes;
+        self.set_status(format!("Undone: {}", undo.description));
+    }
+
+    /// Orders the transactions by date, keeping the order of transactions on the same day
+    fn sort_transactions(&mut self) {
+        self.leave_transaction();
+        let mut order: Vec<usize> = (0..self.transactions.len()).collect();
+        let dates: Vec<_> = self
+            .transactions
+            .iter()
+            .map(|t| parse_date(&textarea_content(&t.metadata_textareas()[0])))
+            .collect();
+        // transactions with an invalid date go last
+        order.sort_by_key(|&index| (dates[index].is_none(), dates[index]));
+        if order
+            .iter()
+            .enumerate()
+            .all(|(position, &index)| position == index)
+        {
+            self.set_status("Already sorted");
+            return;
+        }
+        self.batch_undo = None;
+        let mut transactions: Vec<Option<TransactionTui<'t>>> =
+            self.transactions.drain(..).map(Some).collect();
+        self.transactions = order
+            .iter()
+            .map(|&index| {
+                transactions[index]
+                    .take()
+                    .expect("every index is used once")
+            })
+            .collect();
+        let new_position = |old: usize| order.iter().position(|&index| index == old);
+        self.marks = self.marks.iter().filter_map(|&m| new_position(m)).collect();
+        self.current_index = new_position(self.current_index).unwrap_or_default();
+        self.unsaved_changes = true;
+        self.reordered = true;
+        self.clamp_focus();
+        self.set_status("Sorted transactions by date");
+    }
+
+    /// Limits navigation to matching transactions, `None` visits all of them again
+    fn set_filter(&mut self, filter: Option<Filter>) -> Result<(), BeancountTuiError> {
+        let Some(filter) = filter else {
+            self.filter = None;
+            self.set_status("Showing all transactions");
+            return Ok(());
+        };
+        let n_matching = self
+            .transactions
+            .iter()
+            .filter(|t| filter.matches(t))
+            .count();
+        if n_matching == 0 {
+            return Err(BeancountTuiError::Command(format!(
+                "no transaction matches {}",
+                filter.describe()
+            )));
+        }
+        if !filter.matches(&self.transactions[self.current_index]) {
+            self.leave_transaction();
+            let n_transactions = self.transactions.len();
+            self.current_index = (0..n_transactions)
+                .map(|offset| (self.current_index + offset) % n_transactions)
+                .find(|&index| filter.matches(&self.transactions[index]))
+                .expect("at least one transaction matches");
+            self.clamp_focus();
+        }
+        self.set_status(format!("{} matching transactions", n_matching));
+        self.filter = Some(filter);
+        Ok(())
+    }
+
+    /// Whether navigation should stop at the transaction at `index`
+    fn is_visible(&self, index: usize) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|filter| filter.matches(&self.transactions[index]))
+    }
+
+    /// Opens the date picker on the entered date, or on today if it isn't valid
+    fn open_calendar(&mut self) {
+        let date_textarea = &self.transactions[self.current_index].metadata_textareas()[0];
+        let date = parse_date(&textarea_content(date_textarea)).unwrap_or_else(today);
+        self.calendar = Some(Calendar::new(date));
+    }
+
+    fn handle_calendar_key_event(&mut self, key_event: KeyEvent) {
+        let Some(calendar) = &mut self.calendar else {
+            return;
+        };
+        match key_event.code {
+            KeyCode::Esc => self.calendar = None,
+            KeyCode::Left => calendar.shift(-1, false),
+            KeyCode::Right => calendar.shift(1, false),
+            KeyCode::Up => calendar.shift(-7, false),
+            KeyCode::Down => calendar.shift(7, false),
+            KeyCode::PageUp => calendar.shift(-1, true),
+            KeyCode::PageDown => calendar.shift(1, true),
+            KeyCode::Char('t') => calendar.selected = today(),
+            KeyCode::Enter => {
+                let date = format_naive_date(&calendar.selected);
+                self.calendar = None;
+                let date_textarea =
+                    &mut self.transactions[self.current_index].metadata_textareas_mut()[0];
+                if textarea_content(date_textarea) != date {
+                    set_textarea_content(date_textarea, &date);
+                    self.mark_dirty();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn open_account_browser(&mut self) {
+        if self.known_accounts.is_empty() {
+            self.set_status("No opened accounts to browse");
+            return;
+        }
+        let account = textarea_content(self.current_field_mut());
+        self.account_browser = Some(AccountBrowser::new(&self.known_accounts, &account));
+    }
+
+    fn handle_account_browser_key_event(&mut self, key_event: KeyEvent) {
+        let Some(browser) = &mut self.account_browser else {
+            return;
+        };
+        match key_event.code {
+            KeyCode::Esc | KeyCode::Char('q') => self.account_browser = None,
+            KeyCode::Char('j') | KeyCode::Down => browser.select_next(true),
+            KeyCode::Char('k') | KeyCode::Up => browser.select_next(false),
+            KeyCode::Char('l') | KeyCode::Right => browser.expand(),
+            KeyCode::Char('h') | KeyCode::Left => browser.collapse(),
+            KeyCode::Enter => {
+                let account = browser.selected_account();
+                self.account_browser = None;
+                if let Some(account) = account {
+                    if textarea_content(self.current_field_mut()) != account {
+                        set_textarea_content(self.current_field_mut(), &account);
+                        self.mark_dirty();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Saves right away if no transaction has a problem, otherwise lists the problems first
+    fn review_before_save(&mut self) {
+        if self.output.is_none() && self.export_json.is_none() && !self.in_place {
+            self.set_status("No output file given, use --output");
+            return;
+        }
+        let problems = self.save_problems();
+        if problems.is_empty() {
+            self.save();
+            return;
+        }
+        let edited = self
+            .transactions
+            .iter()
+            .filter(|transaction| transaction.dirty || transaction.edited)
+            .count();
+        self.save_review = Some(SaveReview::new(problems, edited));
+    }
+
+    /// The problems the review before saving finds, one line per problem with the location
+    /// of the transaction, e.g. for `--check`
+    pub fn check_report(&self) -> Vec<String> {
+        self.save_problems()
+            .iter()
+            .map(|problem| {
+                let transaction = &self.transactions[problem.index];
+                // the whole path, so editors and CI logs can jump to it
+                let location = if transaction.source.as_os_str().is_empty() {
+                    transaction.location()
+                } else {
+                    format!(
+                        "{}:{}",
+                        transaction.source.display(),
+                        transaction.line_number
+                    )
+                };
+                let severity = match problem.severity {
+                    Severity::Error => "error",
+                    Severity::Warning => "warning",
+                };
+                format!(
+                    "{}: {}: {} {}: {}",
+                    location, severity, problem.date, problem.payee, problem.issue
+                )
+            })
+            .collect()
+    }
+
+    /// The problems of all transactions, invalid ones are errors and postings to
+    /// accounts that aren't open are warnings
+    fn save_problems(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        for (index, transaction) in self.transactions.iter().enumerate() {
+            let [date, _, payee, narration, _] = transaction.metadata_textareas();
+            let mut payee = textarea_content(payee);
+            if payee.trim().is_empty() {
+                payee = textarea_content(narration);
+            }
+            let problem = |issue: String, severity: Severity| Problem {
+                index,
+                date: textarea_content(date),
+                payee: payee.clone(),
+                issue,
+                severity,
+            };
+            for issue in validate_transaction(transaction) {
+                problems.push(problem(issue, Severity::Error));
+            }
+            if !self.open_check {
+                continue;
+            }
+            let postings = transaction.postings_textareas();
+            for posting in unopened_postings(
+                transaction,
+                &self.known_accounts,
+                self.config.allow_sub_accounts,
+            ) {
+                let issue = format!(
+                    "posting {} uses the unopened account {}",
+                    posting + 1,
+                    textarea_content(&postings[posting].account_textarea)
+                );
+                problems.push(problem(issue, Severity::Warning));
+            }
+            for (posting, reason) in inactive_postings(transaction, &self.account_dates) {
+                let issue = format!(
+                    "posting {} uses {}, which was {}",
+                    posting + 1,
+                    textarea_content(&postings[posting].account_textarea),
+                    reason
+                );
+                problems.push(problem(issue, Severity::Warning));
+            }
+        }
+        problems
+    }
+
+    fn handle_save_review_key_event(&mut self, key_event: KeyEvent) {
+        let Some(review) = &mut self.save_review else {
+            return;
+        };
+        match key_event.code {
+            KeyCode::Esc | KeyCode::Char('q') => self.save_review = None,
+            KeyCode::Char('j') | KeyCode::Down => review.select_next(true),
+            KeyCode::Char('k') | KeyCode::Up => review.select_next(false),
+            KeyCode::Enter => {
+                let index = review.selected_problem().map(|problem| problem.index);
+                self.save_review = None;
+                if let Some(index) = index {
+                    self.leave_transaction();
+                    self.current_index = index;
+                    self.clamp_focus();
+                }
+            }
+            KeyCode::Char('s') if review.has_errors() => {
+                self.set_status("Not saved: fix the transactions failing validation first");
+            }
+            KeyCode::Char('s') => {
+                self.save_review = None;
+                self.save();
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_rejected_list_key_event(&mut self, key_event: KeyEvent) {
+        let Some(selected) = self.rejected_list else {
+            return;
+        };
+        let last = self.rejected.len().saturating_sub(1);
+        match key_event.code {
+            KeyCode::Esc | KeyCode::Char('q') => self.rejected_list = None,
+            KeyCode::Char('j') | KeyCode::Down => {
+                self.rejected_list = Some((selected + 1).min(last))
+            }
+            KeyCode::Char('k') | KeyCode::Up => {
+                self.rejected_list = Some(selected.saturating_sub(1))
+            }
+            KeyCode::Enter => {
+                self.leave_transaction();
+                self.restore_rejected(selected);
+                self.rejected_list = if self.rejected.is_empty() {
+                    None
+                } else {
+                    Some(selected.min(self.rejected.len() - 1))
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_check_failure_key_event(&mut self, key_event: KeyEvent) {
+        let Some(failure) = &mut self.check_failure else {
+            return;
+        };
+        match key_event.code {
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Enter => self.check_failure = None,
+            KeyCode::Char('j') | KeyCode::Down => failure.scroll_by(1),
+            KeyCode::Char('k') | KeyCode::Up => failure.scroll_by(-1),
+            KeyCode::PageDown => failure.scroll_by(self.config.page_size as isize),
+            KeyCode::PageUp => failure.scroll_by(-(self.config.page_size as isize)),
+            _ => {}
+        }
+    }
+
+    fn open_comment_editor(&mut self) {
+        let comments = self.transactions[self.current_index].comments.clone();
+        let mut editor = TextArea::new(if comments.is_empty() {
+            vec![String::new()]
+        } else {
+            comments
+        });
+        editor.set_cursor_line_style(Style::default());
+        editor.set_cursor_style(self.theme.cursor);
+        editor.move_cursor(CursorMove::Bottom);
+        editor.move_cursor(CursorMove::End);
+        self.comment_editor = Some(editor);
+    }
+
+    fn handle_comment_editor_key_event(&mut self, key_event: KeyEvent) {
+        let Some(editor) = &mut self.comment_editor else {
+            return;
+        };
+        match key_event.into() {
+            Input { key: Key::Esc, .. } => self.comment_editor = None,
+            Input {
+                key: Key::Char('s'),
+                ctrl: true,
+                ..
+            } => {
+                let comments = comment_lines(editor.lines());
+                self.comment_editor = None;
+                let transaction = &mut self.transactions[self.current_index];
+                if transaction.comments != comments {
+                    transaction.comments = comments;
+                    self.mark_dirty();
+                }
+            }
+            input => {
+                editor.input(input);
+            }
+        }
+    }
+
+    fn handle_completion_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
+        match key_event.into() {
+            Input { key: Key::Esc, .. } => self.completion.close(),
+            Input { key: Key::Up, .. }
+            | Input {
+                key: Key::Char('p'),
+                ctrl: true,
+                ..
+            } => self.completion.select_next(false),
+            Input { key: Key::Down, .. }
+            | Input {
+                key: Key::Char('n'),
+                ctrl: true,
+                ..
+            } => self.completion.select_next(true),
+            Input {
+                key: Key::Tab | Key::Enter,
+                ..
+            } => self.accept_completion(),
+            _ => {
+                self.handle_key_event(key_event)?;
+                if self.focused_field_type() == InputFieldType::Account {
+                    let query = textarea_content(self.current_field_mut());
+                    self.completion.update(&query, &self.known_accounts);
+                } else {
+                    self.completion.close();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the focused account with the selected completion candidate
+    fn accept_completion(&mut self) {
+        if let Some(account) = self.completion.selected_candidate().cloned() {
+            set_textarea_content(self.current_field_mut(), &account);
+            self.mark_dirty();
+        }
+        self.completion.close();
+    }
+
+    fn handle_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
+        if self.read_only {
+            return self.handle_read_only_key_event(key_event);
+        }
+        let single_line = self.focused_field_is_single_line();
+        if key_event.code == KeyCode::Enter && !single_line {
+            if self.current_field_mut().input(key_event) {
+                self.mark_dirty();
+            }
+            return Ok(());
+        }
+        let command = self
+            .config
+            .keys
+            .lookup(key_event)
+            .or_else(|| keymap::lookup(key_event, self.focused_field_type()));
+        let Some(command) = command else {
+            if let (InputFieldType::Flag, KeyCode::Char(flag)) =
+                (self.focused_field_type(), key_event.code)
+            {
+                if !key_event
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+                {
+                    self.input_flag(&flag.to_string());
+                    return Ok(());
+                }
+            }
+            if single_line && inserts_newline(&key_event.into()) {
+                return Ok(());
+            }
+            if self.current_field_mut().input(key_event) {
+                self.mark_dirty();
+                self.check_amount();
+            }
+            return Ok(());
+        };
+        self.run_command(command)
+    }
+
+    /// Like `handle_key_event`, but only runs commands that don't edit anything and
+    /// only lets keys through to the fields that move the cursor
+    fn handle_read_only_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
+        let command = self
+            .config
+            .keys
+            .lookup(key_event)
+            .or_else(|| keymap::lookup(key_event, self.focused_field_type()));
+        match command {
+            Some(command) if !command.is_mutating() => self.run_command(command),
+            None if moves_cursor(key_event) => {
+                self.current_field_mut().input(key_event);
+                Ok(())
+            }
+            _ => {
+                self.set_status(READ_ONLY_HINT);
+                Ok(())
+            }
+        }
+    }
+
+    fn run_command(&mut self, command: Command) -> Result<()> {
+        match command {
+            Command::Quit => self.request_exit(),
+            Command::Save => self.review_before_save(),
+            Command::NextTransaction => self.next_transaction()?,
+            Command::PrevTransaction => self.prev_transaction()?,
+            Command::FirstTransaction => self.move_transactions(usize::MAX, false),
+            Command::LastTransaction => self.move_transactions(usize::MAX, true),
+            Command::PageDown => self.move_transactions(self.config.page_size, true),
+            Command::PageUp => self.move_transactions(self.config.page_size, false),
+            Command::CopyTransaction => self.copy_transaction(),
+            Command::NextIssue => self.next_issue(),
+            Command::AcceptAndNext => self.accept_and_next(),
+            Command::RejectTransaction => self.reject_transaction(self.current_index),
+            Command::ShowRejected => {
+                if self.rejected.is_empty() {
+                    self.set_status("No rejected transactions");
+                } else {
+                    self.rejected_list = Some(self.rejected.len() - 1);
+                }
+            }
+            Command::ToggleContext => {
+                self.show_context = !self.show_context;
+                self.set_status(if self.show_context {
+                    "Showing the pad, balance and note directives before the transaction"
+                } else {
+                    "Hiding the pad, balance and note directives"
+                });
+            }
+            Command::Paste => self.paste_into_current_field(),
+            Command::CycleFlag => self.cycle_flag(),
+            Command::ToggleMark => self.toggle_mark(),
+            Command::NextMark => self.jump_to_mark(true),
+            Command::PrevMark => self.jump_to_mark(false),
+            Command::AcceptRemaining => {
+                self.popup = Popup::new(
+                    format!(
+                        "Accept remaining {} transactions?",
+                        self.transactions.len() - self.current_index
+                    ),
+                    PendingAction::AcceptRemaining,
+                )
+            }
+            Command::NextDay => self.shift_current_date(1, false),
+            Command::PrevDay => self.shift_current_date(-1, false),
+            Command::NextMonth => self.shift_current_date(1, true),
+            Command::PrevMonth => self.shift_current_date(-1, true),
+            Command::OpenCalendar => self.open_calendar(),
+            Command::BrowseAccounts => self.open_account_browser(),
+            Command::Today => {
+                set_textarea_content(self.current_field_mut(), &format_naive_date(&today()));
+                self.mark_dirty();
+            }
+            Command::Normalize => self.normalize_current_field(),
+            Command::RevertField => self.revert_current_field(),
+            Command::ExpandAccount => self.expand_current_account(),
+            Command::CompleteAccount => {
+                let query = textarea_content(self.current_field_mut());
+                self.completion.open(&query, &self.known_accounts);
+            }
+            Command::ClearField => {
+                let current_field = self.current_field_mut();
+                if !textarea_content(current_field).is_empty() {
+                    set_textarea_content(current_field, "");
+                    self.mark_dirty();
+                }
+            }
+            Command::Undo => {
+                if let Some(undo) = self.batch_undo.take() {
+                    self.undo_batch(undo);
+                } else if self.undo_reject && !self.rejected.is_empty() {
+                    self.restore_rejected(self.rejected.len() - 1);
+                } else if self.current_field_mut().undo() {
+                    self.mark_dirty();
+                }
+            }
+            Command::NextField => self.navigate_field(true),
+            Command::PrevField => self.navigate_field(false),
+            Command::FocusRight | Command::FocusLeft => {
+                let forward = command == Command::FocusRight;
+                if self.focus_on_postings {
+                    self.navigate_posting_field(forward)?;
+                } else {
+                    self.navigate_metadata_field(forward)?;
+                }
+            }
+            Command::InsertPostingBelow => self.insert_posting(false),
+            Command::InsertPostingAbove => self.insert_posting(true),
+            Command::DeletePosting => self.confirm_delete_posting(),
+            Command::SplitPosting => self.split_posting(),
+            Command::BalancePosting => self.balance_current_posting(),
+            Command::DeleteTransaction => self.confirm_delete_transaction(),
+            Command::OpenCommandLine => {
+                let mut command_line = TextArea::default();
+                command_line.set_cursor_line_style(Style::default());
+                self.command_line = Some(command_line);
+            }
+            Command::JumpToPosting(posting) => self.jump_to_posting(posting as usize),
+            Command::FlipSign => self.flip_current_amount(),
+            Command::FlipAllSigns => self.flip_all_amounts(),
+            Command::CleanTransaction => {
+                if clean_transaction(&mut self.transactions[self.current_index]) {
+                    self.mark_dirty();
+                    self.set_status("Cleaned up the whitespace");
+                } else {
+                    self.set_status("Nothing to clean up");
+                }
+            }
+            Command::EditComments => self.open_comment_editor(),
+            Command::SwapPayeeNarration => self.swap_payee_narration(),
+            Command::NarrationToPayee => self.narration_to_payee(),
+            Command::FocusDown | Command::FocusUp if self.focus_on_postings => {
+                self.navigate_posting(command == Command::FocusDown)?;
+            }
+            Command::FocusDown | Command::FocusUp => {
+                let n_postings = self.transactions[self.current_index]
+                    .postings_textareas()
+                    .len();
+                if n_postings == 0 {
+                    self.set_status(NO_POSTINGS);
+                    return Ok(());
+                }
+                self.leave_field();
+                self.focus_on_postings = true;
+                self.currently_selected_posting = if command == Command::FocusDown {
+                    0
+                } else {
+                    n_postings - 1
+                };
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the metadata field at `index` of [`METAFIELD_ORDER`] has the focus
+    pub fn is_metadata_field_focused(&self, index: usize) -> bool {
+        !self.focus_on_postings && index == self.currently_selected_metadata_field
+    }
+
+    /// Whether `field` of the posting at `index` has the focus
+    pub fn is_posting_field_focused(&self, index: usize, field: PostingField) -> bool {
+        self.focus_on_postings
+            && index == self.currently_selected_posting
+            && field == self.currently_selected_posting_field
+    }
+
+    /// Returns the type of the field that currently has focus
+    pub fn focused_field_type(&self) -> InputFieldType {
+        if self.focus_on_postings {
+            self.currently_selected_posting_field.into()
+        } else {
+            METAFIELD_ORDER[self.currently_selected_metadata_field]
+        }
+    }
+
+    /// Moves the date of the current transaction by `amount` days (or months)
+    fn shift_current_date(&mut self, amount: i32, by_month: bool) {
+        let date_textarea = &mut self.transactions[self.current_index].metadata_textareas_mut()[0];
+        let content = textarea_content(date_textarea);
+        let Some(date) = parse_date(&content) else {
+            // a broken import date is easier to fix starting from today
+            set_textarea_content(date_textarea, &format_naive_date(&today()));
+            self.mark_dirty();
+            self.set_status(format!("Invalid date '{}', reset to today", content));
+            return;
+        };
+        match shift_date(date, amount, by_month) {
+            Some(new_date) => {
+                set_textarea_content(date_textarea, &format_naive_date(&new_date));
+                self.mark_dirty();
+            }
+            None => self.set_status("Date out of range"),
+        }
+    }
+
+    /// Normalizes the content of the focused field depending on its type
+    fn normalize_current_field(&mut self) {
+        match self.focused_field_type() {
+            InputFieldType::Date => {
+                let date_textarea =
+                    &mut self.transactions[self.current_index].metadata_textareas_mut()[0];
+                let content = textarea_content(date_textarea);
+                match parse_relative_date(&content, today()) {
+                    Some(date) => {
+                        set_textarea_content(date_textarea, &format_naive_date(&date));
+                        self.mark_dirty();
+                    }
+                    None => self.set_status(format!("Couldn't interpret date '{}'", content)),
+                }
+            }
+            InputFieldType::Account => self.normalize_current_account(),
+            _ => {}
+        }
+    }
+
+    /// Expands aliases and abbreviated components of the focused account field
+    fn expand_current_account(&mut self) {
+        let account = textarea_content(self.current_field_mut());
+        match expand_account(&account, &self.config.aliases, &self.known_accounts) {
+            Expansion::Expanded(expanded) => {
+                set_textarea_content(self.current_field_mut(), &expanded);
+                self.mark_dirty();
+            }
+            Expansion::Ambiguous(prefix) => {
+                if prefix != account {
+                    set_textarea_content(self.current_field_mut(), &prefix);
+                    self.mark_dirty();
+                }
+                self.completion.open(&prefix, &self.known_accounts);
+            }
+            Expansion::NoMatch => self.set_status("no alias match"),
+        }
+    }
+
+    /// Fixes the capitalization of the focused account field
+    fn normalize_current_account(&mut self) {
+        let account_textarea = &mut self.transactions[self.current_index].postings_textareas_mut()
+            [self.currently_selected_posting]
+            .account_textarea;
+        let account = textarea_content(account_textarea);
+        let normalized = normalize_account(&account, &self.known_accounts);
+        if normalized != account {
+            set_textarea_content(account_textarea, &normalized);
+            self.mark_dirty();
+            self.set_status(format!("Normalized account to {}", normalized));
+        }
+    }
+
+    /// Position of the focused metadata field in the configured `metadata_order`
+    fn metadata_position(&self) -> usize {
+        let field = METAFIELD_ORDER[self.currently_selected_metadata_field];
+        self.config
+            .metadata_order
+            .iter()
+            .position(|&f| f == field)
+            .expect("metadata_order contains every metadata field")
+    }
+
+    /// Focuses the metadata field at `position` of the configured `metadata_order`
+    fn select_metadata_position(&mut self, position: usize) {
+        self.currently_selected_metadata_field =
+            metafield_index(self.config.metadata_order[position]);
+    }
+
+    fn navigate_metadata_field(&mut self, forward: bool) -> Result<()> {
+        self.leave_field();
+        let n_metadata = METAFIELD_ORDER.len();
+        let position = self.metadata_position();
+        if forward {
+            self.select_metadata_position((position + 1) % n_metadata);
+        } else {
+            self.select_metadata_position((position + n_metadata - 1) % n_metadata);
+        }
+        Ok(())
+    }
+
+    /// Moves to the next or previous field in one linear order: the metadata fields,
+    /// then the fields of each posting, wrapping around at both ends
+    fn navigate_field(&mut self, forward: bool) {
+        self.leave_field();
+        let n_postings = self.transactions[self.current_index]
+            .postings_textareas()
+            .len();
+        let n_metadata = METAFIELD_ORDER.len();
+        let n_fields = n_metadata + n_postings * POSTING_FIELD_ORDER.len();
+        let current = if self.focus_on_postings {
+            let field = POSTING_FIELD_ORDER
+                .iter()
+                .position(|&field| field == self.currently_selected_posting_field)
+                .expect("Posting fields are in POSTING_FIELD_ORDER");
+            n_metadata + self.currently_selected_posting * POSTING_FIELD_ORDER.len() + field
+        } else {
+            self.metadata_position()
+        };
+        let next = if forward {
+            (current + 1) % n_fields
+        } else {
+            (current + n_fields - 1) % n_fields
+        };
+        if next < n_metadata {
+            self.focus_on_postings = false;
+            self.select_metadata_position(next);
+        } else {
+            let posting_field = next - n_metadata;
+            self.focus_on_postings = true;
+            self.currently_selected_posting = posting_field / POSTING_FIELD_ORDER.len();
+            self.currently_selected_posting_field =
+                POSTING_FIELD_ORDER[posting_field % POSTING_FIELD_ORDER.len()];
+        }
+    }
+
+    fn navigate_posting(&mut self, forward: bool) -> Result<()> {
+        self.leave_field();
+        let current_transaction = &mut self.transactions[self.current_index];
+        let n_postings = current_transaction.postings_textareas().len();
+        if forward {
+            let next_posting = self.currently_selected_posting + 1;
+            if next_posting >= n_postings {
+                self.focus_on_postings = false
+            } else {
+                self.currently_selected_posting = next_posting
+            }
+        } else {
+            match self.currently_selected_posting.checked_sub(1) {
+                Some(prev_posting) => self.currently_selected_posting = prev_posting,
+                None => self.focus_on_postings = false,
+            }
+        }
+        Ok(())
+    }
+    /// Focuses the account of the 1-based `posting`, clamped to the last posting,
+    /// or the payee for 0
+    fn jump_to_posting(&mut self, posting: usize) {
+        self.leave_field();
+        if posting == 0 {
+            self.focus_on_postings = false;
+            self.currently_selected_metadata_field = metafield_index(InputFieldType::Payee);
+        } else {
+            let n_postings = self.transactions[self.current_index]
+                .postings_textareas()
+                .len();
+            if n_postings == 0 {
+                self.set_status(NO_POSTINGS);
+                return;
+            }
+            if posting > n_postings {
+                self.set_status(format!(
+                    "No posting {}, the transaction has {}",
+                    posting, n_postings
+                ));
+            }
+            self.focus_on_postings = true;
+            self.currently_selected_posting = posting.min(n_postings) - 1;
+            self.currently_selected_posting_field = PostingField::Account;
+        }
+    }
+
+    fn navigate_posting_field(&mut self, forward: bool) -> Result<()> {
+        self.leave_field();
+        let current_transaction = &mut self.transactions[self.current_index];
+        let current_posting =
+            &mut current_transaction.postings_textareas_mut()[self.currently_selected_posting];
+        self.currently_selected_posting_field =
+            current_posting.next_field(&self.currently_selected_posting_field, forward);
+        Ok(())
+    }
+
+    fn next_transaction(&mut self) -> Result<()> {
+        self.move_transactions(1, true);
+        Ok(())
+    }
+
+    fn prev_transaction(&mut self) -> Result<()> {
+        self.move_transactions(1, false);
+        Ok(())
+    }
+
+    /// Moves `count` visible transactions forward or back, stopping at the last or first one
+    fn move_transactions(&mut self, count: usize, forward: bool) {
+        if self.transactions.is_empty() {
+            return;
+        }
+        self.leave_transaction();
+        let target = if forward {
+            (self.current_index + 1..self.transactions.len())
+                .filter(|&i| self.is_visible(i))
+                .take(count)
+                .last()
+        } else {
+            (0..self.current_index)
+                .rev()
+                .filter(|&i| self.is_visible(i))
+                .take(count)
+                .last()
+        };
+        if let Some(target) = target {
+            self.current_index = target;
+        }
+        self.clamp_focus();
+        if self.config.focus_issues && target.is_some() {
+            self.focus_first_issue();
+        }
+    }
+
+    /// Keeps the focused field after switching transactions, moving to the last posting
+    /// if the new transaction has fewer postings
+    fn clamp_focus(&mut self) {
+        let n_postings = self.transactions[self.current_index]
+            .postings_textareas()
+            .len();
+        if n_postings == 0 {
+            self.focus_on_postings = false;
+            self.currently_selected_posting = 0;
+        } else {
+            self.currently_selected_posting = self.currently_selected_posting.min(n_postings - 1);
+        }
+        self.drop_distant_widgets();
+    }
+
+    /// Frees the text areas of unedited transactions far from the current one, e.g. the
+    /// ones a filter search or a save went through
+    fn drop_distant_widgets(&mut self) {
+        let kept = self.current_index.saturating_sub(WIDGET_CACHE_RADIUS)
+            ..=self.current_index + WIDGET_CACHE_RADIUS;
+        for (index, transaction) in self.transactions.iter_mut().enumerate() {
+            if !kept.contains(&index) {
+                transaction.drop_widgets();
+            }
+        }
+    }
+
+    /// Inserts an empty posting next to the selected one and focuses it.
+    /// Without a selected posting, the new posting is appended.
+    fn insert_posting(&mut self, above: bool) {
+        let n_postings = self.transactions[self.current_index]
+            .postings_textareas()
+            .len();
+        let index = match (self.focus_on_postings, above) {
+            (false, _) => n_postings,
+            (true, true) => self.currently_selected_posting,
+            (true, false) => self.currently_selected_posting + 1,
+        };
+        self.add_posting(index);
+    }
+
+    /// Removes the focused posting, a transaction keeps at least one posting
+    fn confirm_delete_posting(&mut self) {
+        let postings = &self.transactions[self.current_index].postings_textareas();
+        if postings.len() <= 1 {
+            self.set_status("Can't delete the only posting");
+            return;
+        }
+        let account = textarea_content(&postings[self.currently_selected_posting].account_textarea);
+        self.popup = Popup::new(
+            format!("Delete the posting to {}?", account.trim()),
+            PendingAction::DeletePosting(self.current_index, self.currently_selected_posting),
+        );
+    }
+
+    fn delete_posting(&mut self, index: usize, posting: usize) {
+        let postings = &mut self.transactions[index].postings_textareas_mut();
+        if postings.len() <= 1 || posting >= postings.len() {
+            return;
+        }
+        postings.remove(posting);
+        self.mark_transaction_dirty(index);
+        self.clamp_focus();
+    }
+
+    /// Asks before deleting the current transaction, there always has to be one left
+    fn confirm_delete_transaction(&mut self) {
+        if self.transactions.len() <= 1 {
+            self.set_status("Can't delete the only transaction");
+            return;
+        }
+        if self.in_place {
+            // the transaction would stay in its source file
+            self.set_status("Can't delete transactions when saving in place");
+            return;
+        }
+        let transaction = &self.transactions[self.current_index];
+        self.popup = Popup::new(
+            format!(
+                "Delete the transaction from {}?",
+                textarea_content(&transaction.metadata_textareas()[0]).trim()
+            ),
+            PendingAction::DeleteTransaction(self.current_index),
+        );
+    }
+
+    fn delete_transaction(&mut self, index: usize) {
+        if self.transactions.len() <= 1 || index >= self.transactions.len() {
+            return;
+        }
+        self.remove_transaction(index);
+        self.set_status(format!("Deleted transaction {}", index + 1));
+    }
+
+    /// Moves the transaction at `index` out of the output into `rejected`, it is written
+    /// to its own file when saving
+    fn reject_transaction(&mut self, index: usize) {
+        if self.transactions.len() <= 1 {
+            self.set_status("The last transaction can't be rejected");
+            return;
+        }
+        if self.in_place {
+            // it would stay in its source file and end up in both files
+            self.set_status("Can't reject transactions when saving in place");
+            return;
+        }
+        self.leave_field();
+        let transaction = self.remove_transaction(index);
+        self.rejected.push((index, transaction));
+        self.undo_reject = true;
+        self.set_status(format!(
+            "Rejected transaction {}, {} rejected in total",
+            index + 1,
+            self.rejected.len()
+        ));
+    }
+
+    /// Puts the rejected transaction at `position` of `rejected` back where it was
+    fn restore_rejected(&mut self, position: usize) {
+        let (index, transaction) = self.rejected.remove(position);
+        let index = index.min(self.transactions.len());
+        self.transactions.insert(index, transaction);
+        self.batch_undo = None;
+        self.undo_reject = false;
+        self.marks = self
+            .marks
+            .iter()
+            .map(|&mark| if mark >= index { mark + 1 } else { mark })
+            .collect();
+        self.current_index = index;
+        self.unsaved_changes = true;
+        self.reordered = true;
+        self.clamp_focus();
+        self.set_status(format!("Restored transaction {}", index + 1));
+    }
+
+    /// Removes the transaction at `index`, keeping the marks and the current transaction
+    fn remove_transaction(&mut self, index: usize) -> TransactionTui<'t> {
+        let transaction = self.transactions.remove(index);
+        self.batch_undo = None;
+        self.marks = self
+            .marks
+            .iter()
+            .filter(|&&mark| mark != index)
+            .map(|&mark| if mark > index { mark - 1 } else { mark })
+            .collect();
+        if self.current_index > index || self.current_index == self.transactions.len() {
+            self.current_index -= 1;
+        }
+        self.unsaved_changes = true;
+        self.reordered = true;
+        self.clamp_focus();
+        transaction
+    }
+
+    /// Inserts an empty posting at `index` of the current transaction and focuses its account
+    pub fn add_posting(&mut self, index: usize) {
+        self.leave_field();
+        let postings = &mut self.transactions[self.current_index].postings_textareas_mut();
+        let index = index.min(postings.len());
+        let mut posting = PostingTui::default();
+        if let Some(currency) = &self.config.default_currency {
+            set_textarea_content(&mut posting.currency_textarea, currency);
+        }
+        postings.insert(index, posting);
+        self.focus_on_postings = true;
+        self.currently_selected_posting = index;
+        self.currently_selected_posting_field = PostingField::Account;
+        self.mark_dirty();
+    }
+
+    /// Called before the focus moves away from the current field
+    fn leave_field(&mut self) {
+        match self.focused_field_type() {
+            InputFieldType::Amount => self.fill_default_currency(),
+            InputFieldType::Payee if self.config.suggest_accounts => self.suggest_account(),
+            InputFieldType::Account if self.config.normalize_accounts => {
+                self.normalize_current_account()
+            }
+            _ => {}
+        }
+    }
+
+    /// Fills the first empty account with the account most often used with the payee
+    fn suggest_account(&mut self) {
+        let transaction = &mut self.transactions[self.current_index];
+        let payee = textarea_content(&transaction.metadata_textareas()[2]);
+        let Some(accounts) = self.payee_accounts.get(payee.trim()) else {
+            return;
+        };
+        let used_accounts: Vec<String> = transaction
+            .postings_textareas()
+            .iter()
+            .map(|p| textarea_content(&p.account_textarea))
+            .collect();
+        let Some(suggestion) = accounts.iter().find(|a| !used_accounts.contains(a)) else {
+            return;
+        };
+        let empty_posting = transaction
+            .postings_textareas_mut()
+            .iter_mut()
+            .find(|p| textarea_content(&p.account_textarea).trim().is_empty());
+        if let Some(posting) = empty_posting {
+            set_textarea_content(&mut posting.account_textarea, suggestion);
+            self.set_status(format!("Suggested {} for {}", suggestion, payee.trim()));
+            self.mark_dirty();
+        }
+    }
+
+    /// Fills in the default currency if the focused posting has an amount but no currency
+    fn fill_default_currency(&mut self) {
+        let Some(currency) = &self.config.default_currency else {
+            return;
+        };
+        let posting = &mut self.transactions[self.current_index].postings_textareas_mut()
+            [self.currently_selected_posting];
+        if !textarea_content(&posting.amount_textarea).trim().is_empty()
+            && textarea_content(&posting.currency_textarea)
+                .trim()
+                .is_empty()
+        {
+            set_textarea_content(&mut posting.currency_textarea, currency);
+            self.mark_dirty();
+        }
+    }
+
+    /// Explains in the status bar why the focused amount field isn't a valid number,
+    /// the message goes away once it is fixed
+    fn check_amount(&mut self) {
+        if self.focused_field_type() != InputFieldType::Amount {
+            return;
+        }
+        let amount = textarea_content(self.current_field_mut());
+        if !is_valid_amount(&amount) {
+            self.set_status(invalid_amount_message(&amount));
+        } else if self
+            .status_message
+            .as_ref()
+            .is_some_and(|message| message.text.starts_with("Invalid amount"))
+        {
+            self.status_message = None;
+        }
+    }
+
+    /// Negates the focused amount, keeping its decimal places
+    fn flip_current_amount(&mut self) {
+        let amount = textarea_content(self.current_field_mut());
+        if amount.trim().is_empty() {
+            self.set_status("No amount to flip");
+            return;
+        }
+        match negate_amount(&amount) {
+            Some(negated) => {
+                set_textarea_content(self.current_field_mut(), &negated);
+                self.mark_dirty();
+            }
+            None => self.set_status(invalid_amount_message(&amount)),
+        }
+    }
+
+    /// Negates every amount of the current transaction, for imports with the opposite
+    /// sign convention
+    fn flip_all_amounts(&mut self) {
+        let postings = self.transactions[self.current_index].postings_textareas_mut();
+        let amounts: Vec<String> = postings
+            .iter()
+            .map(|p| textarea_content(&p.amount_textarea))
+            .collect();
+        if let Some(invalid) = amounts.iter().find(|amount| !is_valid_amount(amount)) {
+            let message = invalid_amount_message(invalid);
+            self.set_status(message);
+            return;
+        }
+        let mut flipped = 0;
+        for (posting, amount) in postings.iter_mut().zip(&amounts) {
+            if let Some(negated) = negate_amount(amount) {
+                set_textarea_content(&mut posting.amount_textarea, &negated);
+                flipped += 1;
+            }
+        }
+        if flipped == 0 {
+            self.set_status("No amounts to flip");
+            return;
+        }
+        self.mark_dirty();
+        let plural = if flipped == 1 { "" } else { "s" };
+        self.set_status(format!("Flipped the sign of {} amount{}", flipped, plural));
+    }
+
+    /// Inserts a posting with the same currency below the focused one and moves half of
+    /// the amount there, the account of the new posting is focused to fill it in
+    fn split_posting(&mut self) {
+        let index = self.currently_selected_posting;
+        let posting = &self.transactions[self.current_index].postings_textareas()[index];
+        let amount = textarea_content(&posting.amount_textarea);
+        let currency = textarea_content(&posting.currency_textarea);
+        let (cost, price) = (posting.cost.clone(), posting.price.clone());
+        if price.as_ref().is_some_and(|price| price.total) {
+            self.set_status("Can't split a posting with a total price");
+            return;
+        }
+        let halves = if amount.trim().is_empty() {
+            // the new posting is left empty as well
+            (String::new(), String::new())
+        } else {
+            match split_amount(&amount) {
+                Some(halves) => halves,
+                None => {
+                    self.set_status(invalid_amount_message(&amount));
+                    return;
+                }
+            }
+        };
+        self.add_posting(index + 1);
+        let postings = self.transactions[self.current_index].postings_textareas_mut();
+        set_textarea_content(&mut postings[index].amount_textarea, &halves.0);
+        set_textarea_content(&mut postings[index + 1].amount_textarea, &halves.1);
+        set_textarea_content(&mut postings[index + 1].currency_textarea, &currency);
+        postings[index + 1].cost = cost;
+        postings[index + 1].price = price;
+    }
+
+    /// Writes the residual of the focused posting's currency into its amount
+    fn balance_current_posting(&mut self) {
+        let index = self.currently_selected_posting;
+        let transaction = &mut self.transactions[self.current_index];
+        let Some((amount, currency)) = balancing_amount(transaction, index) else {
+            self.set_status("Can't balance, set the currency or fix the invalid amounts");
+            return;
+        };
+        let posting = &mut transaction.postings_textareas_mut()[index];
+        let changed = parse_amount(&textarea_content(&posting.amount_textarea)) != Some(amount)
+            || textarea_content(&posting.currency_textarea).trim() != currency;
+        if !changed {
+            self.set_status(format!("{} is already balanced", currency));
+            return;
+        }
+        set_textarea_content(&mut posting.amount_textarea, &amount.to_string());
+        set_textarea_content(&mut posting.currency_textarea, &currency);
+        self.mark_dirty();
+        self.set_status(format!("Balanced {} with this posting", currency));
+    }
+
+    /// The transaction and posting of the first amount that isn't a valid number.
+    /// Only edited transactions are checked, parsed amounts are always valid.
+    fn invalid_amount(&self) -> Option<(usize, usize)> {
+        self.transactions
+            .iter()
+            .enumerate()
+            .filter(|(_, transaction)| transaction.dirty || transaction.edited)
+            .find_map(|(index, transaction)| Some((index, first_invalid_amount(transaction)?)))
+    }
+
+    /// Focuses the first amount that isn't a valid number. Returns false if all
+    /// amounts are valid.
+    fn focus_invalid_amount(&mut self) -> bool {
+        let Some((index, posting)) = self.invalid_amount() else {
+            return false;
+        };
+        if index != self.current_index {
+            self.leave_transaction();
+            self.current_index = index;
+        }
+        self.focus_on_postings = true;
+        self.currently_selected_posting = posting;
+        self.currently_selected_posting_field = PostingField::Amount;
+        self.clamp_focus();
+        true
+    }
+
+    /// Marks the current transaction as edited
+    fn mark_dirty(&mut self) {
+        self.mark_transaction_dirty(self.current_index);
+    }
+
+    fn mark_transaction_dirty(&mut self, index: usize) {
+        // the positions stored for undoing a batch may not be valid anymore
+        self.batch_undo = None;
+        self.undo_reject = false;
+        self.transactions[index].dirty = true;
+        self.unsaved_changes = true;
+    }
+
+    /// Flags all valid transactions from the current one on that are marked `!` as reviewed
+    fn accept_remaining(&mut self) {
+        let mut accepted = 0;
+        let mut skipped = 0;
+        for index in self.current_index..self.transactions.len() {
+            let transaction = &mut self.transactions[index];
+            if textarea_content(&transaction.metadata_textareas()[1]).trim() != "!" {
+                continue;
+            }
+            if !validate_transaction(transaction).is_empty() {
+                skipped += 1;
+                continue;
+            }
+            set_textarea_content(&mut transaction.metadata_textareas_mut()[1], "*");
+            self.mark_transaction_dirty(index);
+            accepted += 1;
+        }
+        self.set_status(format!(
+            "Accepted {} transactions, skipped {} failing validation",
+            accepted, skipped
+        ));
+    }
+
+    /// Flags the current transaction as reviewed and moves on to the next one, focusing
+    /// its first issue. A transaction failing validation keeps the focus on its problem.
+    fn accept_and_next(&mut self) {
+        self.leave_field();
+        let index = self.current_index;
+        if let Some(problem) = validate_transaction(&self.transactions[index]).first() {
+            let message = format!("Not accepted: {}", problem);
+            self.focus_first_issue();
+            self.set_status(message);
+            return;
+        }
+        let flag_textarea = &mut self.transactions[index].metadata_textareas_mut()[1];
+        if textarea_content(flag_textarea).trim() == "!" {
+            set_textarea_content(flag_textarea, "*");
+            self.mark_transaction_dirty(index);
+        }
+        if self.config.save_on_accept && !self.save() {
+            return;
+        }
+        self.move_transactions(1, true);
+        if self.current_index == index {
+            self.set_status("Accepted the last transaction");
+        } else {
+            self.focus_first_issue();
+        }
+    }
+
+    /// Called before the current transaction is left, triggers the autosave if enabled
+    fn leave_transaction(&mut self) {
+        self.leave_field();
+        if self.autosave && self.unsaved_changes {
+            self.autosave_pending = true;
+        }
+        if self.autosave_pending
+            && self
+                .last_save
+                .is_none_or(|last_save| last_save.elapsed() >= AUTOSAVE_INTERVAL)
+        {
+            self.save();
+        }
+    }
+
+    /// Writes all transactions to the output file, returns wether everything was written
+    fn save(&mut self) -> bool {
+        if self.output.is_none() && self.export_json.is_none() && !self.in_place {
+            self.set_status("No output file given, use --output");
+            return false;
+        }
+        if self.focus_invalid_amount() {
+            let amount = textarea_content(self.current_field_mut());
+            self.set_status(format!("Not saved: {}", invalid_amount_message(&amount)));
+            return false;
+        }
+        self.clean_edited_transactions();
+        let mut messages = Vec::new();
+        let mut saved = true;
+        // written first, a rejected transaction must not vanish from both files
+        if !self.rejected.is_empty() {
+            let rejected: Vec<TransactionTui> = self
+                .rejected
+                .iter()
+                .map(|(_, transaction)| transaction.clone())
+                .collect();
+            let content = format_transactions(&rejected, &self.config.format);
+            if let Err(err) = write_atomically(&self.rejected_output, &content) {
+                self.set_status(format!(
+                    "Not saved: couldn't write {}: {}",
+                    self.rejected_output.display(),
+                    err
+                ));
+                return false;
+            }
+            messages.push(format!(
+                "{} rejected to {}",
+                rejected.len(),
+                self.rejected_output.display()
+            ));
+        }
+        if self.in_place {
+            match self.save_in_place() {
+                Ok(message) => messages.push(message),
+                Err(message) => {
+                    messages.push(message);
+                    saved = false;
+                }
+            }
+        }
+        if let Some(output) = self.output.clone() {
+            let content = if self.inputs.len() > 1 {
+                format_transactions_by_source(
+                    &self.transactions,
+                    &self.context,
+                    &self.config.format,
+                )
+            } else {
+                format_transactions_with_context(
+                    &self.transactions,
+                    &self.context,
+                    &self.config.format,
+                )
+            };
+            let written = self
+                .check_output(&content)
+                .and_then(|()| write_atomically(&output, &content).map_err(|err| err.to_string()));
+            match written {
+                Ok(()) => {
+                    if let Some(watcher) = &mut self.watcher {
+                        watcher.remember(&output, &content);
+                    }
+                    messages.push(format!("Saved to {}", output.display()));
+                    self.unsaved_changes = false;
+                    self.autosave_pending = false;
+                    self.last_save = Some(Instant::now());
+                    self.remember_own_write(&output);
+                }
+                Err(err) => {
+                    messages.push(format!("Couldn't save {}: {}", output.display(), err));
+                    saved = false;
+                }
+            }
+        }
+        if let Some(export) = &self.export_json {
+            match write_json(export, &self.transactions) {
+                Ok(()) => messages.push(format!("Exported to {}", export.display())),
+                Err(err) => {
+                    messages.push(format!("{:#}", err));
+                    saved = false;
+                }
+            }
+        }
+        self.set_status(messages.join(", "));
+        saved
+    }
+
+    /// Parses `content` again and runs the `--check-cmd` on it before it is written,
+    /// unless `--force` was given. A failure is shown until closed.
+    fn check_output(&mut self, content: &str) -> Result<(), String> {
+        if self.force {
+            return Ok(());
+        }
+        let checked = check_parses(content).and_then(|()| match &self.check_cmd {
+            Some(command) => run_check_command(command, content),
+            None => Ok(()),
+        });
+        checked.map_err(|failure| {
+            let summary = failure.summary.clone();
+            self.check_failure = Some(failure);
+            summary
+        })
+    }
+
+    /// Cleans the fields of the edited transactions so the text areas show what is saved
+    fn clean_edited_transactions(&mut self) {
+        for index in 0..self.transactions.len() {
+            let transaction = &mut self.transactions[index];
+            if (transaction.dirty || transaction.edited) && clean_transaction(transaction) {
+                self.mark_transaction_dirty(index);
+            }
+        }
+    }
+
+    /// Splices the edited transactions into their source files, or only records
+    /// the diffs for a dry run. Returns the status message.
+    fn save_in_place(&mut self) -> Result<String, String> {
+        let changes = plan_writeback(&self.transactions, &self.config.format)
+            .map_err(|err| format!("{:#}", err))?;
+        let files = changes
+            .iter()
+            .map(|change| change.path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        if self.dry_run {
+            self.dry_run_report = changes.iter().map(FileChange::unified_diff).collect();
+            return Ok(if changes.is_empty() {
+                "Dry run: no file would change".to_string()
+            } else {
+                format!("Dry run: would change {}", files)
+            });
+        }
+        for change in &changes {
+            self.check_output(&change.new)
+                .map_err(|err| format!("Couldn't save {}: {}", change.path.display(), err))?;
+        }
+        for change in &changes {
+            change.write().map_err(|err| format!("{:#}", err))?;
+            update_spans(&mut self.transactions, change);
+            if let Some(watcher) = &mut self.watcher {
+                watcher.remember(&change.path, &change.new);
+            }
+            self.remember_own_write(&change.path);
+        }
+        if changes
+            .iter()
+            .any(|change| self.file_modified.contains_key(&change.path))
+        {
+            if let Ok(hash) = inputs_hash(&self.inputs) {
+                self.content_hash = hash;
+            }
+        }
+        for transaction in &mut self.transactions {
+            // the saved fields no longer match the parsed directive
+            transaction.edited |= transaction.dirty;
+            transaction.dirty = false;
+        }
+        self.unsaved_changes = false;
+        self.autosave_pending = false;
+        self.last_save = Some(Instant::now());
+        Ok(if changes.is_empty() {
+            "Nothing to save".to_string()
+        } else {
+            format!("Saved to {}", files)
+        })
+    }
+
+    /// Exits right away if nothing would be lost, otherwise asks for confirmation
+    fn request_exit(&mut self) {
+        if self.autosave && self.unsaved_changes {
+            self.save();
+        }
+        let mut warnings = Vec::new();
+        // nothing can be saved in read-only mode
+        if self.has_unsaved_changes() && !self.read_only {
+            warnings.push("You have unsaved changes.".to_string());
+        }
+        if !self.marks.is_empty() {
+            warnings.push(format!("{} marked transactions remain.", self.marks.len()));
+        }
+        if warnings.is_empty() {
+            self.exit();
+        } else {
+            self.popup = Popup::new(
+                format!("{} Quit anyway?", warnings.join(" ")),
+                PendingAction::Exit,
+            );
+        }
+    }
+
+    /// Whether quitting would lose edits. Edits that were undone by hand don't count
+    /// until something was saved, from then on the output has to be written again.
+    fn has_unsaved_changes(&self) -> bool {
+        if !self.unsaved_changes {
+            return false;
+        }
+        if self.reordered || self.last_save.is_some() {
+            return true;
+        }
+        self.transactions.iter().any(|t| t.dirty && t.changed())
+    }
+
+    /// Puts back the parsed content of the focused field
+    fn revert_current_field(&mut self) {
+        let transaction = &mut self.transactions[self.current_index];
+        let reverted = if self.focus_on_postings {
+            transaction.revert_posting_field(
+                self.currently_selected_posting,
+                &self.currently_selected_posting_field,
+            )
+        } else {
+            transaction.revert_metadata(self.currently_selected_metadata_field)
+        };
+        if reverted {
+            self.mark_dirty();
+            self.check_amount();
+            self.set_status("Reverted the field to its parsed value");
+        } else {
+            self.set_status("The field wasn't changed");
+        }
+    }
+
+    /// Marks or unmarks the current transaction
+    fn toggle_mark(&mut self) {
+        if !self.marks.remove(&self.current_index) {
+            self.marks.insert(self.current_index);
+        }
+    }
+
+    /// Moves to the next (or previous) marked transaction, wrapping around at the ends
+    fn jump_to_mark(&mut self, forward: bool) {
+        let n_transactions = self.transactions.len();
+        let target = (1..n_transactions)
+            .map(|offset| {
+                if forward {
+                    (self.current_index + offset) % n_transactions
+                } else {
+                    (self.current_index + n_transactions - offset) % n_transactions
+                }
+            })
+            .find(|index| self.marks.contains(index));
+        match target {
+            Some(index) => {
+                self.leave_transaction();
+                self.current_index = index;
+                self.clamp_focus();
+            }
+            None => self.set_status("No other marked transactions"),
+        }
+    }
+
+    /// Focuses the next field needing attention after the focused one, see
+    /// `issue_fields`, or the first one of the next transaction having any
+    fn next_issue(&mut self) {
+        self.leave_field();
+        let focused = self.field_rank(self.focused_transaction_field());
+        let later_issue = self
+            .issues(self.current_index)
+            .into_iter()
+            .find(|&field| self.field_rank(field) > focused);
+        if let Some(field) = later_issue {
+            self.focus_transaction_field(field);
+            return;
+        }
+        let next = (self.current_index + 1..self.transactions.len())
+            .filter(|&index| self.is_visible(index))
+            .find_map(|index| Some((index, *self.issues(index).first()?)));
+        match next {
+            Some((index, field)) => {
+                self.leave_transaction();
+                self.current_index = index;
+                self.clamp_focus();
+                self.focus_transaction_field(field);
+            }
+            None => self.set_status("No more issues"),
+        }
+    }
+
+    /// Focuses the first field of the current transaction needing attention, if any
+    fn focus_first_issue(&mut self) {
+        if let Some(&field) = self.issues(self.current_index).first() {
+            self.focus_transaction_field(field);
+        }
+    }
+
+    /// The fields of the transaction at `index` needing attention, in the order Tab
+    /// moves through them
+    fn issues(&self, index: usize) -> Vec<TransactionField> {
+        let mut fields = issue_fields(&self.transactions[index]);
+        fields.sort_by_key(|&field| self.field_rank(field));
+        fields
+    }
+
+    /// Position of `field` in the order Tab moves through the fields
+    fn field_rank(&self, field: TransactionField) -> (usize, usize, usize) {
+        match field {
+            TransactionField::Metadata(index) => {
+                let position = self
+                    .config
+                    .metadata_order
+                    .iter()
+                    .position(|&f| f == METAFIELD_ORDER[index])
+                    .unwrap_or(index);
+                (0, position, 0)
+            }
+            TransactionField::Posting(posting, field) => {
+                let position = POSTING_FIELD_ORDER
+                    .iter()
+                    .position(|&f| f == field)
+                    .unwrap_or_default();
+                (1, posting, position)
+            }
+        }
+    }
+
+    fn focused_transaction_field(&self) -> TransactionField {
+        if self.focus_on_postings {
+            TransactionField::Posting(
+                self.currently_selected_posting,
+                self.currently_selected_posting_field,
+            )
+        } else {
+            TransactionField::Metadata(self.currently_selected_metadata_field)
+        }
+    }
+
+    fn focus_transaction_field(&mut self, field: TransactionField) {
+        match field {
+            TransactionField::Metadata(index) => {
+                self.focus_on_postings = false;
+                self.currently_selected_metadata_field = index;
+            }
+            TransactionField::Posting(posting, field) => {
+                self.focus_on_postings = true;
+                self.currently_selected_posting = posting;
+                self.currently_selected_posting_field = field;
+            }
+        }
+    }
+
+    /// The context directives read right before the current transaction, while shown
+    pub fn shown_context(&self) -> Vec<&ContextDirective> {
+        if !self.show_context {
+            return Vec::new();
+        }
+        let transaction = &self.transactions[self.current_index];
+        self.context
+            .iter()
+            .filter(|directive| {
+                directive.source == transaction.source
+                    && directive.next_transaction == transaction.line_number
+                    && transaction.line_number != 0
+            })
+            .collect()
+    }
+
+    /// Copies the formatted current transaction to the system clipboard
+    fn copy_transaction(&mut self) {
+        let formatted =
+            format_transaction_with(&self.transactions[self.current_index], &self.config.format);
+        let message = match self.clipboard.set_text(formatted) {
+            Ok(()) => "Copied transaction to clipboard".to_string(),
+            Err(err) => format!("Couldn't access clipboard: {}", err),
+        };
+        self.set_status(message);
+    }
+
+    fn focused_field_is_single_line(&self) -> bool {
+        self.focused_field_type().is_single_line() || !self.config.multiline_narration
+    }
+
+    /// Inserts the clipboard content at the cursor of the focused field
+    fn paste_into_current_field(&mut self) {
+        match self.clipboard.get_text() {
+            Ok(text) => self.paste_text(text),
+            Err(err) => self.set_status(format!("Couldn't access clipboard: {}", err)),
+        }
+    }
+
+    /// Inserts `text` at the cursor of the focused field.
+    /// Line breaks are collapsed into spaces for single line fields.
+    fn paste_text(&mut self, text: String) {
+        let text = if !text.contains(['\n', '\r']) {
+            text
+        } else if self.focused_field_is_single_line() {
+            self.set_status("Collapsed line breaks in pasted text");
+            collapse_line_breaks(&text)
+        } else {
+            // some terminals paste line breaks as carriage returns
+            text.replace("\r\n", "\n").replace('\r', "\n")
+        };
+        if self.focused_field_type() == InputFieldType::Flag {
+            self.input_flag(text.trim());
+        } else if self.current_field_mut().insert_str(text) {
+            self.mark_dirty();
+            self.check_amount();
+        }
+    }
+
+    /// Replaces the transaction flag with `flag` if it is one of the valid flags
+    fn input_flag(&mut self, flag: &str) {
+        let valid_flags = &self.config.valid_flags;
+        if flag.chars().count() != 1 || !valid_flags.contains(flag) {
+            self.set_status(format!(
+                "'{}' is not a valid flag, use one of {}",
+                flag, valid_flags
+            ));
+            return;
+        }
+        let flag_textarea = &mut self.transactions[self.current_index].metadata_textareas_mut()[1];
+        if textarea_content(flag_textarea) != flag {
+            set_textarea_content(flag_textarea, flag);
+            self.mark_dirty();
+        }
+    }
+
+    /// The payee and the narration of the current transaction
+    fn payee_and_narration(&mut self) -> (String, String) {
+        let textareas = self.transactions[self.current_index].metadata_textareas();
+        (
+            textarea_content(&textareas[metafield_index(InputFieldType::Payee)]),
+            textarea_content(&textareas[metafield_index(InputFieldType::Narration)]),
+        )
+    }
+
+    fn set_payee_and_narration(&mut self, payee: &str, narration: &str) {
+        let textareas = self.transactions[self.current_index].metadata_textareas_mut();
+        set_textarea_content(
+            &mut textareas[metafield_index(InputFieldType::Payee)],
+            &collapse_line_breaks(payee),
+        );
+        set_textarea_content(
+            &mut textareas[metafield_index(InputFieldType::Narration)],
+            narration,
+        );
+        self.mark_dirty();
+    }
+
+    /// Swaps the payee and the narration of the current transaction
+    fn swap_payee_narration(&mut self) {
+        let (payee, narration) = self.payee_and_narration();
+        if payee.is_empty() && narration.is_empty() {
+            self.set_status("Nothing to swap, payee and narration are empty");
+            return;
+        }
+        self.set_payee_and_narration(&narration, &payee);
+    }
+
+    /// Moves the narration of the current transaction into the payee, bank imports often
+    /// put the payee there
+    fn narration_to_payee(&mut self) {
+        let (_, narration) = self.payee_and_narration();
+        if narration.is_empty() {
+            self.set_status("Nothing to move, the narration is empty");
+            return;
+        }
+        self.set_payee_and_narration(&narration, "");
+    }
+
+    /// Sets the flag of the current transaction to the next one of the configured flags
+    fn cycle_flag(&mut self) {
+        if self.focus_on_postings {
+            self.cycle_posting_flag();
+            return;
+        }
+        let flags = &self.config.flags;
+        let flag_textarea = &mut self.transactions[self.current_index].metadata_textareas_mut()[1];
+        let current_flag = textarea_content(flag_textarea);
+        let next_flag = match flags.iter().position(|f| *f == current_flag) {
+            Some(index) => &flags[(index + 1) % flags.len()],
+            None => &flags[0],
+        };
+        set_textarea_content(flag_textarea, next_flag);
+        self.mark_dirty();
+    }
+
+    /// Cycles the flag of the focused posting through no flag and the configured flags
+    fn cycle_posting_flag(&mut self) {
+        let flags: Vec<char> = self
+            .config
+            .flags
+            .iter()
+            .filter_map(|f| f.parse::<char>().ok())
+            .collect();
+        let posting = &mut self.transactions[self.current_index].postings_textareas_mut()
+            [self.currently_selected_posting];
+        posting.flag = match posting
+            .flag
+            .and_then(|f| flags.iter().position(|&c| c == f))
+        {
+            Some(index) => flags.get(index + 1).copied(),
+            None if posting.flag.is_none() => flags.first().copied(),
+            None => None,
+        };
+        self.mark_dirty();
+    }
+
+    /// Indexes of the postings of the current transaction whose account was not opened
+    pub fn unopened_postings(&self) -> Vec<usize> {
+        if !self.open_check {
+            return Vec::new();
+        }
+        unopened_postings(
+            &self.transactions[self.current_index],
+            &self.known_accounts,
+            self.config.allow_sub_accounts,
+        )
+    }
+
+    /// Postings of the current transaction whose account isn't open on its date, with
+    /// the reason
+    pub fn inactive_postings(&self) -> Vec<(usize, String)> {
+        if !self.open_check {
+            return Vec::new();
+        }
+        inactive_postings(&self.transactions[self.current_index], &self.account_dates)
+    }
+
+    /// The textarea that currently has the focus
+    pub fn current_field(&self) -> &TextArea<'t> {
+        let current_transaction = &self.transactions[self.current_index];
+        if self.focus_on_postings {
+            current_transaction.postings_textareas()[self.currently_selected_posting]
+                .get_field(&self.currently_selected_posting_field)
+        } else {
+            &current_transaction.metadata_textareas()[self.currently_selected_metadata_field]
+        }
+    }
+
+    /// Returns the textarea that currently has focus
+    fn current_field_mut(&mut self) -> &mut TextArea<'t> {
+        let current_transaction = &mut self.transactions[self.current_index];
+        if self.focus_on_postings {
+            current_transaction.postings_textareas_mut()[self.currently_selected_posting]
+                .get_field_mut(&self.currently_selected_posting_field)
+        } else {
+            &mut current_transaction.metadata_textareas_mut()
+                [self.currently_selected_metadata_field]
+        }
+    }
+
+    fn exit(&mut self) {
+        self.write_session();
+        self.exit = true;
+    }
+}
+
+/// What [`load_file`] read from the inputs
+struct LoadedInput<'t> {
+    transactions: Vec<TransactionTui<'t>>,
+    known_accounts: BTreeSet<String>,
+    account_dates: BTreeMap<String, AccountDates>,
+    import_errors: Vec<String>,     // CSV rows that couldn't be imported
+    uninterpreted: Vec<String>,     // `option` and `plugin` directives, kept but not understood
+    context: Vec<ContextDirective>, // `pad`, `balance` and `note` directives
+}
+
+/// Parses all transactions and opened accounts of the input files,
+/// together with the CSV rows that couldn't be imported
+fn load_file<'t>(
+    files: &[PathBuf],
+    csv_input: bool,
+    config: &Config,
+    progress: &(dyn Fn(usize) + Sync),
+) -> Result<LoadedInput<'t>> {
+    if csv_input {
+        let Some(csv_config) = &config.csv else {
+            bail!("--csv needs a [csv] section in the config file");
+        };
+        let import = import_csv(&files[0], csv_config, config.default_currency.as_deref())?;
+        let known_accounts = BTreeSet::from([csv_config.account.clone()]);
+        progress(import.transactions.len());
+        return Ok(LoadedInput {
+            transactions: import.transactions,
+            known_accounts,
+            account_dates: BTreeMap::new(),
+            import_errors: import.errors,
+            uninterpreted: Vec::new(),
+            context: Vec::new(),
+        });
+    }
+    let mut known_accounts = BTreeSet::new();
+    let mut account_dates = BTreeMap::new();
+    let mut transactions = Vec::new();
+    let mut uninterpreted = Vec::new();
+    let mut context = Vec::new();
+    let mut ledger_files = Vec::new();
+    let mut done = 0; // directives of the files before, the progress counts all of them
+    for file in files {
+        let parsed = parse_ledger_with_progress(file, &|n| progress(done + n)).map_err(|err| {
+            match extension_warning(file) {
+                Some(warning) => err.wrap_err(warning),
+                None => err,
+            }
+        })?;
+        done += parsed
+            .iter()
+            .map(|f| f.beancount.directives.len())
+            .sum::<usize>();
+        ledger_files.extend(parsed);
+    }
+    for ledger_file in ledger_files {
+        known_accounts.extend(open_accounts(&ledger_file.beancount));
+        collect_account_dates(&ledger_file.beancount, &mut account_dates);
+        uninterpreted.extend(uninterpreted_directives(&ledger_file));
+        context.extend(context_directives(&ledger_file));
+        let starts = line_starts(&ledger_file.content);
+        for directive in filter_transactions(ledger_file.beancount) {
+            let mut transaction = TransactionTui::try_from(directive)?;
+            transaction.set_source(&ledger_file.path, &ledger_file.content, &starts);
+            transactions.push(transaction);
+        }
+    }
+    Ok(LoadedInput {
+        transactions,
+        known_accounts,
+        account_dates,
+        import_errors: Vec::new(),
+        uninterpreted,
+        context,
+    })
+}
+
+/// A hash over the content of all `files`, a stored session only applies to the same inputs
+fn inputs_hash(files: &[PathBuf]) -> Result<u64> {
+    match files {
+        [file] => Ok(content_hash(&fs::read(file)?)),
+        files => {
+            let mut content = Vec::new();
+            for file in files {
+                content.extend(fs::read(file)?);
+                content.push(0);
+            }
+            Ok(content_hash(&content))
+        }
+    }
+}
+
+/// Names the first file and how many others there are
+fn describe_files(files: &[PathBuf]) -> String {
+    match files {
+        [] => String::new(),
+        [file] => file.display().to_string(),
+        [first, rest @ ..] => format!("{} and {} other files", first.display(), rest.len()),
+    }
+}
+
+fn modified_time(file: &Path) -> Option<SystemTime> {
+    fs::metadata(file).and_then(|m| m.modified()).ok()
+}
+
+fn invalid_amount_message(amount: &str) -> String {
+    if amount.contains(',') {
+        format!("Invalid amount '{}', use . as decimal separator", amount)
+    } else {
+        format!("Invalid amount '{}'", amount)
+    }
+}
+
+/// The non-empty `lines` of the comment editor, each starting with `;`
+fn comment_lines(lines: &[String]) -> Vec<String> {
+    lines
+        .iter()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            if line.starts_with(';') {
+                line.to_string()
+            } else {
+                format!("; {}", line)
+            }
+        })
+        .collect()
+}
+
+/// The field focused on startup, the narration if most transactions have no payee
+fn first_metadata_field(transactions: &[TransactionTui]) -> InputFieldType {
+    let without_payee = transactions
+        .iter()
+        .filter(|transaction| {
+            transaction
+                .directive
+                .payee
+                .as_deref()
+                .is_none_or(|payee| payee.trim().is_empty())
+        })
+        .count();
+    if without_payee * 2 > transactions.len() {
+        InputFieldType::Narration
+    } else {
+        InputFieldType::Payee
+    }
+}
+
+/// The index of the metadata `field` in [`METAFIELD_ORDER`], the order the fields
+/// are stored and shown in
+fn metafield_index(field: InputFieldType) -> usize {
+    METAFIELD_ORDER
+        .iter()
+        .position(|&f| f == field)
+        .expect("field is a metadata field")
+}
+
+/// Joins the non-empty lines of `text` with spaces
+fn collapse_line_breaks(text: &str) -> String {
+    text.split(['\n', '\r'])
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether the key only moves the cursor of a text area without editing it
+fn moves_cursor(key_event: KeyEvent) -> bool {
+    matches!(
+        key_event.code,
+        KeyCode::Left | KeyCode::Right | KeyCode::Up | KeyCode::Down | KeyCode::Home | KeyCode::End
+    )
+}
+
+/// Whether the text area would insert a line break for `input`
+fn inserts_newline(input: &Input) -> bool {
+    match input.key {
+        Key::Enter | Key::Char('\n' | '\r') => true,
+        Key::Char('m') => input.ctrl && !input.alt,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beancount::{format_transaction, FormatOptions};
+
+    fn test_app<'t>() -> App<'t> {
+        App::new(Args {
+            file: vec!["data/test.beancount".into()],
+            no_session: true,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn ctrl(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    /// A fresh directory for the files of one test, removed when dropped, also after a
+    /// failed assertion. `name` keeps tests running in parallel apart.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("beancount-tui-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn path(&self, file: &str) -> PathBuf {
+            self.0.join(file)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn accounts(app: &App) -> Vec<String> {
+        app.transactions[app.current_index]
+            .postings_textareas()
+            .iter()
+            .map(|p| textarea_content(&p.account_textarea))
+            .collect()
+    }
+
+    #[test]
+    fn insert_posting_below_and_above() {
+        let mut app = test_app();
+        // focus the first posting
+        app.handle_key_event(ctrl('j')).unwrap();
+        app.handle_key_event(ctrl('o')).unwrap();
+        assert_eq!(accounts(&app), ["Assets:Test", "", "Expenses:Test"]);
+        assert_eq!(app.currently_selected_posting, 1);
+        assert!(app.focus_on_postings);
+
+        app.handle_key_event(KeyEvent::new(
+            KeyCode::Char('O'),
+            KeyModifiers::CONTROL | KeyModifiers::SHIFT,
+        ))
+        .unwrap();
+        assert_eq!(accounts(&app), ["Assets:Test", "", "", "Expenses:Test"]);
+        assert_eq!(app.currently_selected_posting, 1);
+        assert!(app.transactions[0].dirty);
+    }
+
+    #[test]
+    fn accept_and_move_to_the_next_transaction() {
+        let mut app = test_app();
+        for index in 0..2 {
+            set_textarea_content(
+                &mut app.transactions[index].metadata_textareas_mut()[1],
+                "!",
+            );
+        }
+        let accept = KeyEvent::new(KeyCode::Enter, KeyModifiers::ALT);
+        app.handle_key_event(accept).unwrap();
+        assert_eq!(app.current_index, 1);
+        assert!(app.transactions[0].dirty);
+        assert_eq!(
+            textarea_content(&app.transactions[0].metadata_textareas()[1]),
+            "*"
+        );
+        // the `!` of the next transaction is its first issue
+        assert_eq!(
+            app.focused_transaction_field(),
+            TransactionField::Metadata(1)
+        );
+
+        // the last transaction only has a single posting and doesn't balance
+        app.current_index = 3;
+        app.handle_key_event(accept).unwrap();
+        assert_eq!(app.current_index, 3);
+        assert!(app.focus_on_postings);
+        assert!(app
+            .status_message
+            .unwrap()
+            .text
+            .starts_with("Not accepted: unbalanced by"));
+    }
+
+    #[test]
+    fn accept_remaining_skips_invalid_transactions() {
+        let mut app = test_app();
+        for index in 0..app.transactions.len() {
+            set_textarea_content(
+                &mut app.transactions[index].metadata_textareas_mut()[1],
+                "!",
+            );
+            app.mark_transaction_dirty(index);
+        }
+        // the last transaction only has a single posting and doesn't balance
+        app.current_index = 1;
+        app.handle_key_event(KeyEvent::new(KeyCode::Char('a'), KeyModifiers::ALT))
+            .unwrap();
+        assert_eq!(app.popup.on_confirm, PendingAction::AcceptRemaining);
+        app.handle_popup_key_event(KeyCode::Enter.into());
+        let flags: Vec<_> = app
+            .transactions
+            .iter()
+            .map(|t| textarea_content(&t.metadata_textareas()[1]))
+            .collect();
+        assert_eq!(flags, ["!", "*", "*", "!"]);
+        assert_eq!(
+            app.status_message.unwrap().text,
+            "Accepted 2 transactions, skipped 1 failing validation"
+        );
+        // continues to the exit confirmation
+        assert!(app.popup.active);
+        assert_eq!(app.popup.on_confirm, PendingAction::Exit);
+    }
+
+    #[test]
+    fn switching_transactions_keeps_focus() {
+        let mut app = test_app();
+        app.current_index = 2;
+        app.focus_on_postings = true;
+        app.currently_selected_posting = 2;
+        app.currently_selected_posting_field = PostingField::Amount;
+        app.handle_key_event(ctrl('n')).unwrap();
+        // the last transaction only has one posting
+        assert_eq!(app.current_index, 3);
+        assert!(app.focus_on_postings);
+        assert_eq!(app.currently_selected_posting, 0);
+        assert_eq!(app.currently_selected_posting_field, PostingField::Amount);
+
+        app.handle_key_event(ctrl('p')).unwrap();
+        assert_eq!(app.current_index, 2);
+        assert_eq!(app.currently_selected_posting, 0);
+        assert_eq!(app.currently_selected_posting_field, PostingField::Amount);
+    }
+
+    #[test]
+    fn jump_between_marks_wraps_around() {
+        let mut app = test_app();
+        let alt = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::ALT);
+        app.handle_key_event(alt('m')).unwrap();
+        app.current_index = 2;
+        app.handle_key_event(alt('m')).unwrap();
+        assert_eq!(app.marks, HashSet::from([0, 2]));
+
+        app.handle_key_event(alt(']')).unwrap();
+        assert_eq!(app.current_index, 0);
+        app.handle_key_event(alt('[')).unwrap();
+        assert_eq!(app.current_index, 2);
+
+        app.handle_key_event(alt('m')).unwrap();
+        app.handle_key_event(alt(']')).unwrap();
+        assert_eq!(app.current_index, 0);
+        app.handle_key_event(alt(']')).unwrap();
+        assert_eq!(app.current_index, 0);
+    }
+
+    #[test]
+    fn clear_field_can_be_undone() {
+        let mut app = test_app();
+        app.handle_key_event(ctrl('u')).unwrap();
+        assert_eq!(
+            textarea_content(&app.transactions[0].metadata_textareas()[2]),
+            ""
+        );
+        assert!(app.transactions[0].dirty);
+        app.handle_key_event(ctrl('z')).unwrap();
+        assert_eq!(
+            textarea_content(&app.transactions[0].metadata_textareas()[2]),
+            "Test Co"
+        );
+    }
+
+    #[test]
+    fn default_currency_fills_new_postings_and_amounts() {
+        let mut app = test_app();
+        app.config.default_currency = Some("EUR".to_string());
+        app.handle_key_event(ctrl('o')).unwrap();
+        let posting = &app.transactions[0].postings_textareas()[2];
+        assert_eq!(textarea_content(&posting.currency_textarea), "EUR");
+
+        // the currency is filled when leaving an amount field without currency
+        app.currently_selected_posting = 1;
+        app.handle_key_event(ctrl('l')).unwrap();
+        app.handle_key_event(KeyCode::Char('3').into()).unwrap();
+        app.handle_key_event(ctrl('l')).unwrap();
+        let posting = &app.transactions[0].postings_textareas()[1];
+        assert_eq!(textarea_content(&posting.amount_textarea), "3");
+        assert_eq!(textarea_content(&posting.currency_textarea), "EUR");
+
+        // an explicitly typed currency is kept
+        let posting = &app.transactions[0].postings_textareas()[0];
+        assert_eq!(textarea_content(&posting.currency_textarea), "USD");
+    }
+
+    #[test]
+    fn infer_the_default_currency_unless_given() {
+        let dir = TempDir::new("infer");
+        let config = dir.path("config.toml");
+        fs::write(&config, "infer_currency = true\n").unwrap();
+        let app = |currency: Option<&str>| {
+            App::new(Args {
+                file: vec!["data/test.beancount".into()],
+                config: Some(config.clone()),
+                currency: currency.map(str::to_string),
+                no_session: true,
+                ..Default::default()
+            })
+            .unwrap()
+        };
+        assert_eq!(app(None).config.default_currency.as_deref(), Some("USD"));
+        let app = app(Some("EUR"));
+        assert_eq!(app.config.default_currency.as_deref(), Some("EUR"));
+    }
+
+    #[test]
+    fn expand_ambiguous_account_opens_completion() {
+        let mut app = test_app();
+        app.config
+            .aliases
+            .insert("e".to_string(), "Expenses".to_string());
+        app.currently_selected_posting = 1;
+        app.focus_on_postings = true;
+        set_textarea_content(app.current_field_mut(), "e:t");
+        app.handle_key_event(ctrl('x')).unwrap();
+        assert!(app.completion.active);
+        assert_eq!(
+            app.completion.candidates,
+            ["Expenses:Test", "Expenses:Test1"]
+        );
+
+        app.handle_action(Action::Key(KeyCode::Down.into()))
+            .unwrap();
+        app.handle_action(Action::Key(KeyCode::Enter.into()))
+            .unwrap();
+        assert!(!app.completion.active);
+        assert_eq!(textarea_content(app.current_field_mut()), "Expenses:Test1");
+    }
+
+    #[test]
+    fn suggest_account_for_known_payee() {
+        let mut app = test_app();
+        app.config.suggest_accounts = true;
+        // "Test Co" was posted against Assets:Test and Expenses:Test most often
+        app.current_index = 3;
+        app.add_posting(1);
+        app.focus_on_postings = false;
+        app.handle_key_event(ctrl('l')).unwrap();
+        let posting = &app.transactions[3].postings_textareas()[1];
+        assert_eq!(textarea_content(&posting.account_textarea), "Expenses:Test");
+    }
+
+    #[test]
+    fn normalize_account_when_leaving_it() {
+        let mut app = test_app();
+        app.handle_key_event(ctrl('j')).unwrap();
+        set_textarea_content(app.current_field_mut(), "expenses:tes");
+        // typing keeps the spelling
+        app.handle_key_event(KeyEvent::from(KeyCode::Char('t')))
+            .unwrap();
+        assert_eq!(textarea_content(app.current_field_mut()), "expenses:test");
+        app.handle_key_event(ctrl('l')).unwrap();
+        assert_eq!(accounts(&app)[0], "Expenses:Test");
+
+        let mut app = test_app();
+        app.config.normalize_accounts = false;
+        app.handle_key_event(ctrl('j')).unwrap();
+        set_textarea_content(app.current_field_mut(), "expenses:test");
+        app.handle_key_event(ctrl('l')).unwrap();
+        assert_eq!(accounts(&app)[0], "expenses:test");
+    }
+
+    #[test]
+    fn next_issue_cycles_through_the_transactions() {
+        let mut app = test_app();
+        let transaction = &mut app.transactions[0];
+        set_textarea_content(&mut transaction.metadata_textareas_mut()[1], "!");
+        let postings = transaction.postings_textareas_mut();
+        set_textarea_content(&mut postings[0].currency_textarea, "");
+        set_textarea_content(&mut postings[1].account_textarea, "");
+        let focus = |app: &App| (app.current_index, app.focused_transaction_field());
+
+        // the flag comes before the focused payee
+        app.handle_key_event(ctrl('.')).unwrap();
+        assert_eq!(
+            focus(&app),
+            (0, TransactionField::Posting(0, PostingField::Currency))
+        );
+        app.handle_key_event(ctrl('.')).unwrap();
+        assert_eq!(
+            focus(&app),
+            (0, TransactionField::Posting(1, PostingField::Account))
+        );
+        // the last transaction has a single posting
+        app.handle_key_event(ctrl('.')).unwrap();
+        assert_eq!(
+            focus(&app),
+            (3, TransactionField::Posting(0, PostingField::Amount))
+        );
+        app.handle_key_event(ctrl('.')).unwrap();
+        assert_eq!(app.current_index, 3);
+        assert_eq!(app.status_message.unwrap().text, "No more issues");
+    }
+
+    #[test]
+    fn focus_issues_when_moving_to_a_transaction() {
+        let mut app = test_app();
+        app.config.focus_issues = true;
+        app.handle_key_event(ctrl('n')).unwrap();
+        // nothing to fix, the focus stays
+        assert_eq!(
+            app.focused_transaction_field(),
+            TransactionField::Metadata(2)
+        );
+        app.handle_key_event(KeyEvent::new(KeyCode::End, KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(
+            app.focused_transaction_field(),
+            TransactionField::Posting(0, PostingField::Amount)
+        );
+    }
+
+    #[test]
+    fn focus_stays_on_the_metadata_without_postings() {
+        let mut app = test_app();
+        app.transactions[0].postings_textareas_mut().clear();
+        for key in [
+            ctrl('j'),
+            ctrl('k'),
+            KeyEvent::new(KeyCode::Char('1'), KeyModifiers::ALT),
+        ] {
+            app.handle_key_event(key).unwrap();
+            assert!(!app.focus_on_postings);
+            assert_eq!(app.status_message.take().unwrap().text, NO_POSTINGS);
+        }
+        // Tab wraps around the metadata
+        for _ in 0..METAFIELD_ORDER.len() {
+            app.handle_key_event(KeyEvent::from(KeyCode::Tab)).unwrap();
+            assert!(!app.focus_on_postings);
+        }
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(100, 30)).unwrap();
+        app.draw(&mut terminal).unwrap();
+
+        app.handle_key_event(ctrl('o')).unwrap();
+        assert_eq!(accounts(&app), [""]);
+        assert!(app.focus_on_postings);
+    }
+
+    #[test]
+    fn failing_check_blocks_the_save() {
+        let dir = TempDir::new("check");
+        let output = dir.path("out.beancount");
+        let args = |force| Args {
+            file: vec!["data/test.beancount".into()],
+            output: Some(output.clone()),
+            check_cmd: Some("echo wrong >&2; false".to_string()),
+            force,
+            no_session: true,
+            ..Default::default()
+        };
+        let mut app = App::new(args(false)).unwrap();
+        assert!(!app.save());
+        assert!(!output.exists());
+        let failure = app.check_failure.as_ref().unwrap();
+        assert_eq!(failure.details, ["wrong"]);
+        app.handle_action(Action::Key(KeyCode::Esc.into())).unwrap();
+        assert!(app.check_failure.is_none());
+
+        let mut app = App::new(args(true)).unwrap();
+        assert!(app.save());
+        assert!(output.exists());
+    }
+
+    #[test]
+    fn output_keeps_the_context_directives() {
+        let dir = TempDir::new("context");
+        let output = dir.path("out.beancount");
+        let mut app = App::new(Args {
+            file: vec!["data/context.beancount".into()],
+            output: Some(output.clone()),
+            no_session: true,
+            ..Default::default()
+        })
+        .unwrap();
+        app.handle_key_event(KeyEvent::new(KeyCode::Char('x'), KeyModifiers::ALT))
+            .unwrap();
+        assert_eq!(app.shown_context().len(), 2);
+        app.transactions.reverse();
+        assert_eq!(app.shown_context().len(), 1);
+
+        let written_order = |app: &mut App| {
+            assert!(app.save());
+            let written = fs::read_to_string(&output).unwrap();
+            assert!(written.contains("\n  source: \"phone\"\n"));
+            written
+                .lines()
+                .filter(|line| line.starts_with("20"))
+                .map(|line| line.split_whitespace().nth(1).unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        // each directive stays right before the transaction read after it
+        assert_eq!(
+            written_order(&mut app),
+            ["note", "*", "pad", "balance", "*", "balance"]
+        );
+        app.delete_transaction(1);
+        assert_eq!(
+            written_order(&mut app),
+            ["pad", "balance", "note", "*", "balance"]
+        );
+    }
+
+    #[test]
+    fn stale_session_is_not_offered() {
+        let dir = TempDir::new("stale");
+        let path = dir.path("session.json");
+        let mut app = test_app();
+        app.session_path = Some(path.clone());
+        app.current_index = 2;
+        app.write_session();
+
+        let mut changed = test_app();
+        // as if the file now had one transaction less
+        changed.transactions.pop();
+        changed.n_parsed -= 1;
+        changed.session_path = Some(path.clone());
+        changed.offer_session();
+        assert!(!changed.popup.active);
+        assert_eq!(changed.current_index, 0);
+        assert_eq!(
+            changed.status_message.unwrap().text,
+            "The file changed since the last session, starting fresh"
+        );
+    }
+
+    #[test]
+    fn insert_posting_from_metadata_appends() {
+        let mut app = test_app();
+        app.handle_key_event(ctrl('o')).unwrap();
+        assert_eq!(accounts(&app), ["Assets:Test", "Expenses:Test", ""]);
+        assert_eq!(app.currently_selected_posting, 2);
+    }
+
+    #[test]
+    fn resume_session_restores_progress() {
+        let dir = TempDir::new("resume");
+        let path = dir.path("session.json");
+        let mut app = test_app();
+        app.session_path = Some(path.clone());
+        app.current_index = 2;
+        app.marks.insert(1);
+        app.handle_key_event(KeyCode::Char('x').into()).unwrap();
+        app.write_session();
+
+        let mut resumed = test_app();
+        resumed.session_path = Some(path.clone());
+        resumed.offer_session();
+        assert!(resumed.popup.active);
+        resumed
+            .handle_action(Action::Key(KeyCode::Enter.into()))
+            .unwrap();
+        assert_eq!(resumed.current_index, 2);
+        assert!(resumed.marks.contains(&1));
+        assert_eq!(
+            textarea_content(&resumed.transactions[2].metadata_textareas()[0]),
+            textarea_content(&app.transactions[2].metadata_textareas()[0])
+        );
+        assert!(resumed.unsaved_changes);
+    }
+
+    #[test]
+    fn session_survives_deleting_a_transaction() {
+        let dir = TempDir::new("resume-delete");
+        let path = dir.path("session.json");
+        let mut app = test_app();
+        app.session_path = Some(path.clone());
+        app.current_index = 1;
+        let alt_shift_d =
+            KeyEvent::new(KeyCode::Char('D'), KeyModifiers::ALT | KeyModifiers::SHIFT);
+        app.handle_key_event(alt_shift_d).unwrap();
+        app.handle_action(Action::Key(KeyCode::Enter.into()))
+            .unwrap();
+        assert_eq!(app.transactions.len(), 3);
+        app.handle_key_event(KeyCode::Char('x').into()).unwrap();
+        app.write_session();
+
+        let mut resumed = test_app();
+        resumed.session_path = Some(path.clone());
+        resumed.offer_session();
+        assert!(resumed.popup.active);
+        resumed
+            .handle_action(Action::Key(KeyCode::Enter.into()))
+            .unwrap();
+        assert_eq!(resumed.current_index, 1);
+        assert!(resumed.unsaved_changes);
+    }
+
+    #[test]
+    fn resumed_edits_find_their_file() {
+        // both files have a transaction on line 1
+        let args = || Args {
+            file: vec![
+                "data/flags.beancount".into(),
+                "data/metadata.beancount".into(),
+            ],
+            no_session: true,
+            ..Default::default()
+        };
+        let mut app = App::new(args()).unwrap();
+        let index = app
+            .transactions
+            .iter()
+            .position(|t| t.source.ends_with("metadata.beancount"))
+            .unwrap();
+        set_textarea_content(
+            &mut app.transactions[index].metadata_textareas_mut()[3],
+            "Lunch",
+        );
+        let mut resumed = App::new(args()).unwrap();
+        resumed.pending_session = Some(Session {
+            edited: vec![EditedTransaction::from_transaction(
+                index,
+                &app.transactions[index],
+            )],
+            ..Session::default()
+        });
+        resumed.resume_session();
+        let narrations: Vec<String> = resumed
+            .transactions
+            .iter()
+            .filter(|t| t.line_number == 1)
+            .map(|t| textarea_content(&t.metadata_textareas()[3]))
+            .collect();
+        assert_eq!(narrations, ["Bread", "Lunch"]);
+    }
+
+    #[test]
+    fn csv_input_summarizes_skipped_rows() {
+        let app = App::new(Args {
+            csv: Some("data/test.csv".into()),
+            config: Some("data/test-csv.toml".into()),
+            no_session: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(app.transactions.len(), 2);
+        assert_eq!(accounts(&app), ["Assets:Checking", ""]);
+        assert!(app.known_accounts.contains("Assets:Checking"));
+        assert_eq!(app.popup.on_confirm, PendingAction::Dismiss);
+        assert!(app.popup.prompt.starts_with("Skipped 2 rows"));
+    }
+
+    #[test]
+    fn cycle_posting_flag() {
+        let mut app = test_app();
+        app.handle_key_event(ctrl('j')).unwrap();
+        let flag = |app: &App| app.transactions[0].postings_textareas()[0].flag;
+        assert_eq!(flag(&app), None);
+        app.handle_key_event(ctrl('f')).unwrap();
+        assert_eq!(flag(&app), Some('*'));
+        app.handle_key_event(ctrl('f')).unwrap();
+        assert_eq!(flag(&app), Some('!'));
+        app.handle_key_event(ctrl('f')).unwrap();
+        assert_eq!(flag(&app), None);
+        // the transaction flag is untouched
+        assert_eq!(
+            textarea_content(&app.transactions[0].metadata_textareas()[1]),
+            "*"
+        );
+        assert!(app.unsaved_changes);
+    }
+
+    #[test]
+    fn jump_to_first_last_and_by_page() {
+        let mut app = test_app();
+        app.config.page_size = 2;
+        let key = |code, modifiers| KeyEvent::new(code, modifiers);
+        app.handle_key_event(key(KeyCode::PageDown, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.current_index, 2);
+        app.handle_key_event(key(KeyCode::PageDown, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.current_index, 3);
+        app.handle_key_event(key(KeyCode::PageUp, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.current_index, 1);
+        app.handle_key_event(key(KeyCode::End, KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(app.current_index, 3);
+        app.handle_key_event(key(KeyCode::Home, KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(app.current_index, 0);
+    }
+
+    #[test]
+    fn jump_to_posting_with_alt_number() {
+        let mut app = test_app();
+        let alt = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::ALT);
+        app.handle_key_event(alt('2')).unwrap();
+        assert!(app.focus_on_postings);
+        assert_eq!(app.currently_selected_posting, 1);
+        assert_eq!(app.focused_field_type(), InputFieldType::Account);
+        assert!(app.status_message.is_none());
+        app.handle_key_event(alt('9')).unwrap();
+        assert_eq!(app.currently_selected_posting, 1);
+        assert_eq!(
+            app.status_message.as_ref().unwrap().text,
+            "No posting 9, the transaction has 2"
+        );
+        app.handle_key_event(alt('0')).unwrap();
+        assert!(!app.focus_on_postings);
+        assert_eq!(app.focused_field_type(), InputFieldType::Payee);
+    }
+
+    #[test]
+    fn tab_walks_all_fields_in_order() {
+        use InputFieldType::*;
+        let mut app = test_app();
+        let focus = |app: &App| {
+            let posting = app
+                .focus_on_postings
+                .then_some(app.currently_selected_posting);
+            (app.focused_field_type(), posting)
+        };
+        let order = [
+            (Date, None),
+            (Flag, None),
+            (Payee, None),
+            (Narration, None),
+            (Comment, None),
+            (Account, Some(0)),
+            (Amount, Some(0)),
+            (Currency, Some(0)),
+            (Account, Some(1)),
+            (Amount, Some(1)),
+            (Currency, Some(1)),
+        ];
+        // starts on the payee and wraps around back to it
+        let mut expected = order.to_vec();
+        expected.rotate_left(2);
+        expected.push((Payee, None));
+        let mut visited = vec![focus(&app)];
+        for _ in 0..order.len() {
+            app.handle_key_event(KeyCode::Tab.into()).unwrap();
+            visited.push(focus(&app));
+        }
+        assert_eq!(visited, expected);
+
+        let back_tab = KeyEvent::new(KeyCode::BackTab, KeyModifiers::SHIFT);
+        let mut visited = vec![focus(&app)];
+        for _ in 0..order.len() {
+            app.handle_key_event(back_tab).unwrap();
+            visited.push(focus(&app));
+        }
+        expected.reverse();
+        assert_eq!(visited, expected);
+    }
+
+    #[test]
+    fn tab_follows_the_configured_metadata_order() {
+        use InputFieldType::*;
+        let mut app = test_app();
+        app.config.metadata_order = vec![Payee, Narration, Date, Flag, Comment];
+        let mut visited = vec![app.focused_field_type()];
+        for _ in 0..5 {
+            app.handle_key_event(KeyCode::Tab.into()).unwrap();
+            visited.push(app.focused_field_type());
+        }
+        assert_eq!(visited, [Payee, Narration, Date, Flag, Comment, Account]);
+
+        // the fields keep their place on screen
+        assert_eq!(
+            METAFIELD_ORDER[app.currently_selected_metadata_field],
+            Comment
+        );
+        app.focus_on_postings = false;
+        app.handle_key_event(KeyEvent::new(KeyCode::Char('l'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(app.focused_field_type(), Payee);
+    }
+
+    #[test]
+    fn payee_and_narration_can_be_swapped_and_moved() {
+        let mut app = test_app();
+        let alt = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::ALT);
+        // works from any field
+        app.handle_key_event(ctrl('j')).unwrap();
+        app.handle_key_event(alt('s')).unwrap();
+        assert_eq!(
+            app.payee_and_narration(),
+            ("Foo Bar".to_string(), "Test Co".to_string())
+        );
+        assert!(app.transactions[0].dirty);
+
+        app.handle_key_event(alt('n')).unwrap();
+        assert_eq!(
+            app.payee_and_narration(),
+            ("Test Co".to_string(), String::new())
+        );
+
+        // nothing left to move
+        app.handle_key_event(alt('n')).unwrap();
+        assert_eq!(
+            app.status_message.as_ref().unwrap().text,
+            "Nothing to move, the narration is empty"
+        );
+        app.set_payee_and_narration("", "");
+        app.status_message = None;
+        app.handle_key_event(alt('s')).unwrap();
+        assert_eq!(
+            app.status_message.as_ref().unwrap().text,
+            "Nothing to swap, payee and narration are empty"
+        );
+    }
+
+    #[test]
+    fn clean_transaction_only_marks_changes() {
+        let mut app = test_app();
+        let alt_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::ALT);
+        app.handle_key_event(alt_c).unwrap();
+        assert!(!app.transactions[0].dirty);
+        assert_eq!(
+            app.status_message.as_ref().unwrap().text,
+            "Nothing to clean up"
+        );
+
+        app.set_payee_and_narration("  AMAZON  EU S.A.R.L  ", "Foo Bar\n");
+        set_textarea_content(
+            &mut app.transactions[0].postings_textareas_mut()[0].currency_textarea,
+            "usd",
+        );
+        app.transactions[0].dirty = false;
+        app.handle_key_event(alt_c).unwrap();
+        assert!(app.transactions[0].dirty);
+        assert_eq!(
+            app.payee_and_narration(),
+            ("AMAZON EU S.A.R.L".to_string(), "Foo Bar".to_string())
+        );
+        assert_eq!(
+            textarea_content(&app.transactions[0].postings_textareas()[0].currency_textarea),
+            "USD"
+        );
+    }
+
+    #[test]
+    fn amount_signs_can_be_flipped() {
+        let mut app = test_app();
+        let amounts = |app: &App| -> Vec<String> {
+            app.transactions[app.current_index]
+                .postings_textareas()
+                .iter()
+                .map(|p| textarea_content(&p.amount_textarea))
+                .collect()
+        };
+        app.handle_key_event(ctrl('j')).unwrap();
+        app.handle_key_event(KeyCode::Tab.into()).unwrap();
+        assert_eq!(app.focused_field_type(), InputFieldType::Amount);
+        let alt_minus = KeyEvent::new(KeyCode::Char('-'), KeyModifiers::ALT);
+        app.handle_key_event(alt_minus).unwrap();
+        assert_eq!(amounts(&app), ["-5", ""]);
+        assert!(app.transactions[0].dirty);
+
+        // the second posting's amount is inferred
+        app.handle_key_event(ctrl('j')).unwrap();
+        app.handle_key_event(alt_minus).unwrap();
+        assert_eq!(
+            app.status_message.as_ref().unwrap().text,
+            "No amount to flip"
+        );
+
+        app.current_index = 1;
+        app.handle_key_event(KeyEvent::new(
+            KeyCode::Char('_'),
+            KeyModifiers::ALT | KeyModifiers::SHIFT,
+        ))
+        .unwrap();
+        assert_eq!(amounts(&app), ["-5", "5"]);
+        assert!(app.transactions[1].dirty);
+    }
+
+    #[test]
+    fn negate_account_amounts_in_all_transactions() {
+        let mut app = test_app();
+        let amount = |app: &App, transaction: usize, posting: usize| {
+            textarea_content(
+                &app.transactions[transaction].postings_textareas()[posting].amount_textarea,
+            )
+        };
+        app.execute_command(":negate expenses:test").unwrap();
+        assert_eq!(amount(&app, 1, 1), "5");
+        assert_eq!(amount(&app, 2, 1), "-2");
+        assert_eq!(amount(&app, 2, 0), "-5");
+        assert!(app.transactions[1].dirty && app.transactions[2].dirty);
+        assert!(!app.transactions[0].dirty);
+        assert_eq!(
+            app.status_message.as_ref().unwrap().text,
+            "Negated 2 postings in 2 transactions, skipped 2 with an empty or invalid amount"
+        );
+
+        // one undo reverts the whole batch
+        app.handle_key_event(ctrl('z')).unwrap();
+        assert_eq!(amount(&app, 1, 1), "-5");
+        assert_eq!(amount(&app, 2, 1), "2");
+        assert!(!app.transactions[1].dirty && !app.transactions[2].dirty);
+        assert!(!app.unsaved_changes);
+
+        // an edit after the batch ends the chance to undo it
+        app.execute_command(":negate assets").unwrap();
+        app.handle_key_event(KeyCode::Char('x').into()).unwrap();
+        app.handle_key_event(ctrl('z')).unwrap();
+        assert_eq!(amount(&app, 0, 0), "-5");
+    }
+
+    #[test]
+    fn flag_all_transactions_after_confirming() {
+        let mut app = test_app();
+        let flags = |app: &App| {
+            app.transactions
+                .iter()
+                .map(|t| textarea_content(&t.metadata_textareas()[1]))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            app.execute_command(":flagall x").unwrap_err().to_string(),
+            "'x' is not a valid flag, use one of *!PSTCURM"
+        );
+        assert!(!app.popup.active);
+
+        app.execute_command(":flagall !").unwrap();
+        assert_eq!(app.popup.on_confirm, PendingAction::FlagAll('!'));
+        assert_eq!(flags(&app), ["*"; 4]);
+        app.handle_action(Action::Key(KeyCode::Enter.into()))
+            .unwrap();
+        assert_eq!(flags(&app), ["!"; 4]);
+        assert!(app.transactions.iter().all(|t| t.dirty));
+        assert_eq!(
+            app.status_message.as_ref().unwrap().text,
+            "Set the flag of 4 transactions to '!'"
+        );
+    }
+
+    #[test]
+    fn read_only_mode_ignores_edits() {
+        let mut app = App::new(Args {
+            file: vec!["data/test.beancount".into()],
+            no_session: true,
+            readonly: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let before = format_transactions(&app.transactions, &FormatOptions::default());
+        app.focus_on_postings = true;
+        for key_event in [
+            KeyCode::Char('x').into(),
+            KeyCode::Backspace.into(),
+            ctrl('o'),
+            KeyEvent::new(KeyCode::Char('D'), KeyModifiers::ALT),
+        ] {
+            app.handle_key_event(key_event).unwrap();
+        }
+        app.handle_action(Action::Paste("pasted".to_string()))
+            .unwrap();
+        assert_eq!(
+            app.status_message.as_ref().unwrap().text,
+            "Read-only mode, editing is disabled"
+        );
+        assert_eq!(
+            app.execute_command("sort").unwrap_err().to_string(),
+            "Read-only mode, editing is disabled"
+        );
+        assert_eq!(
+            format_transactions(&app.transactions, &FormatOptions::default()),
+            before
+        );
+        assert!(!app.unsaved_changes && !app.popup.active);
+
+        // moving around still works
+        app.handle_key_event(KeyCode::End.into()).unwrap();
+        assert_eq!(app.current_field_mut().cursor(), (0, 11));
+        app.handle_key_event(ctrl('n')).unwrap();
+        assert_eq!(app.current_index, 1);
+        app.execute_command("goto 3").unwrap();
+        assert_eq!(app.current_index, 2);
+        app.handle_key_event(ctrl('q')).unwrap();
+        assert!(app.exit);
+    }
+
+    #[test]
+    fn revert_field_to_the_parsed_value() {
+        let mut app = test_app();
+        let alt_r = KeyEvent::new(KeyCode::Char('r'), KeyModifiers::ALT);
+        app.focus_on_postings = true;
+        app.currently_selected_posting_field = PostingField::Amount;
+        app.handle_key_event(KeyCode::Char('0').into()).unwrap();
+        assert!(app.transactions[0].changed());
+
+        // the other transaction fields are untouched
+        app.focus_on_postings = false;
+        app.handle_key_event(alt_r).unwrap();
+        assert_eq!(
+            app.status_message.as_ref().unwrap().text,
+            "The field wasn't changed"
+        );
+
+        app.handle_key_event(ctrl('q')).unwrap();
+        assert!(app.popup.active && !app.exit);
+        app.handle_action(Action::Key(KeyCode::Esc.into())).unwrap();
+
+        app.focus_on_postings = true;
+        app.handle_key_event(alt_r).unwrap();
+        assert!(!app.transactions[0].changed());
+        assert!(app.unsaved_changes);
+        // nothing differs from the file anymore
+        app.handle_key_event(ctrl('q')).unwrap();
+        assert!(app.exit);
+    }
+
+    #[test]
+    fn split_posting_halves_the_amount() {
+        let mut app = test_app();
+        app.current_index = 2;
+        app.focus_on_postings = true;
+        app.currently_selected_posting = 1;
+        app.currently_selected_posting_field = PostingField::Amount;
+        app.handle_key_event(KeyEvent::new(KeyCode::Char('/'), KeyModifiers::ALT))
+            .unwrap();
+        let postings = app.transactions[2].postings_textareas();
+        let amounts: Vec<_> = postings
+            .iter()
+            .map(|p| textarea_content(&p.amount_textarea))
+            .collect();
+        assert_eq!(amounts, ["-5", "1", "1", ""]);
+        assert_eq!(textarea_content(&postings[2].currency_textarea), "USD");
+        assert_eq!(textarea_content(&postings[2].account_textarea), "");
+        assert_eq!(app.currently_selected_posting, 2);
+        assert_eq!(app.currently_selected_posting_field, PostingField::Account);
+        assert!(app.transactions[2].dirty);
+    }
+
+    #[test]
+    fn balance_the_focused_posting() {
+        let mut app = test_app();
+        let alt_b = KeyEvent::new(KeyCode::Char('b'), KeyModifiers::ALT);
+        app.current_index = 2;
+        app.focus_on_postings = true;
+        app.currently_selected_posting = 1;
+        app.currently_selected_posting_field = PostingField::Amount;
+        app.handle_key_event(alt_b).unwrap();
+        let amount = |app: &App, posting: usize| {
+            textarea_content(&app.transactions[2].postings_textareas()[posting].amount_textarea)
+        };
+        assert_eq!(amount(&app, 1), "5");
+        assert_eq!(
+            app.status_message.as_ref().unwrap().text,
+            "Balanced USD with this posting"
+        );
+
+        app.handle_key_event(alt_b).unwrap();
+        assert_eq!(
+            app.status_message.as_ref().unwrap().text,
+            "USD is already balanced"
+        );
+    }
+
+    #[test]
+    fn account_browser_inserts_the_selected_account() {
+        let mut app = test_app();
+        app.focus_on_postings = true;
+        app.currently_selected_posting = 1;
+        app.handle_key_event(KeyEvent::new(KeyCode::Char('t'), KeyModifiers::ALT))
+            .unwrap();
+        assert_eq!(
+            app.account_browser.as_ref().unwrap().selected_account(),
+            Some("Expenses:Test".to_string())
+        );
+        for c in ['j', 'h', 'k'] {
+            app.handle_action(Action::Key(KeyCode::Char(c).into()))
+                .unwrap();
+        }
+        assert!(!app.transactions[0].dirty);
+        app.handle_action(Action::Key(KeyCode::Enter.into()))
+            .unwrap();
+        assert!(app.account_browser.is_none());
+        assert_eq!(accounts(&app), ["Assets:Test", "Assets"]);
+        assert!(app.transactions[0].dirty);
+    }
+
+    #[test]
+    fn calendar_picks_a_date() {
+        let mut app = test_app();
+        app.currently_selected_metadata_field = 0;
+        app.handle_key_event(ctrl('d')).unwrap();
+        assert_eq!(
+            app.calendar.as_ref().unwrap().selected,
+            parse_date("2023-10-01").unwrap()
+        );
+        for code in [KeyCode::Right, KeyCode::Down, KeyCode::PageDown] {
+            app.handle_action(Action::Key(code.into())).unwrap();
+        }
+        assert!(!app.transactions[0].dirty);
+        app.handle_action(Action::Key(KeyCode::Enter.into()))
+            .unwrap();
+        assert!(app.calendar.is_none());
+        assert_eq!(
+            textarea_content(&app.transactions[0].metadata_textareas()[0]),
+            "2023-11-09"
+        );
+        assert!(app.transactions[0].dirty);
+
+        // an invalid date starts at today, Esc keeps the field as it is
+        set_textarea_content(&mut app.transactions[0].metadata_textareas_mut()[0], "soon");
+        app.handle_key_event(ctrl('d')).unwrap();
+        assert_eq!(app.calendar.as_ref().unwrap().selected, today());
+        app.handle_action(Action::Key(KeyCode::Esc.into())).unwrap();
+        assert!(app.calendar.is_none());
+        assert_eq!(
+            textarea_content(&app.transactions[0].metadata_textareas()[0]),
+            "soon"
+        );
+    }
+
+    #[test]
+    fn arrows_shift_the_date() {
+        let mut app = test_app();
+        let date = |app: &App| textarea_content(&app.transactions[0].metadata_textareas()[0]);
+        app.currently_selected_metadata_field = 0;
+        app.handle_key_event(KeyCode::Up.into()).unwrap();
+        app.handle_key_event(KeyCode::Up.into()).unwrap();
+        assert_eq!(date(&app), "2023-10-03");
+        app.handle_key_event(KeyCode::Down.into()).unwrap();
+        assert_eq!(date(&app), "2023-10-02");
+
+        set_textarea_content(
+            &mut app.transactions[0].metadata_textareas_mut()[0],
+            "2023-13-01",
+        );
+        app.handle_key_event(KeyCode::Up.into()).unwrap();
+        assert_eq!(date(&app), format_naive_date(&today()));
+        assert_eq!(
+            app.status_message.as_ref().unwrap().text,
+            "Invalid date '2023-13-01', reset to today"
+        );
+    }
+
+    #[test]
+    fn enter_moves_to_the_next_field() {
+        let mut app = test_app();
+        assert_eq!(app.focused_field_type(), InputFieldType::Payee);
+        app.handle_key_event(KeyCode::Enter.into()).unwrap();
+        assert_eq!(app.focused_field_type(), InputFieldType::Narration);
+        // other ways to type a line break are ignored in single line fields
+        let ctrl_m = KeyEvent::new(KeyCode::Char('m'), KeyModifiers::CONTROL);
+        let shift_enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::SHIFT);
+        app.handle_key_event(ctrl_m).unwrap();
+        app.handle_key_event(shift_enter).unwrap();
+        assert_eq!(app.current_field_mut().lines().len(), 1);
+        assert!(!app.unsaved_changes);
+
+        app.config.multiline_narration = true;
+        app.handle_key_event(KeyCode::Enter.into()).unwrap();
+        assert_eq!(app.focused_field_type(), InputFieldType::Narration);
+        assert_eq!(app.current_field_mut().lines().len(), 2);
+        app.handle_key_event(KeyCode::Tab.into()).unwrap();
+        app.handle_key_event(KeyCode::Enter.into()).unwrap();
+        assert!(app.focus_on_postings);
+        assert_eq!(app.focused_field_type(), InputFieldType::Account);
+    }
+
+    #[test]
+    fn pasted_line_breaks_stay_out_of_single_line_fields() {
+        let mut app = test_app();
+        app.jump_to_posting(1);
+        app.run_command(Command::NextField).unwrap();
+        assert_eq!(app.focused_field_type(), InputFieldType::Amount);
+        let amount = |app: &mut App| app.current_field_mut().lines().to_vec();
+        set_textarea_content(app.current_field_mut(), "");
+        app.handle_action(Action::Paste("12.50\r\n\n".to_string()))
+            .unwrap();
+        assert_eq!(amount(&mut app), ["12.50"]);
+        assert_eq!(
+            app.status_message.as_ref().unwrap().text,
+            "Collapsed line breaks in pasted text"
+        );
+        // typed line breaks are ignored as well
+        app.handle_key_event(KeyEvent::new(KeyCode::Char('m'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(amount(&mut app), ["12.50"]);
+
+        app.config.multiline_narration = true;
+        app.jump_to_posting(0);
+        app.run_command(Command::NextField).unwrap();
+        app.handle_action(Action::Paste("a\rb".to_string()))
+            .unwrap();
+        assert_eq!(app.current_field_mut().lines().len(), 2);
+    }
+
+    #[test]
+    fn invalid_amounts_are_reported_and_block_saving() {
+        let dir = TempDir::new("amounts");
+        let output = dir.path("out.beancount");
+        let mut app = test_app();
+        app.output = Some(output.clone());
+        app.jump_to_posting(1);
+        app.run_command(Command::NextField).unwrap();
+        set_textarea_content(app.current_field_mut(), "12");
+        for c in ",34".chars() {
+            app.handle_key_event(KeyCode::Char(c).into()).unwrap();
+        }
+        assert_eq!(
+            app.status_message.as_ref().unwrap().text,
+            "Invalid amount '12,34', use . as decimal separator"
+        );
+
+        app.next_transaction().unwrap();
+        assert!(!app.save());
+        assert!(!output.exists());
+        assert_eq!(app.current_index, 0);
+        assert_eq!(app.focused_field_type(), InputFieldType::Amount);
+        assert_eq!(app.currently_selected_posting, 0);
+        assert!(app
+            .status_message
+            .as_ref()
+            .unwrap()
+            .text
+            .starts_with("Not saved"));
+
+        // fixing the amount clears the message and allows saving
+        for key in [KeyCode::Backspace, KeyCode::Backspace, KeyCode::Backspace] {
+            app.handle_key_event(key.into()).unwrap();
+        }
+        for c in ".34".chars() {
+            app.handle_key_event(KeyCode::Char(c).into()).unwrap();
+        }
+        assert!(app.status_message.is_none());
+        assert!(app.save());
+        assert!(fs::read_to_string(&output).unwrap().contains("12.34"));
+    }
+
+    #[test]
+    fn delete_posting_keeps_one() {
+        let mut app = test_app();
+        app.handle_key_event(ctrl('j')).unwrap();
+        let alt_d = KeyEvent::new(KeyCode::Char('d'), KeyModifiers::ALT);
+        app.handle_key_event(alt_d).unwrap();
+        assert_eq!(app.popup.on_confirm, PendingAction::DeletePosting(0, 0));
+        assert!(!app.unsaved_changes);
+        app.handle_action(Action::Key(KeyCode::Enter.into()))
+            .unwrap();
+        assert!(!app.popup.active);
+        assert_eq!(accounts(&app), ["Expenses:Test"]);
+        app.handle_key_event(alt_d).unwrap();
+        assert!(!app.popup.active);
+        assert_eq!(accounts(&app), ["Expenses:Test"]);
+        assert!(app.unsaved_changes);
+    }
+
+    #[test]
+    fn rejected_transactions_are_written_apart() {
+        let dir = TempDir::new("rejected");
+        let output = dir.path("out.beancount");
+        let rejected = dir.path("rejected.beancount");
+        let mut app = App::new(Args {
+            file: vec!["data/test.beancount".into()],
+            output: Some(output.clone()),
+            rejected: Some(rejected.clone()),
+            no_session: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let narrations = |app: &App| -> Vec<String> {
+            app.transactions
+                .iter()
+                .map(|t| textarea_content(&t.metadata_textareas()[3]))
+                .collect()
+        };
+        let before = narrations(&app);
+        let reject = KeyEvent::new(KeyCode::Char('j'), KeyModifiers::ALT);
+        app.current_index = 1;
+        app.handle_key_event(reject).unwrap();
+        assert_eq!(app.transactions.len(), 3);
+        app.handle_key_event(ctrl('z')).unwrap();
+        assert_eq!(narrations(&app), before);
+        assert_eq!(app.current_index, 1);
+
+        app.handle_key_event(reject).unwrap();
+        app.current_index = 0;
+        app.handle_key_event(reject).unwrap();
+        assert_eq!(app.rejected.len(), 2);
+        assert!(app.save());
+        let written = fs::read_to_string(&output).unwrap();
+        let written_rejected = fs::read_to_string(&rejected).unwrap();
+        for narration in &before[..2] {
+            let narration = format!("\"{}\"\n", narration);
+            assert!(!written.contains(&narration) && written_rejected.contains(&narration));
+        }
+
+        // the list restores the selected one, the most recent rejection is selected first
+        app.handle_key_event(KeyEvent::new(KeyCode::Char('J'), KeyModifiers::ALT))
+            .unwrap();
+        assert_eq!(app.rejected_list, Some(1));
+        app.handle_action(Action::Key(KeyCode::Char('k').into()))
+            .unwrap();
+        app.handle_action(Action::Key(KeyCode::Enter.into()))
+            .unwrap();
+        assert_eq!(app.rejected_list, Some(0));
+        assert_eq!(app.rejected.len(), 1);
+        assert_eq!(
+            textarea_content(&app.transactions[app.current_index].metadata_textareas()[3]),
+            before[1]
+        );
+    }
+
+    #[test]
+    fn no_rejecting_when_saving_in_place() {
+        let mut app = App::new(Args {
+            file: vec!["data/test.beancount".into()],
+            in_place: true,
+            no_session: true,
+            ..Default::default()
+        })
+        .unwrap();
+        app.handle_key_event(KeyEvent::new(KeyCode::Char('j'), KeyModifiers::ALT))
+            .unwrap();
+        assert_eq!(app.transactions.len(), 4);
+        assert!(app.rejected.is_empty());
+        assert_eq!(
+            app.status_message.unwrap().text,
+            "Can't reject transactions when saving in place"
+        );
+    }
+
+    #[test]
+    fn delete_transaction_remaps_marks() {
+        let mut app = test_app();
+        app.marks = HashSet::from([0, 1, 3]);
+        app.current_index = 1;
+        let alt_shift_d =
+            KeyEvent::new(KeyCode::Char('D'), KeyModifiers::ALT | KeyModifiers::SHIFT);
+        app.handle_key_event(alt_shift_d).unwrap();
+        app.handle_action(Action::Key(KeyCode::Enter.into()))
+            .unwrap();
+        assert_eq!(app.transactions.len(), 3);
+        assert_eq!(app.marks, HashSet::from([0, 2]));
+        assert_eq!(app.current_index, 1);
+        assert!(app.unsaved_changes);
+    }
+
+    #[test]
+    fn popup_esc_changes_nothing() {
+        for action in [
+            PendingAction::Exit,
+            PendingAction::AcceptRemaining,
+            PendingAction::ResumeSession,
+            PendingAction::ReloadFile { keep_edits: true },
+            PendingAction::DeleteTransaction(1),
+            PendingAction::DeletePosting(0, 0),
+            PendingAction::FlagAll('!'),
+            PendingAction::Dismiss,
+        ] {
+            let mut app = test_app();
+            app.popup = Popup::new("?", action);
+            let before = format_transactions(&app.transactions, &FormatOptions::default());
+            app.handle_action(Action::Key(KeyCode::Esc.into())).unwrap();
+            assert!(!app.popup.active, "{:?}", action);
+            assert!(!app.exit, "{:?}", action);
+            assert!(!app.unsaved_changes, "{:?}", action);
+            assert_eq!(app.transactions.len(), 4, "{:?}", action);
+            assert_eq!(
+                format_transactions(&app.transactions, &FormatOptions::default()),
+                before,
+                "{:?}",
+                action
+            );
+        }
+    }
+
+    #[test]
+    fn watch_asks_before_dropping_unsaved_edits() {
+        let dir = TempDir::new("watch-app");
+        let file = dir.path("ledger.beancount");
+        let content = fs::read_to_string("data/test.beancount").unwrap();
+        fs::write(&file, &content).unwrap();
+        let mut app = App::new(Args {
+            file: vec![file.clone()],
+            no_session: true,
+            watch: true,
+            ..Default::default()
+        })
+        .unwrap();
+        app.handle_key_event(KeyCode::Char('x').into()).unwrap();
+        write_atomically(&file, &format!("{}\n; appended\n", content)).unwrap();
+        for _ in 0..50 {
+            app.handle_action(Action::Tick).unwrap();
+            if app.popup.active {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(app.popup.prompt.contains("while you have unsaved edits"));
+        app.handle_action(Action::Key(KeyCode::Char('k').into()))
+            .unwrap();
+        assert!(textarea_content(&app.transactions[0].metadata_textareas()[2]).contains('x'));
+    }
+
+    #[test]
+    fn kept_edits_are_saved_at_their_new_position() {
+        let dir = TempDir::new("reload-in-place");
+        let file = dir.path("ledger.beancount");
+        let content = fs::read_to_string("data/flags.beancount").unwrap();
+        fs::write(&file, &content).unwrap();
+        let mut app = App::new(Args {
+            file: vec![file.clone()],
+            no_session: true,
+            in_place: true,
+            ..Default::default()
+        })
+        .unwrap();
+        app.current_index = 1;
+        app.handle_key_event(KeyCode::Char('x').into()).unwrap();
+        // another program inserts lines above the edited transaction
+        let added = "; added by another program\n\n";
+        fs::write(&file, format!("{}{}", added, content)).unwrap();
+        app.popup = Popup::new("?", PendingAction::ReloadFile { keep_edits: false });
+        app.handle_action(Action::Key(KeyCode::Char('k').into()))
+            .unwrap();
+        assert_eq!(app.transactions[1].line_number, 7);
+        assert!(app.save());
+        let saved = fs::read_to_string(&file).unwrap();
+        let (before, after) = content.split_once("2024-03-02").unwrap();
+        let (_, after) = after.split_once("\n\n").unwrap();
+        assert!(saved.starts_with(&format!("{}{}2024-03-02 * ", added, before)));
+        assert!(saved.ends_with(&format!("12.00 EUR\n\n{}", after)));
+        assert!(textarea_content(&app.transactions[1].metadata_textareas()[2]).contains('x'));
+        assert!(saved.contains(&textarea_content(
+            &app.transactions[1].metadata_textareas()[2]
+        )));
+    }
+
+    fn type_command(app: &mut App, command: &str) {
+        app.handle_action(Action::Key(KeyEvent::new(
+            KeyCode::Char(':'),
+            KeyModifiers::ALT,
+        )))
+        .unwrap();
+        for c in command.chars() {
+            app.handle_action(Action::Key(KeyCode::Char(c).into()))
+                .unwrap();
+        }
+        app.handle_action(Action::Key(KeyCode::Enter.into()))
+            .unwrap();
+    }
+
+    #[test]
+    fn distant_widgets_are_dropped_but_edits_kept() {
+        let mut app = test_app();
+        let end = KeyEvent::new(KeyCode::End, KeyModifiers::CONTROL);
+        let home = KeyEvent::new(KeyCode::Home, KeyModifiers::CONTROL);
+        // drawing builds the widgets of the current transaction
+        app.transactions[0].metadata_textareas();
+        assert!(app.transactions[0].has_widgets());
+        app.handle_key_event(end).unwrap();
+        assert!(!app.transactions[0].has_widgets());
+        app.handle_key_event(home).unwrap();
+        app.handle_key_event(KeyCode::Char('x').into()).unwrap();
+        app.handle_key_event(end).unwrap();
+        assert!(app.transactions[0].has_widgets());
+        assert!(textarea_content(&app.transactions[0].metadata_textareas()[2]).contains('x'));
+    }
+
+    #[test]
+    fn command_line_goto_and_errors() {
+        let mut app = test_app();
+        type_command(&mut app, "goto 3");
+        assert_eq!(app.current_index, 2);
+        assert!(app.command_line.is_none());
+        type_command(&mut app, "goto 9");
+        assert_eq!(app.current_index, 2);
+        assert_eq!(
+            app.status_message.as_ref().unwrap().text,
+            "there are only 4 transactions"
+        );
+        type_command(&mut app, "bogus");
+        assert_eq!(
+            app.status_message.as_ref().unwrap().text,
+            "unknown command `bogus`"
+        );
+    }
+
+    #[test]
+    fn script_runs_commands_and_saves() {
+        let dir = TempDir::new("script");
+        let output = dir.path("out.beancount");
+        let mut app = test_app();
+        app.output = Some(output.clone());
+        app.run_script("# jump around\ngoto 3\n\nsort\n").unwrap();
+        assert_eq!(app.current_index, 2);
+        assert!(fs::read_to_string(&output).unwrap().contains("Foo Bar3"));
+
+        let err = app.run_script("goto 1\n\nbogus\ngoto 2").unwrap_err();
+        assert_eq!(err.to_string(), "line 3: unknown command `bogus`");
+        assert_eq!(app.current_index, 0);
+
+        app.unsaved_changes = true;
+        let err = app.run_script("q").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("line 1: You have unsaved changes."));
+    }
+
+    #[test]
+    fn edit_the_comments_above_the_transaction() {
+        let mut app = test_app();
+        app.handle_key_event(KeyEvent::new(KeyCode::Char(';'), KeyModifiers::ALT))
+            .unwrap();
+        for c in "source: a.csv".chars() {
+            app.handle_action(Action::Key(KeyCode::Char(c).into()))
+                .unwrap();
+        }
+        app.handle_action(Action::Key(KeyCode::Enter.into()))
+            .unwrap();
+        app.handle_action(Action::Paste(";row 3".to_string()))
+            .unwrap();
+        app.handle_action(Action::Key(ctrl('s'))).unwrap();
+        assert!(app.comment_editor.is_none());
+        assert_eq!(app.transactions[0].comments, ["; source: a.csv", ";row 3"]);
+        assert!(app.transactions[0].dirty);
+        assert!(format_transaction(&app.transactions[0])
+            .starts_with("; source: a.csv\n;row 3\n2023-10-01 txn"));
+
+        // Esc keeps the comments as they were
+        app.handle_key_event(KeyEvent::new(KeyCode::Char(';'), KeyModifiers::ALT))
+            .unwrap();
+        app.handle_action(Action::Key(KeyCode::Char('x').into()))
+            .unwrap();
+        app.handle_action(Action::Key(KeyCode::Esc.into())).unwrap();
+        assert_eq!(app.transactions[0].comments.len(), 2);
+    }
+
+    #[test]
+    fn only_key_presses_act() {
+        let mut app = test_app();
+        let press_repeat_release = |app: &mut App, key: KeyEvent| {
+            for kind in [
+                KeyEventKind::Press,
+                KeyEventKind::Repeat,
+                KeyEventKind::Release,
+            ] {
+                app.handle_action(Action::Key(KeyEvent { kind, ..key }))
+                    .unwrap();
+            }
+        };
+        press_repeat_release(&mut app, KeyCode::Char('x').into());
+        assert_eq!(
+            textarea_content(&app.transactions[0].metadata_textareas()[2]),
+            "xTest Co"
+        );
+        press_repeat_release(&mut app, ctrl('n'));
+        assert_eq!(app.current_index, 1);
+
+        // overlays ignore them too
+        app.open_calendar();
+        let selected = app.calendar.as_ref().unwrap().selected;
+        press_repeat_release(&mut app, KeyCode::Right.into());
+        assert_eq!(
+            app.calendar.as_ref().unwrap().selected,
+            selected.succ_opt().unwrap()
+        );
+    }
+
+    #[test]
+    fn start_on_the_narration_without_payees() {
+        let dir = TempDir::new("narration");
+        let path = dir.path("ledger.beancount");
+        fs::write(
+            &path,
+            "2024-01-02 * \"Bread\"\n    Assets:Cash  -3 USD\n    Expenses:Food\n\
+             2024-01-03 * \"Milk\"\n    Assets:Cash  -1 USD\n    Expenses:Food\n\
+             2024-01-04 * \"Shop\" \"Soap\"\n    Assets:Cash  -2 USD\n    Expenses:Home\n",
+        )
+        .unwrap();
+        let app = App::new(Args {
+            file: vec![path.clone()],
+            no_session: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(app.focused_field_type(), InputFieldType::Narration);
+        assert_eq!(test_app().focused_field_type(), InputFieldType::Payee);
+    }
+
+    #[test]
+    fn review_problems_before_saving() {
+        let dir = TempDir::new("review");
+        let input = dir.path("in.beancount");
+        fs::write(
+            &input,
+            "2024-01-01 open Assets:Cash\n\
+             2024-01-02 * \"Bakery\"\n    Assets:Cash  -3 USD\n    Assets:Cash\n\
+             2024-01-03 * \"Shop\"\n    Assets:Cash  -5 USD\n    Assets:Cash   5 USD\n",
+        )
+        .unwrap();
+        let output = dir.path("out.beancount");
+        let mut app = App::new(Args {
+            file: vec![input],
+            output: Some(output.clone()),
+            no_session: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        // without problems it saves right away
+        app.handle_key_event(ctrl('s')).unwrap();
+        assert!(app.save_review.is_none());
+        assert!(output.exists());
+        fs::remove_file(&output).unwrap();
+
+        set_textarea_content(
+            &mut app.transactions[1].postings_textareas_mut()[1].account_textarea,
+            "",
+        );
+        app.mark_transaction_dirty(1);
+        app.handle_key_event(ctrl('s')).unwrap();
+        let review = app.save_review.as_ref().unwrap();
+        assert_eq!(
+            review.summary(),
+            "1 edited, 1 failing validation, 0 with warnings"
+        );
+        let problem = review.selected_problem().unwrap();
+        assert_eq!((problem.index, problem.payee.as_str()), (1, "Shop"));
+        assert_eq!(problem.issue, "posting 2 has no account");
+        assert_eq!(problem.severity, Severity::Error);
+
+        // saving is blocked until the transaction is fixed
+        app.handle_action(Action::Key(KeyCode::Char('s').into()))
+            .unwrap();
+        assert!(!output.exists());
+        app.handle_action(Action::Key(KeyCode::Enter.into()))
+            .unwrap();
+        assert!(app.save_review.is_none());
+        assert_eq!(app.current_index, 1);
+
+        // warnings can be saved anyway
+        set_textarea_content(
+            &mut app.transactions[1].postings_textareas_mut()[1].account_textarea,
+            "Expenses:Unopened",
+        );
+        app.handle_key_event(ctrl('s')).unwrap();
+        let review = app.save_review.as_ref().unwrap();
+        assert_eq!(review.problems.len(), 1);
+        assert!(!review.has_errors());
+        app.handle_action(Action::Key(KeyCode::Char('s').into()))
+            .unwrap();
+        assert!(app.save_review.is_none());
+        assert!(fs::read_to_string(&output)
+            .unwrap()
+            .contains("Expenses:Unopened"));
+    }
+
+    #[test]
+    fn several_files_are_reviewed_together() {
+        let dir = TempDir::new("files");
+        let visa = dir.path("visa.beancount");
+        fs::write(
+            &visa,
+            "2023-02-01 * \"Shop\" \"Card\"\n    Liabilities:Visa  -3 USD\n    Expenses:Test\n",
+        )
+        .unwrap();
+        let output = dir.path("out.beancount");
+        let mut app = App::new(Args {
+            file: vec!["data/test.beancount".into(), visa.clone()],
+            output: Some(output.clone()),
+            no_session: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(app.transactions.len(), 5);
+        assert_eq!(app.transactions[4].source, visa);
+        assert_eq!(app.source_name().unwrap(), "test.beancount");
+        app.move_transactions(usize::MAX, true);
+        assert_eq!(app.source_name().unwrap(), "visa.beancount");
+        assert_eq!(
+            app.file_summary(),
+            [
+                "data/test.beancount: 4 transactions".to_string(),
+                format!("{}: 1 transaction", visa.display()),
+            ]
+        );
+
+        assert!(app.save());
+        let saved = fs::read_to_string(&output).unwrap();
+        let test_group = saved.find("; data/test.beancount").unwrap();
+        let visa_group = saved.find(&format!("; {}", visa.display())).unwrap();
+        assert!(test_group < saved.find("Foo Bar").unwrap());
+        assert!(visa_group < saved.find("Shop").unwrap());
+        assert!(saved.find("Foo Bar").unwrap() < visa_group);
+    }
+
+    #[test]
+    fn command_line_sort_keeps_marks_and_focus() {
+        let mut app = test_app();
+        // Foo Bar1 (2023-01-11) is the second transaction but the oldest one
+        app.current_index = 1;
+        app.marks.insert(0);
+        app.execute_command("sort").unwrap();
+        let narrations: Vec<String> = app
+            .transactions
+            .iter()
+            .map(|t| textarea_content(&t.metadata_textareas()[3]))
+            .collect();
+        assert_eq!(narrations, ["Foo Bar1", "Foo Bar", "Foo Bar2", "Foo Bar3"]);
+        assert_eq!(app.current_index, 0);
+        assert_eq!(app.marks, HashSet::from([1]));
+        assert!(app.unsaved_changes);
+    }
+
+    #[test]
+    fn command_line_filter_limits_navigation() {
+        let mut app = test_app();
+        app.execute_command("filter account Test1").unwrap();
+        // only Foo Bar2 posts to Expenses:Test1
+        assert_eq!(app.current_index, 2);
+        app.handle_key_event(ctrl('n')).unwrap();
+        assert_eq!(app.current_index, 2);
+        app.handle_key_event(ctrl('p')).unwrap();
+        assert_eq!(app.current_index, 2);
+        assert!(app.execute_command("filter payee nobody").is_err());
+        app.execute_command("filter").unwrap();
+        app.handle_key_event(ctrl('p')).unwrap();
+        assert_eq!(app.current_index, 1);
+    }
+
+    #[test]
+    fn flag_field_keeps_a_single_valid_flag() {
+        let mut app = test_app();
+        app.currently_selected_metadata_field = 1;
+        for c in "P!hello".chars() {
+            app.handle_key_event(KeyCode::Char(c).into()).unwrap();
+        }
+        assert_eq!(
+            textarea_content(&app.transactions[0].metadata_textareas()[1]),
+            "!"
+        );
+        assert!(app
+            .status_message
+            .as_ref()
+            .unwrap()
+            .text
+            .starts_with("'o' is not a valid flag"));
+
+        // an emptied flag is written as a normal transaction
+        app.handle_key_event(ctrl('u')).unwrap();
+        assert!(format_transaction(&app.transactions[0]).starts_with("2023-10-01 * "));
+    }
+}
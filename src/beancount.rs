@@ -1,27 +1,49 @@
-use std::{fs, path::PathBuf};
+use std::{
+    cell::OnceCell,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt, fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
-use beancount_parser::{BeancountFile, Directive, DirectiveContent, Posting, Transaction};
-use color_eyre::Result;
-use ratatui::{
-    style::Style,
-    widgets::{Block, Borders},
+use beancount_parser::{
+    metadata, BeancountFile, Date, Directive, DirectiveContent, Posting, PostingPrice, Transaction,
+};
+use chrono::{
+    format::{Item, StrftimeItems},
+    Datelike, NaiveDate,
 };
+use color_eyre::{eyre::Context, Result};
+use ratatui::{layout::Alignment, style::Style};
+use rayon::prelude::*;
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 use tui_textarea::TextArea;
+use unicode_width::UnicodeWidthStr;
 
-use crate::{error::BeancountTuiError, utils::format_date};
+use crate::{
+    config::is_valid_flag,
+    error::BeancountTuiError,
+    utils::{format_date, parse_date},
+    writeback::{line_starts, transaction_span},
+};
 
+/// A single line text area without block, the border and title are drawn by the ui.
+/// The cursor stays invisible unless the ui shows it in the focused field.
 macro_rules! create_textarea {
-    ($name:expr, $value:expr) => {{
+    ($value:expr) => {{
         let mut textarea = TextArea::new(vec![$value]);
-        textarea.set_block(Block::default().borders(Borders::ALL).title($name));
         textarea.set_cursor_line_style(Style::default());
+        textarea.set_cursor_style(Style::default());
         textarea
     }};
 }
 
 // PostingTUI
 
+/// The text fields of a posting
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PostingField {
     Account,
@@ -29,34 +51,125 @@ pub enum PostingField {
     Currency,
 }
 
-#[allow(dead_code)]
+/// The `@` (per unit) or `@@` (total) price of a posting
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Price {
+    pub total: bool, // `@@` instead of `@`
+    pub amount: String,
+    pub currency: String,
+}
+
+impl Price {
+    pub fn new(total: bool, amount: impl Into<String>, currency: impl Into<String>) -> Self {
+        Self {
+            total,
+            amount: amount.into(),
+            currency: currency.into(),
+        }
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = if self.total { "@@" } else { "@" };
+        write!(f, "{} {} {}", symbol, self.amount, self.currency)
+    }
+}
+
+/// A posting edited in text areas. Its cost and price can't be edited, they are kept
+/// as parsed.
 #[derive(Clone, Debug)]
 pub struct PostingTui<'t> {
+    pub flag: Option<char>, // e.g. `!` on a posting that needs attention
     pub account_textarea: TextArea<'t>,
     pub amount_textarea: TextArea<'t>,
     pub currency_textarea: TextArea<'t>,
+    pub cost: Option<String>, // inside the braces, e.g. `1.10 USD, 2024-01-01`
+    pub price: Option<Price>, // e.g. `@ 1.10 USD`
+    pub comment: Option<String>, // after the posting on its line, without `;`
+    pub lines: Vec<String>,   // metadata and comment lines below it, without indent
 }
 
-impl<'t> TryFrom<Posting<Decimal>> for PostingTui<'t> {
-    type Error = BeancountTuiError;
+/// Amounts are right-aligned so they sit next to their currency
+fn amount_textarea<'t>(amount: String) -> TextArea<'t> {
+    let mut textarea = create_textarea!(amount);
+    textarea.set_alignment(Alignment::Right);
+    textarea
+}
 
-    fn try_from(value: Posting<Decimal>) -> std::prelude::v1::Result<Self, Self::Error> {
-        let account_textarea = create_textarea!("Account", value.account.to_string());
-        let (amount, currency) = match value.amount {
+impl<'t> From<&Posting<Decimal>> for PostingTui<'t> {
+    fn from(value: &Posting<Decimal>) -> Self {
+        let account_textarea = create_textarea!(value.account.to_string());
+        let (amount, currency) = match &value.amount {
             Some(a) => (a.value.to_string(), a.currency.to_string()),
             None => ("".to_string(), "".to_string()),
         };
-        let amount_textarea = create_textarea!("Amount", amount);
-        let currency_textarea = create_textarea!("Currency", currency);
-        Ok(Self {
+        let amount_textarea = amount_textarea(amount);
+        let currency_textarea = create_textarea!(currency);
+        let cost = value.cost.as_ref().map(|cost| {
+            let amount = cost
+                .amount
+                .as_ref()
+                .map(|amount| format!("{} {}", amount.value, amount.currency));
+            let date = cost.date.as_ref().map(format_date);
+            amount
+                .into_iter()
+                .chain(date)
+                .collect::<Vec<_>>()
+                .join(", ")
+        });
+        let price = value.price.as_ref().map(|price| match price {
+            PostingPrice::Unit(amount) => {
+                Price::new(false, amount.value.to_string(), amount.currency.to_string())
+            }
+            PostingPrice::Total(amount) => {
+                Price::new(true, amount.value.to_string(), amount.currency.to_string())
+            }
+        });
+        Self {
+            flag: value.flag,
             account_textarea,
             amount_textarea,
             currency_textarea,
-        })
+            cost,
+            price,
+            comment: None,
+            lines: Vec::new(),
+        }
+    }
+}
+
+impl Default for PostingTui<'_> {
+    fn default() -> Self {
+        Self {
+            flag: None,
+            account_textarea: create_textarea!(String::new()),
+            amount_textarea: amount_textarea(String::new()),
+            currency_textarea: create_textarea!(String::new()),
+            cost: None,
+            price: None,
+            comment: None,
+            lines: Vec::new(),
+        }
     }
 }
 
 impl<'t> PostingTui<'t> {
+    /// A posting without flag with the given field contents
+    pub fn new(account: &str, amount: &str, currency: &str) -> Self {
+        Self {
+            flag: None,
+            account_textarea: create_textarea!(account.to_string()),
+            amount_textarea: amount_textarea(amount.to_string()),
+            currency_textarea: create_textarea!(currency.to_string()),
+            cost: None,
+            price: None,
+            comment: None,
+            lines: Vec::new(),
+        }
+    }
+
+    /// The field after `current_field`, or before it if not `forward`, wrapping around
     pub fn next_field(&mut self, current_field: &PostingField, forward: bool) -> PostingField {
         match (current_field, forward) {
             (PostingField::Account, true) => PostingField::Amount,
@@ -68,6 +181,7 @@ impl<'t> PostingTui<'t> {
         }
     }
 
+    /// The text area of `field`
     pub fn get_field_mut(&mut self, field: &PostingField) -> &mut TextArea<'t> {
         match field {
             PostingField::Account => &mut self.account_textarea,
@@ -87,68 +201,505 @@ impl<'t> PostingTui<'t> {
 
 // TransactionTui
 
-#[allow(dead_code)]
+/// A transaction edited in text areas, created from a parsed [`Directive`]
 #[derive(Clone, Debug)]
 pub struct TransactionTui<'t> {
     pub directive: Transaction<Decimal>,
-    pub metadata_textareas: [TextArea<'t>; 4],
-    pub postings_textareas: Vec<PostingTui<'t>>,
+    pub date: Date,
+    pub dirty: bool,        // wether the transaction was edited since it was parsed
+    pub edited: bool,       // wether the fields differ from `directive`, survives saving
+    pub line_number: u32,   // line of the transaction in the source file, starting at 1
+    pub source: PathBuf,    // file the transaction was read from, empty if it has none
+    pub span: Range<usize>, // bytes of the transaction and its comment lines in the source file
+    pub span_text: String,  // content of `span` when it was read or last written
+    pub comments: Vec<String>, // comment lines right above the header, e.g. `; source: a.csv`
+    parsed_comments: Vec<String>, // `comments` as read from the source file
+    lines: Vec<String>,     // metadata and comment lines between the header and the postings
+    posting_lines: Vec<PostingLines>, // comment and lines of each parsed posting
+    pub metadata: Vec<(String, String)>, // `key: value` lines of the directive, read only
+    header_comment: String, // comment after the header in the source file, without `;`
+    widgets: OnceCell<Box<TransactionWidgets<'t>>>, // built from `directive` on first access
+}
+
+/// The text areas of a transaction. Building them for every transaction of a large
+/// ledger up front is slow, so they only exist for the transactions that were looked at.
+#[derive(Clone, Debug)]
+struct TransactionWidgets<'t> {
+    metadata_textareas: [TextArea<'t>; 5],
+    postings_textareas: Vec<PostingTui<'t>>,
+    original_metadata: [String; 5], // field contents as parsed, to show what was changed
+    original_postings: Vec<[String; 3]>, // account, amount, currency as parsed
+}
+
+/// Position of `field` in the parsed postings stored by `TransactionWidgets`
+fn original_column(field: &PostingField) -> usize {
+    match field {
+        PostingField::Account => 0,
+        PostingField::Amount => 1,
+        PostingField::Currency => 2,
+    }
+}
+
+impl<'t> TransactionTui<'t> {
+    fn widgets(&self) -> &TransactionWidgets<'t> {
+        self.widgets.get_or_init(|| Box::new(self.build_widgets()))
+    }
+
+    fn widgets_mut(&mut self) -> &mut TransactionWidgets<'t> {
+        self.widgets();
+        self.widgets.get_mut().expect("Widgets were just built")
+    }
+
+    fn build_widgets(&self) -> TransactionWidgets<'t> {
+        let transaction = &self.directive;
+        let date_textarea = create_textarea!(format_date(&self.date));
+        let flag_textarea = create_textarea!(match transaction.flag {
+            Some(c) => c.to_string(),
+            None => "*".to_string(),
+        });
+        let payee_textarea =
+            create_textarea!(transaction.payee.as_deref().unwrap_or_default().to_string());
+        let narration_textarea = create_textarea!(transaction
+            .narration
+            .as_deref()
+            .unwrap_or_default()
+            .to_string());
+        // the parser drops comments, it is taken from the source by `set_source`
+        let comment_textarea = create_textarea!(self.header_comment.clone());
+        // like the comment, the metadata and comment lines are taken from the source
+        let postings_textareas: Vec<PostingTui> = transaction
+            .postings
+            .iter()
+            .enumerate()
+            .map(|(index, posting)| {
+                let (comment, lines) = self.posting_lines.get(index).cloned().unwrap_or_default();
+                PostingTui {
+                    comment,
+                    lines,
+                    ..PostingTui::from(posting)
+                }
+            })
+            .collect();
+        let metadata_textareas = [
+            date_textarea,
+            flag_textarea,
+            payee_textarea,
+            narration_textarea,
+            comment_textarea,
+        ];
+        TransactionWidgets {
+            original_metadata: metadata_textareas.each_ref().map(textarea_content),
+            original_postings: postings_textareas
+                .iter()
+                .map(|posting| {
+                    [
+                        PostingField::Account,
+                        PostingField::Amount,
+                        PostingField::Currency,
+                    ]
+                    .map(|field| textarea_content(posting.get_field(&field)))
+                })
+                .collect(),
+            metadata_textareas,
+            postings_textareas,
+        }
+    }
+
+    /// Whether the metadata field at `index` differs from the parsed transaction
+    pub fn metadata_changed(&self, index: usize) -> bool {
+        let widgets = self.widgets();
+        textarea_content(&widgets.metadata_textareas[index]) != widgets.original_metadata[index]
+    }
+
+    /// Whether `field` of the posting at `index` differs from the parsed posting at the
+    /// same position, postings added since count as changed once they have content
+    pub fn posting_field_changed(&self, index: usize, field: &PostingField) -> bool {
+        let widgets = self.widgets();
+        let content = textarea_content(widgets.postings_textareas[index].get_field(field));
+        let column = original_column(field);
+        match widgets.original_postings.get(index) {
+            Some(original) => content != original[column],
+            None => !content.is_empty(),
+        }
+    }
+
+    /// Whether any field differs from the parsed transaction or postings were added
+    /// or removed
+    pub fn changed(&self) -> bool {
+        let n_postings = self.postings_textareas().len();
+        n_postings != self.widgets().original_postings.len()
+            || self.comments != self.parsed_comments
+            || (0..5).any(|index| self.metadata_changed(index))
+            || (0..n_postings).any(|index| {
+                [
+                    PostingField::Account,
+                    PostingField::Amount,
+                    PostingField::Currency,
+                ]
+                .iter()
+                .any(|field| self.posting_field_changed(index, field))
+            })
+    }
+
+    /// Puts back the parsed content of the metadata field at `index`, returns whether
+    /// it was changed
+    pub fn revert_metadata(&mut self, index: usize) -> bool {
+        if !self.metadata_changed(index) {
+            return false;
+        }
+        let widgets = self.widgets_mut();
+        set_textarea_content(
+            &mut widgets.metadata_textareas[index],
+            &widgets.original_metadata[index],
+        );
+        true
+    }
+
+    /// Puts back the parsed content of `field` of the posting at `index`, a posting
+    /// added since is emptied. Returns whether the field was changed.
+    pub fn revert_posting_field(&mut self, index: usize, field: &PostingField) -> bool {
+        if !self.posting_field_changed(index, field) {
+            return false;
+        }
+        let column = original_column(field);
+        let widgets = self.widgets_mut();
+        let original = widgets
+            .original_postings
+            .get(index)
+            .map(|original| original[column].clone())
+            .unwrap_or_default();
+        set_textarea_content(
+            widgets.postings_textareas[index].get_field_mut(field),
+            &original,
+        );
+        true
+    }
+
+    /// Sets the file and span the transaction was read from and takes over the comments
+    /// the parser drops: the comment lines right above the header, which become part of
+    /// the span, and the comment at the end of the header
+    pub fn set_source(&mut self, path: &Path, content: &str, line_starts: &[usize]) {
+        self.source = path.to_path_buf();
+        self.span = transaction_span(content, line_starts, self.line_number);
+        let header = content[self.span.clone()]
+            .lines()
+            .next()
+            .unwrap_or_default();
+        self.header_comment = header_comment(header).unwrap_or_default().to_string();
+        (self.lines, self.posting_lines) = body_lines(&content[self.span.clone()]);
+        let header_line = (self.line_number as usize).saturating_sub(1);
+        let mut comments = Vec::new();
+        for line in (0..header_line.min(line_starts.len())).rev() {
+            let text = content[line_starts[line]..line_starts[line + 1]].trim_end();
+            if !text.starts_with(';') {
+                break;
+            }
+            comments.push(text.to_string());
+            self.span.start = line_starts[line];
+        }
+        comments.reverse();
+        self.comments = comments.clone();
+        self.parsed_comments = comments;
+        self.span_text = content[self.span.clone()].to_string();
+    }
+
+    /// Takes over the fields, postings and comments of `edited`, the same transaction
+    /// read from an older version of the file. The span and line number stay the ones
+    /// of this transaction.
+    pub fn take_edits(&mut self, mut edited: TransactionTui<'t>) {
+        let metadata = edited.metadata_textareas().each_ref().map(textarea_content);
+        for (textarea, content) in self.metadata_textareas_mut().iter_mut().zip(&metadata) {
+            set_textarea_content(textarea, content);
+        }
+        *self.postings_textareas_mut() = std::mem::take(edited.postings_textareas_mut());
+        self.comments = edited.comments;
+        self.dirty = edited.dirty;
+        self.edited = edited.edited;
+    }
+
+    /// Date, flag, payee, narration and comment, in this order
+    pub fn metadata_textareas(&self) -> &[TextArea<'t>; 5] {
+        &self.widgets().metadata_textareas
+    }
+
+    pub fn metadata_textareas_mut(&mut self) -> &mut [TextArea<'t>; 5] {
+        &mut self.widgets_mut().metadata_textareas
+    }
+
+    /// The postings in file order
+    pub fn postings_textareas(&self) -> &[PostingTui<'t>] {
+        &self.widgets().postings_textareas
+    }
+
+    pub fn postings_textareas_mut(&mut self) -> &mut Vec<PostingTui<'t>> {
+        &mut self.widgets_mut().postings_textareas
+    }
+
+    /// Where the transaction was read from, e.g. `new.beancount:143`, `new` if it
+    /// wasn't read from a file
+    pub fn location(&self) -> String {
+        if self.line_number == 0 {
+            return "new".to_string();
+        }
+        match self.source.file_name() {
+            Some(name) => format!("{}:{}", name.to_string_lossy(), self.line_number),
+            None => format!("L{}", self.line_number),
+        }
+    }
+
+    /// Frees the text areas unless they hold edits, they are rebuilt when needed again
+    pub fn drop_widgets(&mut self) {
+        if !self.dirty && !self.edited && self.widgets.get().is_some() {
+            self.widgets.take();
+        }
+    }
+
+    #[cfg(test)]
+    pub fn has_widgets(&self) -> bool {
+        self.widgets.get().is_some()
+    }
 }
 
 impl<'t> TryFrom<&Directive<Decimal>> for TransactionTui<'t> {
     type Error = BeancountTuiError;
 
     fn try_from(value: &Directive<Decimal>) -> Result<Self, BeancountTuiError> {
-        let DirectiveContent::Transaction(transaction) = value.content.to_owned() else {
+        value.clone().try_into()
+    }
+}
+
+/// Takes the directive by value so the parsed transaction is moved, not cloned
+impl<'t> TryFrom<Directive<Decimal>> for TransactionTui<'t> {
+    type Error = BeancountTuiError;
+
+    fn try_from(value: Directive<Decimal>) -> Result<Self, BeancountTuiError> {
+        let DirectiveContent::Transaction(transaction) = value.content else {
             return Err(BeancountTuiError::Parser(
                 "Can only parse Transactions".to_string(),
             ));
         };
-        let date_textarea = create_textarea!("Date", format_date(&value.date));
-        let flag_textarea = create_textarea!(
-            "",
-            match transaction.flag {
-                Some(c) => c.to_string(),
-                None => "*".to_string(),
-            }
-        );
-        let payee_textarea = create_textarea!(
-            "Payee",
-            match transaction.payee.clone() {
-                Some(p) => p,
-                None => String::from(""),
-            }
-        );
-        let narration_textarea = create_textarea!(
-            "Narration",
-            match transaction.narration.clone() {
-                Some(n) => n,
-                None => String::from(""),
-            }
-        );
-        let postings_textareas = transaction
-            .postings
-            .clone()
-            .into_iter()
-            .map(|p| p.try_into().expect("Couldn't parse posting."))
-            .collect::<Vec<PostingTui>>();
         Ok(TransactionTui {
             directive: transaction,
-            metadata_textareas: [
-                date_textarea,
-                flag_textarea,
-                payee_textarea,
-                narration_textarea,
-            ],
-            postings_textareas,
+            date: value.date,
+            dirty: false,
+            edited: false,
+            line_number: value.line_number,
+            source: PathBuf::new(),
+            span: 0..0,
+            span_text: String::new(),
+            comments: Vec::new(),
+            parsed_comments: Vec::new(),
+            lines: Vec::new(),
+            posting_lines: Vec::new(),
+            metadata: metadata_lines(&value.metadata),
+            header_comment: String::new(),
+            widgets: OnceCell::new(),
         })
     }
 }
 
-pub fn parse_beancount_file(file_path: &PathBuf) -> Result<BeancountFile<Decimal>> {
-    let beancount_content = fs::read_to_string(file_path)?;
-    let beancount: BeancountFile<Decimal> = beancount_content.parse()?;
-    Ok(beancount)
+/// The metadata of a directive as key and value text, sorted by key since the parser
+/// doesn't keep their order
+fn metadata_lines(map: &metadata::Map<Decimal>) -> Vec<(String, String)> {
+    let mut lines: Vec<(String, String)> = map
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                metadata::Value::String(text) => quote_string(text),
+                metadata::Value::Number(number) => number.to_string(),
+                metadata::Value::Currency(currency) => currency.to_string(),
+                other => format!("{:?}", other),
+            };
+            (key.to_string(), value)
+        })
+        .collect();
+    lines.sort();
+    lines
+}
+
+/// The comment after a posting and the metadata and comment lines below it
+type PostingLines = (Option<String>, Vec<String>);
+
+/// The metadata and comment lines of the transaction in `text`, header line first: the
+/// ones above the first posting, and the comment and the lines below each posting
+fn body_lines(text: &str) -> (Vec<String>, Vec<PostingLines>) {
+    let mut lines = Vec::new();
+    let mut postings: Vec<PostingLines> = Vec::new();
+    for line in text.lines().skip(1).map(str::trim) {
+        let key = line.split_once(':').map_or("", |(key, _)| key);
+        let is_metadata = key.starts_with(|c: char| c.is_ascii_lowercase())
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if line.is_empty() {
+            continue;
+        } else if line.starts_with(';') || is_metadata {
+            match postings.last_mut() {
+                Some((_, below)) => below.push(line.to_string()),
+                None => lines.push(line.to_string()),
+            }
+        } else {
+            let comment = header_comment(line).map(str::to_string);
+            postings.push((comment, Vec::new()));
+        }
+    }
+    (lines, postings)
+}
+
+/// The text after the `;` of a comment at the end of a header line, `;` in quotes
+/// doesn't count
+fn header_comment(header: &str) -> Option<&str> {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (index, c) in header.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => return Some(header[index + 1..].trim()),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// A parsed file of a ledger together with its content
+#[derive(Debug)]
+pub struct LedgerFile {
+    pub path: PathBuf,
+    pub content: String,
+    pub beancount: BeancountFile<Decimal>,
+}
+
+/// Parses `file_path` and, recursively, every file it includes. Include paths are
+/// relative to the including file, every file is only read once.
+///
+/// The files of each include level are parsed in parallel, the result is in the
+/// order of a depth-first walk through the includes in file order.
+pub fn parse_ledger(file_path: &Path) -> Result<Vec<LedgerFile>> {
+    parse_ledger_with_progress(file_path, &|_| {})
+}
+
+/// Like [`parse_ledger`], calling `progress` with the number of directives parsed
+/// so far whenever a file is done
+pub fn parse_ledger_with_progress(
+    file_path: &Path,
+    progress: &(dyn Fn(usize) + Sync),
+) -> Result<Vec<LedgerFile>> {
+    let n_directives = AtomicUsize::new(0);
+    let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut parsed: HashMap<PathBuf, (LedgerFile, Vec<PathBuf>)> = HashMap::new();
+    let mut seen = BTreeSet::from([canonical(file_path)]);
+    let mut level = vec![file_path.to_path_buf()];
+    while !level.is_empty() {
+        let files = level
+            .into_par_iter()
+            .map(|path| {
+                let ledger_file = parse_ledger_file(path)?;
+                let n = ledger_file.beancount.directives.len();
+                progress(n_directives.fetch_add(n, Ordering::Relaxed) + n);
+                Ok(ledger_file)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        level = Vec::new();
+        for ledger_file in files {
+            let dir = ledger_file.path.parent().unwrap_or(Path::new(""));
+            let includes: Vec<PathBuf> = ledger_file
+                .beancount
+                .includes
+                .iter()
+                .map(|include| dir.join(include))
+                .collect();
+            // the cycle check runs between the levels, so it doesn't race
+            for include in &includes {
+                if seen.insert(canonical(include)) {
+                    level.push(include.clone());
+                }
+            }
+            let includes = includes.iter().map(|include| canonical(include)).collect();
+            parsed.insert(canonical(&ledger_file.path), (ledger_file, includes));
+        }
+    }
+    let mut files = Vec::with_capacity(parsed.len());
+    let mut pending = vec![canonical(file_path)];
+    while let Some(path) = pending.pop() {
+        let Some((ledger_file, includes)) = parsed.remove(&path) else {
+            continue;
+        };
+        // reversed so the includes are visited in file order
+        pending.extend(includes.into_iter().rev());
+        files.push(ledger_file);
+    }
+    Ok(files)
+}
+
+/// The transactions of `file_path` and the files it includes, each knowing the file
+/// and lines it was read from
+pub fn parse_beancount_file<'t>(file_path: &Path) -> Result<Vec<TransactionTui<'t>>> {
+    let mut transactions = Vec::new();
+    for ledger_file in parse_ledger(file_path)? {
+        let starts = line_starts(&ledger_file.content);
+        for directive in filter_transactions(ledger_file.beancount) {
+            let mut transaction = TransactionTui::try_from(directive)?;
+            transaction.set_source(&ledger_file.path, &ledger_file.content, &starts);
+            transactions.push(transaction);
+        }
+    }
+    Ok(transactions)
+}
+
+/// Explains why `path` couldn't be read, naming the full path
+fn read_error(path: &Path, err: io::Error) -> BeancountTuiError {
+    let path = fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+    let path = path.display();
+    BeancountTuiError::File(match err.kind() {
+        io::ErrorKind::NotFound => format!("{} doesn't exist", path),
+        io::ErrorKind::IsADirectory => format!("{} is a directory, not a file", path),
+        io::ErrorKind::PermissionDenied => format!("{} can't be read: permission denied", path),
+        _ => format!("couldn't read {}: {}", path, err),
+    })
+}
+
+/// Checks that `path` is a file that can be opened for reading, so a wrong path
+/// is reported before the terminal is taken over
+pub fn check_readable(path: &Path) -> Result<(), BeancountTuiError> {
+    if fs::metadata(path)
+        .map_err(|err| read_error(path, err))?
+        .is_dir()
+    {
+        return Err(read_error(path, io::ErrorKind::IsADirectory.into()));
+    }
+    fs::File::open(path).map_err(|err| read_error(path, err))?;
+    Ok(())
+}
+
+/// A warning if the extension of `path` suggests it isn't a beancount file
+pub fn extension_warning(path: &Path) -> Option<String> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    let hint = match extension.as_str() {
+        "csv" => "use --csv to import CSV files",
+        "xls" | "xlsx" | "ods" => "export the spreadsheet as CSV and use --csv",
+        _ => return None,
+    };
+    Some(format!(
+        "{} doesn't look like a beancount file, {}",
+        path.display(),
+        hint
+    ))
+}
+
+fn parse_ledger_file(path: PathBuf) -> Result<LedgerFile> {
+    let content = fs::read_to_string(&path).map_err(|err| read_error(&path, err))?;
+    let beancount = content
+        .parse()
+        .wrap_err_with(|| format!("couldn't parse {}", path.display()))?;
+    Ok(LedgerFile {
+        path,
+        content,
+        beancount,
+    })
 }
 
 /// Filters out everything that is not a DirectiveContent::Transaction
@@ -156,12 +707,1139 @@ pub fn filter_transactions(beancount_file: BeancountFile<Decimal>) -> Vec<Direct
     beancount_file
         .directives
         .into_iter()
-        .filter(|d| {
-            if let DirectiveContent::Transaction(_) = &d.content {
-                return true;
+        .filter(|d| matches!(d.content, DirectiveContent::Transaction(_)))
+        .collect()
+}
+
+/// Describes the `option` and `plugin` directives of a parsed file, they are left in
+/// the file but not interpreted. The parser skips plugins like comments, so they are
+/// looked up in the content.
+pub fn uninterpreted_directives(ledger_file: &LedgerFile) -> Vec<String> {
+    let options = ledger_file
+        .beancount
+        .options
+        .iter()
+        .map(|option| format!("option \"{}\"", option.name));
+    let plugins = ledger_file.content.lines().filter_map(|line| {
+        let rest = line.strip_prefix("plugin")?;
+        rest.starts_with([' ', '\t']).then(|| {
+            let name = rest.split('"').nth(1).unwrap_or(rest.trim());
+            format!("plugin \"{}\"", name)
+        })
+    });
+    options.chain(plugins).collect()
+}
+
+/// A `pad`, `balance` or `note` directive. It can't be edited, but it is shown and
+/// written out next to the transactions it was read between.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContextDirective {
+    pub source: PathBuf,
+    pub line_number: u32,
+    pub text: String, // as written in the source file, with its metadata lines
+    pub next_transaction: u32, // line of the transaction following it in the file, 0 if none
+}
+
+/// The `pad`, `balance` and `note` directives of a parsed file in file order. The
+/// parser skips notes like comments, so they are looked up in the content.
+pub fn context_directives(ledger_file: &LedgerFile) -> Vec<ContextDirective> {
+    let starts = line_starts(&ledger_file.content);
+    let mut transaction_lines = Vec::new();
+    let mut lines = Vec::new();
+    for directive in &ledger_file.beancount.directives {
+        match directive.content {
+            DirectiveContent::Transaction(_) => transaction_lines.push(directive.line_number),
+            DirectiveContent::Pad(_) | DirectiveContent::Balance(_) => {
+                lines.push(directive.line_number)
+            }
+            _ => {}
+        }
+    }
+    let notes = ledger_file.content.lines().enumerate().filter(|(_, line)| {
+        let mut words = line.split_whitespace();
+        parse_date(words.next().unwrap_or_default()).is_some() && words.next() == Some("note")
+    });
+    lines.extend(notes.map(|(index, _)| index as u32 + 1));
+    lines.sort_unstable();
+    transaction_lines.sort_unstable();
+    lines
+        .into_iter()
+        .map(|line_number| ContextDirective {
+            source: ledger_file.path.clone(),
+            line_number,
+            text: ledger_file.content[transaction_span(&ledger_file.content, &starts, line_number)]
+                .to_string(),
+            next_transaction: transaction_lines
+                .iter()
+                .find(|&&line| line > line_number)
+                .copied()
+                .unwrap_or(0),
+        })
+        .collect()
+}
+
+/// Collects the names of all accounts that are opened in the file
+pub fn open_accounts(beancount_file: &BeancountFile<Decimal>) -> BTreeSet<String> {
+    beancount_file
+        .directives
+        .iter()
+        .filter_map(|d| match &d.content {
+            DirectiveContent::Open(open) => Some(open.account.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// When an account was opened and closed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AccountDates {
+    pub open: Option<NaiveDate>, // `None` if only a close directive was found
+    pub close: Option<NaiveDate>, // `None` while the account is open
+}
+
+/// Adds the dates of the `open` and `close` directives of the file to `dates`
+pub fn collect_account_dates(
+    beancount_file: &BeancountFile<Decimal>,
+    dates: &mut BTreeMap<String, AccountDates>,
+) {
+    for directive in &beancount_file.directives {
+        let date = NaiveDate::from_ymd_opt(
+            directive.date.year.into(),
+            directive.date.month.into(),
+            directive.date.day.into(),
+        );
+        match &directive.content {
+            DirectiveContent::Open(open) => {
+                dates.entry(open.account.to_string()).or_default().open = date;
+            }
+            DirectiveContent::Close(close) => {
+                dates.entry(close.account.to_string()).or_default().close = date;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The postings whose account isn't open on the date of the transaction, with the
+/// reason, e.g. `closed on 2023-12-31`. Nothing is reported while the date is invalid.
+pub fn inactive_postings(
+    transaction: &TransactionTui,
+    dates: &BTreeMap<String, AccountDates>,
+) -> Vec<(usize, String)> {
+    let Some(date) = parse_date(&textarea_content(&transaction.metadata_textareas()[0])) else {
+        return Vec::new();
+    };
+    transaction
+        .postings_textareas()
+        .iter()
+        .enumerate()
+        .filter_map(|(index, posting)| {
+            let account = textarea_content(&posting.account_textarea);
+            let dates = dates.get(account.trim())?;
+            let reason = match (dates.open, dates.close) {
+                (Some(open), _) if date < open => format!("opened on {}", open),
+                // postings on the closing day are still allowed
+                (_, Some(close)) if date > close => format!("closed on {}", close),
+                _ => return None,
+            };
+            Some((index, reason))
+        })
+        .collect()
+}
+
+/// An account component with the accounts below it, see `account_tree`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountNode {
+    pub name: String,               // the last component, e.g. `Food`
+    pub account: String,            // the account up to here, e.g. `Expenses:Food`
+    pub children: Vec<AccountNode>, // ordered by name
+}
+
+/// Arranges accounts into a tree by their `:` separated components. Parents that
+/// aren't accounts themselves, like `Expenses`, are added.
+pub fn account_tree(accounts: &BTreeSet<String>) -> Vec<AccountNode> {
+    let mut roots: Vec<AccountNode> = Vec::new();
+    for account in accounts {
+        let mut level = &mut roots;
+        let mut path = String::new();
+        for component in account.split(':') {
+            if !path.is_empty() {
+                path.push(':');
+            }
+            path.push_str(component);
+            let position = match level.binary_search_by(|node| node.name.as_str().cmp(component)) {
+                Ok(position) => position,
+                Err(position) => {
+                    let node = AccountNode {
+                        name: component.to_string(),
+                        account: path.clone(),
+                        children: Vec::new(),
+                    };
+                    level.insert(position, node);
+                    position
+                }
+            };
+            level = &mut level[position].children;
+        }
+    }
+    roots
+}
+
+/// Maps every payee to the accounts used in its transactions, most frequently used first
+pub fn payee_accounts(transactions: &[TransactionTui]) -> HashMap<String, Vec<String>> {
+    let mut counts: HashMap<&str, BTreeMap<String, usize>> = HashMap::new();
+    for transaction in transactions {
+        let Some(payee) = transaction.directive.payee.as_deref() else {
+            continue;
+        };
+        let payee_counts = counts.entry(payee).or_default();
+        for posting in &transaction.directive.postings {
+            *payee_counts.entry(posting.account.to_string()).or_default() += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .map(|(payee, account_counts)| {
+            let mut accounts: Vec<(String, usize)> = account_counts.into_iter().collect();
+            accounts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+            (
+                payee.to_string(),
+                accounts.into_iter().map(|(account, _)| account).collect(),
+            )
+        })
+        .collect()
+}
+
+/// The currency used by the most postings, the alphabetically first one on a tie
+pub fn most_common_currency(transactions: &[TransactionTui]) -> Option<String> {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for transaction in transactions {
+        for amount in transaction
+            .directive
+            .postings
+            .iter()
+            .flat_map(|p| &p.amount)
+        {
+            *counts.entry(amount.currency.to_string()).or_default() += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map(|(currency, _)| currency)
+}
+
+/// Fixes the capitalization of an account name.
+///
+/// An account matching exactly one known account case-insensitively takes over its
+/// spelling, if several match it is kept as typed. Otherwise each component is
+/// matched case-insensitively against the known accounts sharing the same parent,
+/// taking over the known spelling. Components without a match get their first
+/// letter uppercased.
+pub fn normalize_account(account: &str, known_accounts: &BTreeSet<String>) -> String {
+    let account = account.trim();
+    let mut candidates = known_accounts
+        .iter()
+        .filter(|known| known.eq_ignore_ascii_case(account));
+    match (candidates.next(), candidates.next()) {
+        (Some(known), None) => return known.clone(),
+        (Some(_), Some(_)) => return account.to_string(),
+        _ => {}
+    }
+    let components: Vec<&str> = account.split(':').collect();
+    let mut normalized: Vec<String> = Vec::with_capacity(components.len());
+    for (depth, component) in components.iter().enumerate() {
+        let known_component = known_accounts.iter().find_map(|known| {
+            let known_components: Vec<&str> = known.split(':').collect();
+            let same_parent = known_components.len() > depth
+                && known_components[..depth]
+                    .iter()
+                    .zip(&normalized)
+                    .all(|(k, n)| k == n);
+            (same_parent && known_components[depth].eq_ignore_ascii_case(component))
+                .then(|| known_components[depth].to_string())
+        });
+        normalized.push(known_component.unwrap_or_else(|| capitalize(component)));
+    }
+    normalized.join(":")
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Returns the content of a textarea with all lines joined into a single one
+pub fn textarea_content(textarea: &TextArea) -> String {
+    textarea.lines().join(" ")
+}
+
+/// Replaces the whole content of a textarea, keeping its styling
+pub fn set_textarea_content(textarea: &mut TextArea, content: &str) {
+    textarea.select_all();
+    textarea.cut();
+    textarea.insert_str(content);
+}
+
+/// A posting as plain text, independent of the text areas it is edited in
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PlainPosting {
+    pub flag: Option<char>, // e.g. `!` on a posting that needs attention
+    pub account: String,
+    pub amount: String, // empty for a posting whose amount is inferred
+    pub currency: String,
+    pub cost: Option<String>, // written inside braces, e.g. `1.10 USD, 2024-01-01`
+    pub price: Option<Price>, // written after the cost
+    pub comment: String,      // written after the posting, left out if empty
+    pub lines: Vec<String>,   // metadata and comment lines below the posting
+}
+
+impl PlainPosting {
+    /// A posting without flag
+    pub fn new(
+        account: impl Into<String>,
+        amount: impl Into<String>,
+        currency: impl Into<String>,
+    ) -> Self {
+        Self {
+            flag: None,
+            account: account.into(),
+            amount: amount.into(),
+            currency: currency.into(),
+            cost: None,
+            price: None,
+            comment: String::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    /// Whether there is nothing to write for the posting
+    pub fn is_empty(&self) -> bool {
+        self.flag.is_none()
+            && [&self.account, &self.amount, &self.currency, &self.comment]
+                .iter()
+                .all(|text| text.trim().is_empty())
+            && self.cost.is_none()
+            && self.price.is_none()
+            && self.lines.is_empty()
+    }
+}
+
+/// A transaction as plain text, what [`format_plain_transaction`] formats
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PlainTransaction {
+    pub date: String, // `YYYY-MM-DD`
+    pub flag: String, // an empty flag is formatted as `*`
+    pub payee: String,
+    pub narration: String,
+    pub tags: Vec<String>,     // written after the narration, without `#`
+    pub links: Vec<String>,    // written after the tags, without `^`
+    pub comment: String,       // written after the header line, left out if empty
+    pub comments: Vec<String>, // lines before the header line, each starting with `;`
+    pub lines: Vec<String>,    // metadata and comment lines between the header and the postings
+    pub postings: Vec<PlainPosting>,
+}
+
+impl PlainTransaction {
+    /// A `*` transaction without comment and postings
+    pub fn new(
+        date: impl Into<String>,
+        payee: impl Into<String>,
+        narration: impl Into<String>,
+    ) -> Self {
+        Self {
+            date: date.into(),
+            flag: "*".to_string(),
+            payee: payee.into(),
+            narration: narration.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Trims every field, collapses runs of spaces in the payee and narration and
+    /// uppercases the currencies. Returns whether anything changed.
+    pub fn clean(&mut self) -> bool {
+        let before = self.clone();
+        for field in [&mut self.date, &mut self.flag, &mut self.comment]
+            .into_iter()
+            .chain(&mut self.comments)
+        {
+            *field = field.trim().to_string();
+        }
+        self.payee = collapse_spaces(&self.payee);
+        self.narration = collapse_spaces(&self.narration);
+        for posting in &mut self.postings {
+            // account names are checked elsewhere, only stray whitespace is removed here
+            posting.account = posting.account.trim().to_string();
+            posting.amount = posting.amount.trim().to_string();
+            posting.currency = posting.currency.trim().to_uppercase();
+        }
+        *self != before
+    }
+}
+
+/// Quotes `text` as a beancount string, escaping backslashes and quotes
+pub fn quote_string(text: &str) -> String {
+    quote_string_with(text, '"')
+}
+
+/// Like [`quote_string`] with another quote character
+fn quote_string_with(text: &str, quote: char) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace(quote, &format!("\\{}", quote));
+    format!("{}{}{}", quote, escaped, quote)
+}
+
+/// Trims `text` and every line of it and replaces runs of whitespace within a line by one space
+fn collapse_spaces(text: &str) -> String {
+    text.trim()
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Cleans the fields of `transaction` like [`PlainTransaction::clean`], returns whether
+/// anything changed
+pub fn clean_transaction(transaction: &mut TransactionTui) -> bool {
+    let mut plain = PlainTransaction::from(&*transaction);
+    if !plain.clean() {
+        return false;
+    }
+    let metadata = [
+        plain.date,
+        plain.flag,
+        plain.payee,
+        plain.narration,
+        plain.comment,
+    ];
+    for (textarea, content) in transaction
+        .metadata_textareas_mut()
+        .iter_mut()
+        .zip(&metadata)
+    {
+        if textarea_content(textarea) != *content {
+            set_textarea_content(textarea, content);
+        }
+    }
+    transaction.comments = plain.comments;
+    for (posting, plain) in transaction
+        .postings_textareas_mut()
+        .iter_mut()
+        .zip(&plain.postings)
+    {
+        for (textarea, content) in [
+            (&mut posting.account_textarea, &plain.account),
+            (&mut posting.amount_textarea, &plain.amount),
+            (&mut posting.currency_textarea, &plain.currency),
+        ] {
+            if textarea_content(textarea) != *content {
+                set_textarea_content(textarea, content);
+            }
+        }
+    }
+    true
+}
+
+impl From<&TransactionTui<'_>> for PlainTransaction {
+    fn from(transaction: &TransactionTui) -> Self {
+        let [date, mut flag, payee, narration, comment] = transaction
+            .metadata_textareas()
+            .each_ref()
+            .map(textarea_content);
+        // the `txn` keyword is shown as `*`, it is written again unless the flag was changed
+        if transaction.directive.flag.is_none() && flag == "*" {
+            flag = "txn".to_string();
+        }
+        let postings = transaction
+            .postings_textareas()
+            .iter()
+            .map(|posting| PlainPosting {
+                flag: posting.flag,
+                cost: posting.cost.clone(),
+                price: posting.price.clone(),
+                comment: posting.comment.clone().unwrap_or_default(),
+                lines: posting.lines.clone(),
+                ..PlainPosting::new(
+                    textarea_content(&posting.account_textarea),
+                    textarea_content(&posting.amount_textarea),
+                    textarea_content(&posting.currency_textarea),
+                )
+            })
+            .collect();
+        // the parser keeps them in sets, sorting makes the written order stable
+        let mut tags: Vec<String> = transaction
+            .directive
+            .tags
+            .iter()
+            .map(|tag| tag.as_str().to_string())
+            .collect();
+        tags.sort();
+        let mut links: Vec<String> = transaction
+            .directive
+            .links
+            .iter()
+            .map(|link| link.as_str().to_string())
+            .collect();
+        links.sort();
+        Self {
+            date,
+            flag,
+            payee,
+            narration,
+            tags,
+            links,
+            comment,
+            comments: transaction.comments.clone(),
+            lines: transaction.lines.clone(),
+            postings,
+        }
+    }
+}
+
+/// How the postings of formatted transactions are laid out, the `[format]` config section
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FormatOptions {
+    /// Spaces before every posting
+    pub indent: usize,
+    /// The fewest spaces between an account and its amount
+    pub min_gap: usize,
+    /// Column the aligned amounts end at, 0 puts them right after the longest account
+    pub amount_column: usize,
+    /// Right-align the amounts so the currencies line up, otherwise every amount
+    /// follows its account
+    pub align_currencies: bool,
+    /// Sort the written transactions by date under a header comment for every month,
+    /// only when all transactions are written, not when saving in place
+    pub group_by_month: bool,
+    /// The header comment of a month as a `strftime` format, e.g. `;; %B %Y`
+    pub month_header: String,
+    /// Quotes around the payee and narration, beancount only reads `"`
+    pub quote: char,
+    /// Write the currency before the amount, e.g. `USD 5`, beancount can't read it
+    pub currency_first: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            indent: 4,
+            min_gap: 2,
+            amount_column: 0,
+            align_currencies: true,
+            group_by_month: false,
+            month_header: ";; %B %Y".to_string(),
+            quote: '"',
+            currency_first: false,
+        }
+    }
+}
+
+impl FormatOptions {
+    /// Explains why the written transactions won't parse as beancount, if they won't
+    pub fn parse_warning(&self) -> Option<String> {
+        let mut settings = Vec::new();
+        if self.quote != '"' {
+            settings.push(format!("format.quote = '{}'", self.quote));
+        }
+        if self.currency_first {
+            settings.push("format.currency_first".to_string());
+        }
+        (!settings.is_empty()).then(|| {
+            format!(
+                "With {} the written transactions can't be read by beancount",
+                settings.join(" and ")
+            )
+        })
+    }
+}
+
+/// Header of the transactions without a valid date when grouping by month
+pub const UNPARSED_HEADER: &str = ";; unparsed";
+
+/// Wether `format` is a `strftime` format chrono can write
+pub fn is_valid_date_format(format: &str) -> bool {
+    StrftimeItems::new(format).all(|item| item != Item::Error)
+}
+
+/// Formats a transaction as it would appear in a beancount file, with its fields cleaned
+/// like [`PlainTransaction::clean`]
+pub fn format_transaction(transaction: &TransactionTui) -> String {
+    format_transaction_with(transaction, &FormatOptions::default())
+}
+
+/// Like [`format_transaction`], with the postings laid out as `options` say
+pub fn format_transaction_with(transaction: &TransactionTui, options: &FormatOptions) -> String {
+    let mut plain = PlainTransaction::from(transaction);
+    plain.clean();
+    format_plain_transaction_with(&plain, options)
+}
+
+/// Formats a transaction as it would appear in a beancount file, the amounts
+/// right-aligned in one column
+pub fn format_plain_transaction(transaction: &PlainTransaction) -> String {
+    format_plain_transaction_with(transaction, &FormatOptions::default())
+}
+
+/// Like [`format_plain_transaction`], with the postings laid out as `options` say
+pub fn format_plain_transaction_with(
+    transaction: &PlainTransaction,
+    options: &FormatOptions,
+) -> String {
+    // an emptied flag field means a normal transaction
+    let flag = match transaction.flag.trim() {
+        "" => "*",
+        flag => flag,
+    };
+    let narration = quote_string_with(&transaction.narration, options.quote);
+    // without payee beancount takes a single string as the narration
+    let mut header = if transaction.payee.trim().is_empty() {
+        format!("{} {} {}", transaction.date, flag, narration)
+    } else {
+        format!(
+            "{} {} {} {}",
+            transaction.date,
+            flag,
+            quote_string_with(&transaction.payee, options.quote),
+            narration
+        )
+    };
+    for tag in &transaction.tags {
+        header.push_str(&format!(" #{}", tag));
+    }
+    for link in &transaction.links {
+        header.push_str(&format!(" ^{}", link));
+    }
+    if !transaction.comment.trim().is_empty() {
+        header.push_str(&format!(" ; {}", transaction.comment.trim()));
+    }
+    let indent = " ".repeat(options.indent);
+    let mut lines = transaction.comments.clone();
+    lines.push(header);
+    lines.extend(
+        transaction
+            .lines
+            .iter()
+            .map(|line| format!("{}{}", indent, line)),
+    );
+    // e.g. the counter posting of an import nobody filled in, it would be an empty line
+    let postings: Vec<&PlainPosting> = transaction
+        .postings
+        .iter()
+        .filter(|posting| !posting.is_empty())
+        .collect();
+    let accounts: Vec<String> = postings
+        .iter()
+        .map(|posting| match posting.flag {
+            Some(flag) => format!("{} {}", flag, posting.account),
+            None => posting.account.clone(),
+        })
+        .collect();
+    // the aligned part, the currency follows it unless it comes first
+    let amounts: Vec<String> = postings
+        .iter()
+        .map(|posting| match options.currency_first {
+            true if !posting.amount.is_empty() => {
+                format!("{} {}", posting.currency, posting.amount)
+                    .trim_start()
+                    .to_string()
+            }
+            _ => posting.amount.clone(),
+        })
+        .collect();
+    // measured in terminal cells, so accounts with wide characters don't shift the column
+    let with_amount = || {
+        accounts
+            .iter()
+            .zip(&amounts)
+            .filter(|(_, amount)| !amount.is_empty())
+    };
+    let account_width = with_amount()
+        .map(|(account, _)| account.width())
+        .max()
+        .unwrap_or(0);
+    let amount_width = with_amount()
+        .map(|(_, amount)| amount.width())
+        .max()
+        .unwrap_or(0);
+    // where the aligned amounts end, counted from the start of the line
+    let amount_end = (options.indent + account_width + options.min_gap + amount_width)
+        .max(options.amount_column);
+    for ((account, amount), posting) in accounts.iter().zip(&amounts).zip(postings) {
+        let line = if amount.is_empty() {
+            format!("{}{}   {}", indent, account, posting.currency)
+        } else {
+            let gap = if options.align_currencies {
+                amount_end - options.indent - account.width() - amount.width()
+            } else {
+                options.min_gap
+            };
+            let currency = if options.currency_first {
+                ""
             } else {
-                return false;
+                &posting.currency
+            };
+            format!(
+                "{}{}{}{} {}",
+                indent,
+                account,
+                " ".repeat(gap),
+                amount,
+                currency
+            )
+        };
+        let mut line = line.trim_end().to_string();
+        if let Some(cost) = &posting.cost {
+            line.push_str(&format!(" {{{}}}", cost));
+        }
+        if let Some(price) = &posting.price {
+            line.push_str(&format!(" {}", price));
+        }
+        if !posting.comment.trim().is_empty() {
+            line.push_str(&format!(" ; {}", posting.comment.trim()));
+        }
+        lines.push(line);
+        // deeper than the posting, so the metadata belongs to it
+        lines.extend(
+            posting
+                .lines
+                .iter()
+                .map(|below| format!("{}{}{}", indent, indent, below)),
+        );
+    }
+    lines.join("\n")
+}
+
+/// Formats all transactions, separated by empty lines
+pub fn format_transactions(transactions: &[TransactionTui], options: &FormatOptions) -> String {
+    format_transactions_with_context(transactions, &[], options)
+}
+
+/// Like [`format_transactions`], every directive of `context` is written right before
+/// the first transaction that was read after it from the same file
+pub fn format_transactions_with_context(
+    transactions: &[TransactionTui],
+    context: &[ContextDirective],
+    options: &FormatOptions,
+) -> String {
+    let mut output = format_group(transactions.iter().collect(), context, options);
+    output.push('\n');
+    output
+}
+
+/// Formats the transactions separated by empty lines, sorted and under month headers
+/// with `group_by_month`, the directives of `context` in between
+fn format_group(
+    transactions: Vec<&TransactionTui>,
+    context: &[ContextDirective],
+    options: &FormatOptions,
+) -> String {
+    let mut sections: Vec<String> = Vec::new();
+    // the line of the transaction each directive goes before, the closest one read after
+    // it, so the directives keep their place when transactions are sorted or deleted
+    let mut pending: Vec<(&ContextDirective, Option<u32>)> = context
+        .iter()
+        .map(|directive| {
+            let next = transactions
+                .iter()
+                .filter(|t| t.source == directive.source && t.line_number > directive.line_number)
+                .map(|t| t.line_number)
+                .min();
+            (directive, next)
+        })
+        .collect();
+    let mut push = |sections: &mut Vec<String>, transaction: &TransactionTui| {
+        pending.retain(|(directive, next)| {
+            let before =
+                directive.source == transaction.source && *next == Some(transaction.line_number);
+            if before {
+                sections.push(directive.text.clone());
+            }
+            !before
+        });
+        sections.push(format_transaction_with(transaction, options));
+    };
+    if !options.group_by_month {
+        for transaction in transactions {
+            push(&mut sections, transaction);
+        }
+        sections.extend(pending.iter().map(|(directive, _)| directive.text.clone()));
+        return sections.join("\n\n");
+    }
+    let mut dated: Vec<(NaiveDate, &TransactionTui)> = Vec::new();
+    let mut unparsed = Vec::new();
+    for transaction in transactions {
+        match parse_date(&textarea_content(&transaction.metadata_textareas()[0])) {
+            Some(date) => dated.push((date, transaction)),
+            None => unparsed.push(transaction),
+        }
+    }
+    // stable, so transactions of the same day keep their order
+    dated.sort_by_key(|(date, _)| *date);
+    let mut month = None;
+    for (date, transaction) in dated {
+        if month != Some((date.year(), date.month())) {
+            month = Some((date.year(), date.month()));
+            sections.push(date.format(&options.month_header).to_string());
+        }
+        push(&mut sections, transaction);
+    }
+    if !unparsed.is_empty() {
+        sections.push(UNPARSED_HEADER.to_string());
+        for transaction in unparsed {
+            push(&mut sections, transaction);
+        }
+    }
+    sections.extend(pending.iter().map(|(directive, _)| directive.text.clone()));
+    sections.join("\n\n")
+}
+
+/// Formats the transactions grouped by the file they were read from, each group
+/// starting with a comment naming the file
+pub fn format_transactions_by_source(
+    transactions: &[TransactionTui],
+    context: &[ContextDirective],
+    options: &FormatOptions,
+) -> String {
+    let mut sources: Vec<&Path> = Vec::new();
+    for transaction in transactions {
+        if !sources.contains(&transaction.source.as_path()) {
+            sources.push(&transaction.source);
+        }
+    }
+    sources
+        .into_iter()
+        .map(|source| {
+            let group = transactions.iter().filter(|t| t.source == source).collect();
+            let context: Vec<ContextDirective> = context
+                .iter()
+                .filter(|directive| directive.source == source)
+                .cloned()
+                .collect();
+            format!(
+                "; {}\n\n{}\n",
+                source.display(),
+                format_group(group, &context, options)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Wether `currency` is a valid beancount commodity, e.g. `USD`, `VBMPX` or `EUR.CASH`
+pub fn is_valid_currency(currency: &str) -> bool {
+    let chars: Vec<char> = currency.chars().collect();
+    match chars.as_slice() {
+        [] => false,
+        [single] => single.is_ascii_uppercase(),
+        [first, middle @ .., last] => {
+            chars.len() <= 24
+                && first.is_ascii_uppercase()
+                && (last.is_ascii_uppercase() || last.is_ascii_digit())
+                && middle
+                    .iter()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || "'._-".contains(*c))
+        }
+    }
+}
+
+/// Parses the content of an amount field, `None` if it isn't a valid decimal
+pub fn parse_amount(amount: &str) -> Option<Decimal> {
+    Decimal::from_str(amount.trim()).ok()
+}
+
+/// The amount with the opposite sign and the same decimal places, `None` if it isn't a
+/// valid decimal
+pub fn negate_amount(amount: &str) -> Option<String> {
+    let amount = parse_amount(amount)?;
+    // rust_decimal keeps the sign of zero, `-0.00` isn't worth writing
+    if amount.is_zero() {
+        return Some(amount.abs().to_string());
+    }
+    Some((-amount).to_string())
+}
+
+/// Splits an amount into two halves with its decimal places, the first half gets the
+/// remainder of an odd amount. `None` if it isn't a valid decimal.
+pub fn split_amount(amount: &str) -> Option<(String, String)> {
+    let amount = parse_amount(amount)?;
+    let scale = amount.scale();
+    let mut half = (amount / Decimal::TWO).trunc_with_scale(scale);
+    half.rescale(scale);
+    let mut rest = amount - half;
+    rest.rescale(scale);
+    Some((rest.to_string(), half.to_string()))
+}
+
+/// Whether an amount field can be saved, it is either empty or a valid decimal
+pub fn is_valid_amount(amount: &str) -> bool {
+    amount.trim().is_empty() || parse_amount(amount).is_some()
+}
+
+/// The index of the first posting whose amount isn't a valid decimal
+pub fn first_invalid_amount(transaction: &TransactionTui) -> Option<usize> {
+    transaction
+        .postings_textareas()
+        .iter()
+        .position(|posting| !is_valid_amount(&textarea_content(&posting.amount_textarea)))
+}
+
+/// What `units` of `posting` count in the balance. A posting with a cost or price counts
+/// with that value in its currency, e.g. `10 EUR @ 1.10 USD` as `11.00 USD`.
+fn posting_weight(units: Decimal, currency: &str, posting: &PostingTui) -> (Decimal, String) {
+    let cost = posting.cost.as_deref().and_then(|cost| {
+        // the cost may also hold a date or a label, the amount comes first
+        let mut parts = cost.split(',').next()?.split_whitespace();
+        let amount = parse_amount(parts.next()?)?;
+        Some((units * amount, parts.next()?.to_string()))
+    });
+    let price = || {
+        let price = posting.price.as_ref()?;
+        let amount = parse_amount(&price.amount)?;
+        let weight = match (price.total, units.is_sign_negative()) {
+            (false, _) => units * amount,
+            (true, false) => amount,
+            (true, true) => -amount,
+        };
+        Some((weight, price.currency.clone()))
+    };
+    cost.or_else(price)
+        .unwrap_or_else(|| (units, currency.to_string()))
+}
+
+/// The sums of the positive (debit) and negative (credit) posting amounts per currency,
+/// in the currency the amounts are written in. Postings with an empty or unparseable
+/// amount or without currency are skipped.
+pub fn posting_totals(transaction: &TransactionTui) -> BTreeMap<String, (Decimal, Decimal)> {
+    let mut totals: BTreeMap<String, (Decimal, Decimal)> = BTreeMap::new();
+    for posting in transaction.postings_textareas() {
+        let currency = textarea_content(&posting.currency_textarea)
+            .trim()
+            .to_string();
+        let Some(amount) = parse_amount(&textarea_content(&posting.amount_textarea)) else {
+            continue;
+        };
+        if currency.is_empty() {
+            continue;
+        }
+        let (debit, credit) = totals.entry(currency).or_default();
+        if amount.is_sign_negative() {
+            *credit += amount;
+        } else {
+            *debit += amount;
+        }
+    }
+    totals
+}
+
+/// Computes what is missing for the postings to sum up to zero, per currency.
+///
+/// A currency maps to `None` if its residual is absorbed by exactly one posting
+/// with an empty amount, either of the same currency or without a currency.
+/// Postings with a cost or price count in the currency of it, see `posting_weight`.
+/// Postings with unparseable amounts are ignored.
+pub fn balance_residual(transaction: &TransactionTui) -> BTreeMap<String, Option<Decimal>> {
+    let mut sums: BTreeMap<String, Decimal> = BTreeMap::new();
+    let mut elided: BTreeMap<String, usize> = BTreeMap::new();
+    let mut elided_without_currency = 0;
+    for posting in transaction.postings_textareas() {
+        let amount = textarea_content(&posting.amount_textarea);
+        let currency = textarea_content(&posting.currency_textarea)
+            .trim()
+            .to_string();
+        if amount.trim().is_empty() {
+            if currency.is_empty() {
+                elided_without_currency += 1;
+            } else {
+                sums.entry(currency.clone()).or_default();
+                *elided.entry(currency).or_default() += 1;
             }
+        } else if let Some(amount) = parse_amount(&amount) {
+            let (weight, currency) = posting_weight(amount, &currency, posting);
+            *sums.entry(currency).or_default() += weight;
+        }
+    }
+    sums.into_iter()
+        .map(|(currency, sum)| {
+            let n_elided = elided.get(&currency).copied().unwrap_or(0);
+            let absorbed = n_elided == 1 || (n_elided == 0 && elided_without_currency == 1);
+            (currency, if absorbed { None } else { Some(sum) })
+        })
+        .collect()
+}
+
+/// The amount the posting at `index` needs so the postings in its currency sum up to
+/// zero, with that currency. A posting without currency takes the only currency of the
+/// other amounts. Other postings count with their cost or price, see `posting_weight`.
+/// `None` without a single currency, if one of the amounts isn't a valid decimal or if
+/// the posting itself has a cost or price.
+pub fn balancing_amount(transaction: &TransactionTui, index: usize) -> Option<(Decimal, String)> {
+    let postings = transaction.postings_textareas();
+    let posting = &postings[index];
+    if posting.cost.is_some() || posting.price.is_some() {
+        return None;
+    }
+    let mut weights = Vec::new();
+    for (i, other) in postings.iter().enumerate() {
+        let amount = textarea_content(&other.amount_textarea);
+        if i == index || amount.trim().is_empty() {
+            continue;
+        }
+        let currency = textarea_content(&other.currency_textarea);
+        weights.push(posting_weight(
+            parse_amount(&amount)?,
+            currency.trim(),
+            other,
+        ));
+    }
+    let mut currency = textarea_content(&posting.currency_textarea)
+        .trim()
+        .to_string();
+    if currency.is_empty() {
+        let currencies: BTreeSet<&str> = weights.iter().map(|(_, c)| c.as_str()).collect();
+        match currencies.into_iter().collect::<Vec<_>>()[..] {
+            [only] => currency = only.to_string(),
+            _ => return None,
+        }
+    }
+    let sum: Decimal = weights
+        .iter()
+        .filter(|(_, c)| *c == currency)
+        .map(|(weight, _)| weight)
+        .sum();
+    // rust_decimal keeps the sign of zero
+    let amount = if sum.is_zero() { sum.abs() } else { -sum };
+    Some((amount, currency))
+}
+
+/// Returns a description of every problem that would make the transaction invalid
+pub fn validate_transaction(transaction: &TransactionTui) -> Vec<String> {
+    let mut problems = Vec::new();
+    let [date, flag, ..] = &transaction.metadata_textareas();
+    let date = textarea_content(date);
+    if parse_date(&date).is_none() {
+        problems.push(format!("invalid date '{}'", date));
+    }
+    let flag = textarea_content(flag);
+    if !flag.trim().is_empty() && !is_valid_flag(flag.trim()) {
+        problems.push(format!("invalid flag '{}'", flag));
+    }
+    for (index, posting) in transaction.postings_textareas().iter().enumerate() {
+        if textarea_content(&posting.account_textarea)
+            .trim()
+            .is_empty()
+        {
+            problems.push(format!("posting {} has no account", index + 1));
+        }
+        let amount = textarea_content(&posting.amount_textarea);
+        if !is_valid_amount(&amount) {
+            problems.push(format!(
+                "posting {} has an invalid amount '{}'",
+                index + 1,
+                amount
+            ));
+        }
+    }
+    for (currency, residual) in balance_residual(transaction) {
+        if let Some(residual) = residual.filter(|r| !r.is_zero()) {
+            problems.push(format!("unbalanced by {} {}", residual, currency));
+        }
+    }
+    problems
+}
+
+/// A text field of a transaction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransactionField {
+    Metadata(usize),              // index into `metadata_textareas`
+    Posting(usize, PostingField), // index into `postings_textareas`
+}
+
+/// The fields that need attention: invalid ones, empty accounts, missing currencies,
+/// the amount closing an unbalanced currency and the flag of a `!` transaction.
+/// Metadata comes first, then the postings in order.
+pub fn issue_fields(transaction: &TransactionTui) -> Vec<TransactionField> {
+    let mut fields = Vec::new();
+    let [date, flag, ..] = &transaction.metadata_textareas();
+    if parse_date(&textarea_content(date)).is_none() {
+        fields.push(TransactionField::Metadata(0));
+    }
+    let flag = textarea_content(flag);
+    if flag.trim() == "!" || (!flag.trim().is_empty() && !is_valid_flag(flag.trim())) {
+        fields.push(TransactionField::Metadata(1));
+    }
+    let postings = transaction.postings_textareas();
+    let unbalanced: Vec<String> = balance_residual(transaction)
+        .into_iter()
+        .filter(|(_, residual)| residual.is_some_and(|r| !r.is_zero()))
+        .map(|(currency, _)| currency)
+        .collect();
+    for (index, posting) in postings.iter().enumerate() {
+        let account = textarea_content(&posting.account_textarea);
+        let amount = textarea_content(&posting.amount_textarea);
+        let currency = textarea_content(&posting.currency_textarea);
+        if account.trim().is_empty() {
+            fields.push(TransactionField::Posting(index, PostingField::Account));
+        }
+        // the last posting in an unbalanced currency is the one to fix, a missing
+        // currency is an issue of its own
+        let closes_unbalanced = !currency.trim().is_empty()
+            && unbalanced.contains(&currency.trim().to_string())
+            && !postings[index + 1..]
+                .iter()
+                .any(|p| textarea_content(&p.currency_textarea).trim() == currency.trim());
+        if !is_valid_amount(&amount) || closes_unbalanced {
+            fields.push(TransactionField::Posting(index, PostingField::Amount));
+        }
+        let currency = currency.trim();
+        if (currency.is_empty() && !amount.trim().is_empty())
+            || (!currency.is_empty() && !is_valid_currency(currency))
+        {
+            fields.push(TransactionField::Posting(index, PostingField::Currency));
+        }
+    }
+    fields
+}
+
+/// Wether `account` was opened. With `allow_sub_accounts` the sub-accounts of an
+/// opened account count as opened too, e.g. `Expenses:Food:Bakery` for `Expenses:Food`.
+pub fn is_account_opened(
+    account: &str,
+    opened: &BTreeSet<String>,
+    allow_sub_accounts: bool,
+) -> bool {
+    if opened.contains(account) {
+        return true;
+    }
+    allow_sub_accounts
+        && account
+            .match_indices(':')
+            .any(|(index, _)| opened.contains(&account[..index]))
+}
+
+/// Indexes of the postings whose account was not opened, postings without account are skipped
+pub fn unopened_postings(
+    transaction: &TransactionTui,
+    opened: &BTreeSet<String>,
+    allow_sub_accounts: bool,
+) -> Vec<usize> {
+    transaction
+        .postings_textareas()
+        .iter()
+        .enumerate()
+        .filter(|(_, posting)| {
+            let account = textarea_content(&posting.account_textarea);
+            let account = account.trim();
+            !account.is_empty() && !is_account_opened(account, opened, allow_sub_accounts)
         })
+        .map(|(index, _)| index)
         .collect()
 }
@@ -0,0 +1,83 @@
+use chrono::{Datelike, Days, NaiveDate};
+
+use crate::utils::shift_date;
+
+/// State of the date picker overlay
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Calendar {
+    pub selected: NaiveDate,
+}
+
+impl Calendar {
+    pub fn new(selected: NaiveDate) -> Self {
+        Self { selected }
+    }
+
+    /// Moves the selection by days or months, it stays put at the end of the supported range
+    pub fn shift(&mut self, amount: i32, by_month: bool) {
+        if let Some(date) = shift_date(self.selected, amount, by_month) {
+            self.selected = date;
+        }
+    }
+
+    /// Title of the shown month, e.g. `March 2024`
+    pub fn title(&self) -> String {
+        self.selected.format("%B %Y").to_string()
+    }
+
+    /// The weeks of the selected month from Monday to Sunday, days of the
+    /// neighbouring months are `None`
+    pub fn weeks(&self) -> Vec<[Option<NaiveDate>; 7]> {
+        let first = self
+            .selected
+            .with_day(1)
+            .expect("every month has a first day");
+        let mut weeks = Vec::new();
+        let mut week = [None; 7];
+        let mut day = Some(first);
+        while let Some(date) = day.filter(|date| date.month() == first.month()) {
+            let weekday = date.weekday().num_days_from_monday() as usize;
+            week[weekday] = Some(date);
+            if weekday == 6 {
+                weeks.push(week);
+                week = [None; 7];
+            }
+            day = date.checked_add_days(Days::new(1));
+        }
+        if week.iter().any(Option::is_some) {
+            weeks.push(week);
+        }
+        weeks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn weeks_start_on_monday() {
+        // March 2024 starts on a Friday and ends on a Sunday
+        let weeks = Calendar::new(date(2024, 3, 15)).weeks();
+        assert_eq!(weeks.len(), 5);
+        assert_eq!(weeks[0][..4], [None; 4]);
+        assert_eq!(weeks[0][4], Some(date(2024, 3, 1)));
+        assert_eq!(weeks[4][6], Some(date(2024, 3, 31)));
+        // February 2021 fits into exactly four weeks
+        assert_eq!(Calendar::new(date(2021, 2, 1)).weeks().len(), 4);
+    }
+
+    #[test]
+    fn shift_by_days_and_months() {
+        let mut calendar = Calendar::new(date(2024, 1, 31));
+        calendar.shift(1, true);
+        assert_eq!(calendar.selected, date(2024, 2, 29));
+        calendar.shift(-7, false);
+        assert_eq!(calendar.selected, date(2024, 2, 22));
+        assert_eq!(calendar.title(), "February 2024");
+    }
+}
@@ -1,11 +1,110 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
-#[derive(Parser)]
+#[derive(Parser, Default)]
 #[command(version, about, long_about = None)]
 pub struct Args {
-    /// The path to the file to handle(, use - to read from stdin (must not be a tty))
+    /// The file to review, repeat it to review several files in one session
+    #[arg(short, long, required_unless_present = "csv")]
+    pub file: Vec<PathBuf>,
+    /// Read the transactions from a CSV file, mapped by the `[csv]` config section
+    #[arg(long, value_name = "PATH", conflicts_with = "file")]
+    pub csv: Option<PathBuf>,
+    /// The file to write the edited transactions to
+    #[arg(short, long, conflicts_with = "in_place")]
+    pub output: Option<PathBuf>,
+    /// Save edited transactions back into the files they were read from, including
+    /// included files, leaving everything else untouched
+    #[arg(long, conflicts_with = "csv")]
+    pub in_place: bool,
+    /// With --in-place, print which files would change and a diff instead of saving
+    #[arg(long, requires = "in_place")]
+    pub dry_run: bool,
+    /// Run the `:` commands in this file, one per line, save and exit without opening the interface
+    #[arg(long, value_name = "PATH")]
+    pub script: Option<PathBuf>,
+    /// Validate all transactions, print the problems to stderr and exit with 1 if there are
+    /// any, without opening the interface
+    #[arg(long, conflicts_with_all = ["script", "readonly", "in_place", "autosave"])]
+    pub check: bool,
+    /// Only browse the transactions, all editing is disabled
+    #[arg(long, conflicts_with_all = ["script", "autosave"])]
+    pub readonly: bool,
+    /// Save to the output file whenever you leave a transaction you edited
+    #[arg(long, requires = "output")]
+    pub autosave: bool,
+    /// The config file to use instead of the default one
     #[arg(short, long)]
-    pub file: PathBuf,
+    pub config: Option<PathBuf>,
+    /// Currency filled into empty currency fields, overrides the config
+    #[arg(long, visible_alias = "default-currency")]
+    pub currency: Option<String>,
+    /// Spaces before every posting of written transactions, overrides the config
+    #[arg(long, value_name = "N")]
+    pub indent: Option<usize>,
+    /// Column the amounts of written transactions end at, overrides the config
+    #[arg(long, value_name = "N")]
+    pub amount_column: Option<usize>,
+    /// Don't align the amounts of written transactions, overrides the config
+    #[arg(long)]
+    pub no_align: bool,
+    /// Sort the printed transactions by date under a comment for every month,
+    /// overrides the config
+    #[arg(long)]
+    pub group_by_month: bool,
+    /// Write rejected transactions to this file instead of `<input>.rejected.beancount`
+    #[arg(long, value_name = "PATH")]
+    pub rejected: Option<PathBuf>,
+    /// Run this command on the output before writing it, e.g. `bean-check {}`, `{}` is
+    /// the path of a temporary file with the output. A failure blocks the write.
+    #[arg(long, value_name = "CMD")]
+    pub check_cmd: Option<String>,
+    /// Write even if the output doesn't parse or the --check-cmd fails
+    #[arg(long)]
+    pub force: bool,
+    /// Also write the transactions as JSON to this file whenever saving
+    #[arg(long, value_name = "PATH")]
+    pub export_json: Option<PathBuf>,
+    /// Don't warn about postings to accounts that aren't open on the transaction date
+    #[arg(long)]
+    pub no_open_check: bool,
+    /// When to draw with colors, `auto` checks NO_COLOR, TERM and COLORTERM
+    #[arg(long, value_name = "WHEN", value_enum, default_value_t)]
+    pub color: ColorChoice,
+    /// Only use bold, underline and reverse video instead of colors, like `--color never`
+    #[arg(long, conflicts_with = "color")]
+    pub no_color: bool,
+    /// Don't store or resume the review progress
+    #[arg(long)]
+    pub no_session: bool,
+    /// Start at the first transaction without offering to resume, the progress is still stored
+    #[arg(long, conflicts_with = "no_session")]
+    pub no_resume: bool,
+    /// Offer to reload when another program changes the file or one of its includes
+    #[arg(long)]
+    pub watch: bool,
+    /// How many included files are parsed at the same time, 0 uses one per CPU
+    #[arg(short, long, value_name = "N")]
+    pub jobs: Option<usize>,
+}
+
+/// When to draw with colors
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    Always,
+    #[default]
+    Auto,
+    Never,
+}
+
+impl Args {
+    /// The `--color` choice, `never` with `--no-color`
+    pub fn color_choice(&self) -> ColorChoice {
+        if self.no_color {
+            ColorChoice::Never
+        } else {
+            self.color
+        }
+    }
 }
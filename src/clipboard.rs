@@ -0,0 +1,38 @@
+use std::fmt;
+
+use arboard::Clipboard;
+
+/// The system clipboard, opened lazily on first use so that headless
+/// environments only fail once the clipboard is actually needed.
+///
+/// The handle is kept alive for the lifetime of the app because on X11 the
+/// copied content is owned by the process that holds the clipboard.
+#[derive(Default)]
+pub struct SystemClipboard {
+    inner: Option<Clipboard>,
+}
+
+impl fmt::Debug for SystemClipboard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SystemClipboard")
+            .field("open", &self.inner.is_some())
+            .finish()
+    }
+}
+
+impl SystemClipboard {
+    fn get_or_open(&mut self) -> Result<&mut Clipboard, arboard::Error> {
+        if self.inner.is_none() {
+            self.inner = Some(Clipboard::new()?);
+        }
+        Ok(self.inner.as_mut().expect("clipboard was just opened"))
+    }
+
+    pub fn set_text(&mut self, text: String) -> Result<(), arboard::Error> {
+        self.get_or_open()?.set_text(text)
+    }
+
+    pub fn get_text(&mut self) -> Result<String, arboard::Error> {
+        self.get_or_open()?.get_text()
+    }
+}
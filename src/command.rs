@@ -0,0 +1,226 @@
+use crate::{
+    beancount::{textarea_content, TransactionTui},
+    error::BeancountTuiError,
+};
+
+/// A command typed into the `:` command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypedCommand {
+    /// `:w`, saves to the output file
+    Write,
+    /// `:q`, quits but asks about unsaved changes
+    Quit,
+    /// `:q!`, quits without asking
+    ForceQuit,
+    /// `:wq` or `:x`
+    WriteQuit,
+    /// `:goto N` or `:N`, jumps to the N-th transaction
+    Goto(usize),
+    /// `:sort`, orders the transactions by date
+    Sort,
+    /// `:filter account|payee TEXT` limits navigation to matching transactions,
+    /// `:filter` alone shows all transactions again
+    Filter(Option<Filter>),
+    /// `:negate TEXT` flips the sign of every posting whose account contains the text,
+    /// ignoring case
+    Negate(String),
+    /// `:flagall FLAG` sets the flag of every transaction after a confirmation
+    FlagAll(char),
+}
+
+impl TypedCommand {
+    /// Wether the command changes or writes the transactions, these fail in read-only mode
+    pub fn is_mutating(&self) -> bool {
+        matches!(
+            self,
+            TypedCommand::Write
+                | TypedCommand::WriteQuit
+                | TypedCommand::Sort
+                | TypedCommand::Negate(_)
+                | TypedCommand::FlagAll(_)
+        )
+    }
+}
+
+/// Which transactions are visited while navigating
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    /// A posting account contains the text, ignoring case
+    Account(String),
+    /// The payee contains the text, ignoring case
+    Payee(String),
+}
+
+impl Filter {
+    pub fn matches(&self, transaction: &TransactionTui) -> bool {
+        match self {
+            Filter::Account(text) => {
+                let text = text.to_lowercase();
+                transaction.postings_textareas().iter().any(|posting| {
+                    textarea_content(&posting.account_textarea)
+                        .to_lowercase()
+                        .contains(&text)
+                })
+            }
+            Filter::Payee(text) => textarea_content(&transaction.metadata_textareas()[2])
+                .to_lowercase()
+                .contains(&text.to_lowercase()),
+        }
+    }
+
+    /// Short description for the title, e.g. `account Expenses`
+    pub fn describe(&self) -> String {
+        match self {
+            Filter::Account(text) => format!("account {}", text),
+            Filter::Payee(text) => format!("payee {}", text),
+        }
+    }
+}
+
+fn error(message: impl Into<String>) -> BeancountTuiError {
+    BeancountTuiError::Command(message.into())
+}
+
+/// Parses the text of the command line, with or without the leading `:`
+pub fn parse_command(input: &str) -> Result<TypedCommand, BeancountTuiError> {
+    let input = input.trim();
+    let input = input.strip_prefix(':').unwrap_or(input).trim();
+    let (name, argument) = match input.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (input, ""),
+    };
+    let no_argument = |command| {
+        if argument.is_empty() {
+            Ok(command)
+        } else {
+            Err(error(format!("`{}` takes no argument", name)))
+        }
+    };
+    match name {
+        "" => Err(error("empty command")),
+        "w" | "write" => no_argument(TypedCommand::Write),
+        "q" | "quit" => no_argument(TypedCommand::Quit),
+        "q!" | "quit!" => no_argument(TypedCommand::ForceQuit),
+        "wq" | "x" => no_argument(TypedCommand::WriteQuit),
+        "sort" => no_argument(TypedCommand::Sort),
+        "goto" | "g" => parse_position(argument).map(TypedCommand::Goto),
+        "filter" => parse_filter(argument).map(TypedCommand::Filter),
+        "negate" if argument.is_empty() => Err(error("usage: negate ACCOUNT")),
+        "negate" => Ok(TypedCommand::Negate(argument.to_string())),
+        "flagall" => {
+            let mut chars = argument.chars();
+            match (chars.next(), chars.next()) {
+                (Some(flag), None) => Ok(TypedCommand::FlagAll(flag)),
+                _ => Err(error("usage: flagall FLAG")),
+            }
+        }
+        name if name.chars().all(|c| c.is_ascii_digit()) && argument.is_empty() => {
+            parse_position(name).map(TypedCommand::Goto)
+        }
+        name => Err(error(format!("unknown command `{}`", name))),
+    }
+}
+
+fn parse_position(argument: &str) -> Result<usize, BeancountTuiError> {
+    match argument.parse::<usize>() {
+        Ok(position) if position > 0 => Ok(position),
+        _ => Err(error(format!(
+            "expected a transaction number, got `{}`",
+            argument
+        ))),
+    }
+}
+
+fn parse_filter(argument: &str) -> Result<Option<Filter>, BeancountTuiError> {
+    if argument.is_empty() {
+        return Ok(None);
+    }
+    let (field, text) = argument
+        .split_once(char::is_whitespace)
+        .unwrap_or((argument, ""));
+    let text = text.trim().to_string();
+    if text.is_empty() {
+        return Err(error("usage: filter account|payee TEXT"));
+    }
+    match field {
+        "account" => Ok(Some(Filter::Account(text))),
+        "payee" => Ok(Some(Filter::Payee(text))),
+        field => Err(error(format!(
+            "can't filter by `{}`, use account or payee",
+            field
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Result<TypedCommand, String> {
+        parse_command(input).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn parse_simple_commands() {
+        assert_eq!(parse(":w"), Ok(TypedCommand::Write));
+        assert_eq!(parse("write"), Ok(TypedCommand::Write));
+        assert_eq!(parse(":q"), Ok(TypedCommand::Quit));
+        assert_eq!(parse(":q!"), Ok(TypedCommand::ForceQuit));
+        assert_eq!(parse(" :wq "), Ok(TypedCommand::WriteQuit));
+        assert_eq!(parse(":sort"), Ok(TypedCommand::Sort));
+    }
+
+    #[test]
+    fn parse_goto() {
+        assert_eq!(parse(":goto 12"), Ok(TypedCommand::Goto(12)));
+        assert_eq!(parse(":3"), Ok(TypedCommand::Goto(3)));
+        assert!(parse(":goto 0").is_err());
+        assert!(parse(":goto").is_err());
+        assert!(parse(":goto x").is_err());
+    }
+
+    #[test]
+    fn parse_filter() {
+        assert_eq!(
+            parse(":filter account Expenses:Food"),
+            Ok(TypedCommand::Filter(Some(Filter::Account(
+                "Expenses:Food".to_string()
+            ))))
+        );
+        assert_eq!(
+            parse(":filter payee Test Co"),
+            Ok(TypedCommand::Filter(Some(Filter::Payee(
+                "Test Co".to_string()
+            ))))
+        );
+        assert_eq!(parse(":filter"), Ok(TypedCommand::Filter(None)));
+        assert!(parse(":filter account").is_err());
+        assert!(parse(":filter date 2024").is_err());
+    }
+
+    #[test]
+    fn parse_negate() {
+        assert_eq!(
+            parse(":negate Assets:Bank "),
+            Ok(TypedCommand::Negate("Assets:Bank".to_string()))
+        );
+        assert_eq!(parse(":negate"), Err("usage: negate ACCOUNT".to_string()));
+    }
+
+    #[test]
+    fn parse_flagall() {
+        assert_eq!(parse(":flagall !"), Ok(TypedCommand::FlagAll('!')));
+        assert_eq!(parse(":flagall"), Err("usage: flagall FLAG".to_string()));
+        assert_eq!(parse(":flagall **"), Err("usage: flagall FLAG".to_string()));
+    }
+
+    #[test]
+    fn reject_invalid_commands() {
+        assert_eq!(parse(":"), Err("empty command".to_string()));
+        assert_eq!(
+            parse(":frobnicate"),
+            Err("unknown command `frobnicate`".to_string())
+        );
+        assert_eq!(parse(":w now"), Err("`w` takes no argument".to_string()));
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Maximum number of candidates shown in the completion popup
+const MAX_CANDIDATES: usize = 8;
+
+/// State of the account completion popup
+#[derive(Debug, Default)]
+pub struct Completion {
+    pub active: bool,
+    pub candidates: Vec<String>,
+    pub selected: usize,
+}
+
+impl Completion {
+    /// Opens the popup with the accounts matching `query`
+    pub fn open(&mut self, query: &str, known_accounts: &BTreeSet<String>) {
+        self.active = true;
+        self.update(query, known_accounts);
+    }
+
+    /// Filters the candidates again after the query changed
+    pub fn update(&mut self, query: &str, known_accounts: &BTreeSet<String>) {
+        self.candidates = complete_account(query, known_accounts);
+        self.selected = 0;
+    }
+
+    pub fn close(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn select_next(&mut self, forward: bool) {
+        let n_candidates = self.candidates.len();
+        if n_candidates == 0 {
+            return;
+        }
+        self.selected = if forward {
+            (self.selected + 1) % n_candidates
+        } else {
+            (self.selected + n_candidates - 1) % n_candidates
+        };
+    }
+
+    pub fn selected_candidate(&self) -> Option<&String> {
+        self.candidates.get(self.selected)
+    }
+}
+
+/// Returns the known accounts that fuzzy match `query`, best matches first
+pub fn complete_account(query: &str, known_accounts: &BTreeSet<String>) -> Vec<String> {
+    let query: Vec<char> = query.trim().chars().flat_map(char::to_lowercase).collect();
+    let mut scored: Vec<(i64, &String)> = known_accounts
+        .iter()
+        .filter_map(|account| fuzzy_score(&query, account).map(|score| (score, account)))
+        .collect();
+    // stable sort keeps the alphabetical order of equally good matches
+    scored.sort_by_key(|(score, _)| -score);
+    scored
+        .into_iter()
+        .take(MAX_CANDIDATES)
+        .map(|(_, account)| account.clone())
+        .collect()
+}
+
+/// Scores how well the lowercase `query` matches `candidate` as a subsequence,
+/// ignoring case. Returns `None` if not all characters of the query appear in order.
+///
+/// Matches at the start of an account component and consecutive matches score
+/// higher, skipped characters lower the score.
+pub fn fuzzy_score(query: &[char], candidate: &str) -> Option<i64> {
+    let mut score = 0;
+    let mut query_chars = query.iter().peekable();
+    let mut previous_matched = false;
+    let mut previous_char = ':';
+    for c in candidate.chars() {
+        let Some(&&wanted) = query_chars.peek() else {
+            break;
+        };
+        if c.to_lowercase().eq(std::iter::once(wanted)) {
+            score += 1;
+            if previous_char == ':' {
+                score += 10;
+            }
+            if previous_matched {
+                score += 5;
+            }
+            query_chars.next();
+            previous_matched = true;
+        } else {
+            score -= 1;
+            previous_matched = false;
+        }
+        previous_char = c;
+    }
+    query_chars.peek().is_none().then_some(score)
+}
+
+/// Result of expanding an abbreviated account name
+#[derive(Debug, PartialEq, Eq)]
+pub enum Expansion {
+    /// The account could be expanded unambiguously
+    Expanded(String),
+    /// Several known accounts match, the popup should offer them
+    Ambiguous(String),
+    /// Neither an alias nor a known account matched
+    NoMatch,
+}
+
+/// Expands an abbreviated account like `e:food` to `Expenses:Food`.
+///
+/// The first component is replaced if it is one of the `aliases`. Then every
+/// component is expanded to the known account component under the same parent
+/// that it is a case-insensitive prefix of. Unknown components are kept as typed.
+pub fn expand_account(
+    input: &str,
+    aliases: &BTreeMap<String, String>,
+    known_accounts: &BTreeSet<String>,
+) -> Expansion {
+    let mut components: Vec<String> = input.trim().split(':').map(str::to_string).collect();
+    let alias = aliases
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(&components[0]));
+    if let Some((_, expansion)) = alias {
+        components.splice(0..1, expansion.split(':').map(str::to_string));
+    }
+    let mut expanded: Vec<String> = Vec::with_capacity(components.len());
+    for component in &components {
+        let parent = expanded.join(":");
+        let mut matches: BTreeSet<&str> = BTreeSet::new();
+        for known in known_accounts {
+            let rest = if expanded.is_empty() {
+                Some(known.as_str())
+            } else {
+                known
+                    .strip_prefix(parent.as_str())
+                    .and_then(|rest| rest.strip_prefix(':'))
+            };
+            if let Some(known_component) = rest.and_then(|rest| rest.split(':').next()) {
+                if known_component
+                    .to_lowercase()
+                    .starts_with(&component.to_lowercase())
+                {
+                    matches.insert(known_component);
+                }
+            }
+        }
+        let exact = matches.iter().find(|m| m.eq_ignore_ascii_case(component));
+        match (exact, matches.len()) {
+            (Some(exact), _) => expanded.push(exact.to_string()),
+            (None, 1) => expanded.push(matches.first().unwrap().to_string()),
+            (None, 0) => expanded.push(component.clone()),
+            (None, _) => {
+                expanded.push(component.clone());
+                return Expansion::Ambiguous(expanded.join(":"));
+            }
+        }
+    }
+    let expanded = expanded.join(":");
+    if expanded == input.trim() {
+        Expansion::NoMatch
+    } else {
+        Expansion::Expanded(expanded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn known() -> BTreeSet<String> {
+        BTreeSet::from([
+            "Assets:Checking".to_string(),
+            "Expenses:Food:Groceries".to_string(),
+            "Expenses:Food:Restaurants".to_string(),
+            "Expenses:Fuel".to_string(),
+        ])
+    }
+
+    fn aliases() -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("e".to_string(), "Expenses".to_string()),
+            ("ef".to_string(), "Expenses:Food".to_string()),
+        ])
+    }
+
+    #[test]
+    fn expand_alias_and_fix_case() {
+        assert_eq!(
+            expand_account("e:food:gro", &aliases(), &known()),
+            Expansion::Expanded("Expenses:Food:Groceries".to_string())
+        );
+        assert_eq!(
+            expand_account("ef:rest", &aliases(), &known()),
+            Expansion::Expanded("Expenses:Food:Restaurants".to_string())
+        );
+        assert_eq!(
+            expand_account("assets:ch", &aliases(), &known()),
+            Expansion::Expanded("Assets:Checking".to_string())
+        );
+    }
+
+    #[test]
+    fn expand_keeps_unknown_components() {
+        assert_eq!(
+            expand_account("e:travel", &aliases(), &known()),
+            Expansion::Expanded("Expenses:travel".to_string())
+        );
+        assert_eq!(
+            expand_account("Income:Salary", &aliases(), &known()),
+            Expansion::NoMatch
+        );
+    }
+
+    #[test]
+    fn fuzzy_match_abbreviations() {
+        let query: Vec<char> = "exfoores".chars().collect();
+        assert!(fuzzy_score(&query, "Expenses:Food:Restaurants").is_some());
+        assert!(fuzzy_score(&query, "Expenses:Fuel").is_none());
+        assert_eq!(
+            complete_account("exfr", &known()),
+            ["Expenses:Food:Restaurants", "Expenses:Food:Groceries"]
+        );
+        assert_eq!(complete_account("fuel", &known()), ["Expenses:Fuel"]);
+    }
+
+    #[test]
+    fn fuzzy_match_prefers_component_starts() {
+        assert_eq!(
+            complete_account("ef", &known())[..2],
+            ["Expenses:Food:Groceries", "Expenses:Food:Restaurants"]
+        );
+        assert_eq!(complete_account("", &known()).len(), known().len());
+    }
+
+    #[test]
+    fn expand_ambiguous_prefix() {
+        assert_eq!(
+            expand_account("e:f", &aliases(), &known()),
+            Expansion::Ambiguous("Expenses:f".to_string())
+        );
+        assert_eq!(
+            complete_account("Expenses:f", &known()),
+            [
+                "Expenses:Food:Groceries",
+                "Expenses:Food:Restaurants",
+                "Expenses:Fuel"
+            ]
+        );
+    }
+}
@@ -0,0 +1,497 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{eyre::Context, Result};
+use ratatui::crossterm::event::KeyEvent;
+use serde::Deserialize;
+
+use crate::{
+    app::{InputFieldType, METAFIELD_ORDER},
+    beancount::{is_valid_currency, is_valid_date_format, FormatOptions},
+    error::BeancountTuiError,
+    keymap::{matches_key, parse_key, Command},
+};
+
+/// User configuration, read from `$XDG_CONFIG_HOME/beancount-tui/config.toml`
+/// or the file given with `--config`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The flags the flag toggle cycles through, in order
+    pub flags: Vec<String>,
+    /// The characters that can be typed into the flag field
+    pub valid_flags: String,
+    /// How often the app wakes up without input, in milliseconds
+    pub tick_rate_ms: u64,
+    /// How many transactions PageUp and PageDown skip
+    pub page_size: usize,
+    /// The order in which Tab and Ctrl-l/h move through the metadata fields
+    pub metadata_order: Vec<InputFieldType>,
+    /// Currency filled into empty currency fields of new postings and amounts
+    pub default_currency: Option<String>,
+    /// Use the most common currency of the input when `default_currency` isn't set
+    pub infer_currency: bool,
+    /// Abbreviations for the first account component, e.g. `e = "Expenses"`
+    pub aliases: BTreeMap<String, String>,
+    /// Fill the first empty account with the one most often used with the payee
+    pub suggest_accounts: bool,
+    /// Fix the capitalization of an account when leaving its field
+    pub normalize_accounts: bool,
+    /// Focus the first empty or invalid field of a transaction when moving to it
+    pub focus_issues: bool,
+    /// Save after a transaction was accepted with `Command::AcceptAndNext`
+    pub save_on_accept: bool,
+    /// Treat sub-accounts of opened accounts as opened when checking postings
+    pub allow_sub_accounts: bool,
+    /// Let Enter insert line breaks into the narration instead of moving to the next field
+    pub multiline_narration: bool,
+    /// Keys of the commands that aren't bound by default
+    pub keys: KeysConfig,
+    /// How the postings of written transactions are laid out
+    pub format: FormatOptions,
+    /// How the columns of a CSV file read with `--csv` map to transactions
+    pub csv: Option<CsvConfig>,
+}
+
+/// Key combinations written like the labels in the instructions, e.g. `A-s` or `C-S-n`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeysConfig {
+    /// Swaps the payee and the narration
+    pub swap_payee_narration: String,
+    /// Moves the narration into the payee, leaving the narration empty
+    pub narration_to_payee: String,
+}
+
+/// Column mapping for importing bank CSV exports
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CsvConfig {
+    pub date_column: Column,
+    /// `chrono` format of the dates, e.g. `%d.%m.%Y`
+    #[serde(default = "default_date_format")]
+    pub date_format: String,
+    pub amount_column: Column,
+    pub payee_column: Option<Column>,
+    pub narration_column: Option<Column>,
+    /// The account every row is posted to, the counter posting is left empty
+    pub account: String,
+    /// Currency of the amounts, falls back to the global `default_currency`
+    pub default_currency: Option<String>,
+    /// Either `.` or `,`, the other one is ignored as thousands separator
+    #[serde(default = "default_decimal_separator")]
+    pub decimal_separator: char,
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+}
+
+/// A CSV column, either by its header or by its position starting at 0
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+fn default_date_format() -> String {
+    "%Y-%m-%d".to_string()
+}
+
+fn default_decimal_separator() -> char {
+    '.'
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            flags: vec!["*".to_string(), "!".to_string()],
+            valid_flags: "*!PSTCURM".to_string(),
+            tick_rate_ms: 250,
+            page_size: 10,
+            metadata_order: METAFIELD_ORDER.to_vec(),
+            default_currency: None,
+            infer_currency: false,
+            aliases: BTreeMap::new(),
+            suggest_accounts: false,
+            normalize_accounts: true,
+            focus_issues: false,
+            save_on_accept: false,
+            allow_sub_accounts: false,
+            multiline_narration: false,
+            keys: KeysConfig::default(),
+            format: FormatOptions::default(),
+            csv: None,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config from `path`, or from the default location if no path is given.
+    /// A missing config file at the default location results in the default config.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match default_config_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+        let content = fs::read_to_string(&path)
+            .wrap_err_with(|| format!("couldn't read config file {}", path.display()))?;
+        let config: Config = toml::from_str(&content)
+            .wrap_err_with(|| format!("couldn't parse config file {}", path.display()))?;
+        config
+            .validate()
+            .wrap_err_with(|| format!("invalid config file {}", path.display()))?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), BeancountTuiError> {
+        if self.flags.is_empty() {
+            return Err(BeancountTuiError::Config(
+                "`flags` must contain at least one flag".to_string(),
+            ));
+        }
+        if self.tick_rate_ms == 0 {
+            return Err(BeancountTuiError::Config(
+                "`tick_rate_ms` must be greater than 0".to_string(),
+            ));
+        }
+        if self.page_size == 0 {
+            return Err(BeancountTuiError::Config(
+                "`page_size` must be greater than 0".to_string(),
+            ));
+        }
+        if let Some(currency) = self
+            .default_currency
+            .as_ref()
+            .filter(|c| !is_valid_currency(c))
+        {
+            return Err(BeancountTuiError::Config(format!(
+                "invalid default currency '{}'",
+                currency
+            )));
+        }
+        if let Some(alias) = self
+            .aliases
+            .keys()
+            .find(|alias| alias.is_empty() || alias.contains(':'))
+        {
+            return Err(BeancountTuiError::Config(format!(
+                "invalid alias '{}', aliases must be non-empty and must not contain ':'",
+                alias
+            )));
+        }
+        if let Some(flag) = self.flags.iter().find(|f| !is_valid_flag(f)) {
+            return Err(BeancountTuiError::Config(format!(
+                "invalid flag '{}', flags must be a single character or `txn`",
+                flag
+            )));
+        }
+        if self.valid_flags.is_empty() || self.valid_flags.contains(char::is_whitespace) {
+            return Err(BeancountTuiError::Config(
+                "`valid_flags` must contain at least one flag and no whitespace".to_string(),
+            ));
+        }
+        if let Some(flag) = self
+            .flags
+            .iter()
+            .find(|f| *f != "txn" && !self.valid_flags.contains(f.as_str()))
+        {
+            return Err(BeancountTuiError::Config(format!(
+                "flag '{}' is missing from `valid_flags`",
+                flag
+            )));
+        }
+        if let Some(field) = METAFIELD_ORDER
+            .iter()
+            .find(|field| self.metadata_order.iter().filter(|f| f == field).count() != 1)
+        {
+            return Err(BeancountTuiError::Config(format!(
+                "`metadata_order` must contain \"{}\" exactly once",
+                format!("{field:?}").to_lowercase()
+            )));
+        }
+        if self.metadata_order.len() != METAFIELD_ORDER.len() {
+            return Err(BeancountTuiError::Config(
+                "`metadata_order` can only contain date, flag, payee, narration and comment"
+                    .to_string(),
+            ));
+        }
+        if self.format.indent == 0 || self.format.min_gap == 0 {
+            return Err(BeancountTuiError::Config(
+                "`format.indent` and `format.min_gap` must be greater than 0".to_string(),
+            ));
+        }
+        let quote = self.format.quote;
+        if quote.is_whitespace() || quote.is_alphanumeric() || quote == '\\' || quote == ';' {
+            return Err(BeancountTuiError::Config(format!(
+                "`format.quote` can't be whitespace, a letter, a digit, '\\' or ';': '{}'",
+                quote
+            )));
+        }
+        if !self.format.month_header.trim_start().starts_with(';')
+            || !is_valid_date_format(&self.format.month_header)
+        {
+            return Err(BeancountTuiError::Config(format!(
+                "`format.month_header` must be a comment starting with ';' and a valid date format: '{}'",
+                self.format.month_header
+            )));
+        }
+        self.keys.validate()?;
+        if let Some(csv) = &self.csv {
+            csv.validate()?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for KeysConfig {
+    fn default() -> Self {
+        Self {
+            swap_payee_narration: "A-s".to_string(),
+            narration_to_payee: "A-n".to_string(),
+        }
+    }
+}
+
+impl KeysConfig {
+    fn bindings(&self) -> [(&'static str, &str, Command); 2] {
+        [
+            (
+                "swap_payee_narration",
+                &self.swap_payee_narration,
+                Command::SwapPayeeNarration,
+            ),
+            (
+                "narration_to_payee",
+                &self.narration_to_payee,
+                Command::NarrationToPayee,
+            ),
+        ]
+    }
+
+    /// The configured command bound to `key_event`, these take precedence over the built-in keys
+    pub fn lookup(&self, key_event: KeyEvent) -> Option<Command> {
+        self.bindings()
+            .into_iter()
+            .find(|(_, key, _)| parse_key(key).is_some_and(|key| matches_key(key_event, key)))
+            .map(|(_, _, command)| command)
+    }
+
+    fn validate(&self) -> Result<(), BeancountTuiError> {
+        for (name, key, _) in self.bindings() {
+            if parse_key(key).is_none() {
+                return Err(BeancountTuiError::Config(format!(
+                    "`keys.{}` isn't a valid key combination: '{}'",
+                    name, key
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CsvConfig {
+    fn validate(&self) -> Result<(), BeancountTuiError> {
+        if !matches!(self.decimal_separator, '.' | ',') {
+            return Err(BeancountTuiError::Config(
+                "`csv.decimal_separator` must be '.' or ','".to_string(),
+            ));
+        }
+        if !self.delimiter.is_ascii() {
+            return Err(BeancountTuiError::Config(
+                "`csv.delimiter` must be an ASCII character".to_string(),
+            ));
+        }
+        if self.account.trim().is_empty() {
+            return Err(BeancountTuiError::Config(
+                "`csv.account` must not be empty".to_string(),
+            ));
+        }
+        if let Some(currency) = self
+            .default_currency
+            .as_ref()
+            .filter(|c| !is_valid_currency(c))
+        {
+            return Err(BeancountTuiError::Config(format!(
+                "invalid CSV default currency '{}'",
+                currency
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Wether `flag` can be used as a transaction flag
+pub fn is_valid_flag(flag: &str) -> bool {
+    flag == "txn" || flag.chars().count() == 1
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("beancount-tui").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::crossterm::event::{KeyCode, KeyModifiers};
+
+    use super::*;
+
+    #[test]
+    fn default_config_is_valid() {
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn parse_format() {
+        let config: Config = toml::from_str("[format]\nindent = 2\namount_column = 60").unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.format.indent, 2);
+        assert_eq!(config.format.min_gap, 2);
+        assert!(config.format.align_currencies);
+        let config: Config = toml::from_str("[format]\nmin_gap = 0").unwrap();
+        assert!(config.validate().is_err());
+        let config: Config = toml::from_str("[format]\nquote = \"'\"").unwrap();
+        assert!(config.validate().is_ok());
+        let config: Config = toml::from_str("[format]\nquote = \"a\"").unwrap();
+        assert!(config.validate().is_err());
+        for header in ["%B %Y", ";; %Q"] {
+            let mut config = Config::default();
+            config.format.month_header = header.to_string();
+            assert!(config.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn reject_zero_page_size() {
+        let config: Config = toml::from_str("page_size = 0").unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn parse_metadata_order() {
+        let config: Config =
+            toml::from_str(r#"metadata_order = ["payee", "narration", "date", "flag", "comment"]"#)
+                .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.metadata_order[0], InputFieldType::Payee);
+        let config: Config =
+            toml::from_str(r#"metadata_order = ["payee", "payee", "date", "flag", "comment"]"#)
+                .unwrap();
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "`metadata_order` must contain \"payee\" exactly once"
+        );
+        let config: Config = toml::from_str(
+            r#"metadata_order = ["payee", "narration", "date", "flag", "comment", "account"]"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_err());
+        assert!(toml::from_str::<Config>(r#"metadata_order = ["memo"]"#).is_err());
+    }
+
+    #[test]
+    fn parse_keys() {
+        let config: Config = toml::from_str("[keys]\nnarration_to_payee = \"C-S-n\"").unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.keys.swap_payee_narration, "A-s");
+        let ctrl_shift_n = KeyEvent::new(
+            KeyCode::Char('n'),
+            KeyModifiers::CONTROL | KeyModifiers::SHIFT,
+        );
+        assert_eq!(
+            config.keys.lookup(ctrl_shift_n),
+            Some(Command::NarrationToPayee)
+        );
+        let config: Config = toml::from_str("[keys]\nswap_payee_narration = \"Hyper-s\"").unwrap();
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "`keys.swap_payee_narration` isn't a valid key combination: 'Hyper-s'"
+        );
+    }
+
+    #[test]
+    fn parse_flags() {
+        let config: Config = toml::from_str(r#"flags = ["*", "!", "txn", "P"]"#).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.flags, ["*", "!", "txn", "P"]);
+    }
+
+    #[test]
+    fn reject_invalid_default_currency() {
+        let config: Config = toml::from_str(r#"default_currency = "EUR""#).unwrap();
+        assert!(config.validate().is_ok());
+        let config: Config = toml::from_str(r#"default_currency = "euro""#).unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn parse_aliases() {
+        let config: Config = toml::from_str(
+            r#"
+            [aliases]
+            e = "Expenses"
+            ef = "Expenses:Food"
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.aliases["ef"], "Expenses:Food");
+        let config: Config = toml::from_str("[aliases]\n\"e:f\" = \"Expenses\"").unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn parse_csv_mapping() {
+        let config: Config = toml::from_str(
+            r#"
+            [csv]
+            date_column = "Booking date"
+            date_format = "%d.%m.%Y"
+            amount_column = 3
+            payee_column = "Payee"
+            account = "Assets:Checking"
+            decimal_separator = ","
+            delimiter = ";"
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        let csv = config.csv.unwrap();
+        assert_eq!(csv.date_column, Column::Name("Booking date".to_string()));
+        assert_eq!(csv.amount_column, Column::Index(3));
+        assert_eq!(csv.narration_column, None);
+        let config: Config = toml::from_str(
+            "[csv]\ndate_column = 0\namount_column = 1\naccount = \"A\"\ndecimal_separator = \"'\"",
+        )
+        .unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn reject_invalid_flags() {
+        let config: Config = toml::from_str(r#"flags = ["*", "done"]"#).unwrap();
+        assert!(config.validate().is_err());
+        let config: Config = toml::from_str(r#"flags = []"#).unwrap();
+        assert!(config.validate().is_err());
+        let config: Config = toml::from_str(
+            r#"
+            flags = ["*", "?"]
+            valid_flags = "*!"
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_err());
+    }
+}
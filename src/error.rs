@@ -1,7 +1,19 @@
 use thiserror::Error;
 
+/// The errors of this crate that callers can react to
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum BeancountTuiError {
+    /// A directive that can't be converted, e.g. one that isn't a transaction
     #[error("couldn't parse input")]
     Parser(String),
+    /// An invalid value in the config file
+    #[error("{0}")]
+    Config(String),
+    /// A file that doesn't exist or can't be read, explained in plain words
+    #[error("{0}")]
+    File(String),
+    /// A `:` command that can't be parsed or run
+    #[error("{0}")]
+    Command(String),
 }
@@ -0,0 +1,213 @@
+//! Export of the transactions as JSON, for post-processing in other tools.
+//!
+//! The output is an array with one object per transaction, in file order:
+//!
+//! ```json
+//! [
+//!   {
+//!     "date": "2023-10-01",
+//!     "flag": "*",
+//!     "payee": "Test Co",
+//!     "narration": "Foo Bar",
+//!     "comment": null,
+//!     "tags": ["trip"],
+//!     "links": [],
+//!     "postings": [
+//!       {
+//!         "flag": null,
+//!         "account": "Assets:Test",
+//!         "amount": "5.00",
+//!         "number": "5.00",
+//!         "currency": "USD"
+//!       }
+//!     ]
+//!   }
+//! ]
+//! ```
+//!
+//! The `flag` of a posting is e.g. `"!"`, `null` if the posting has none.
+//! All other values are strings as shown in the fields, so no precision is lost.
+//! `payee`, `narration`, `comment`, `amount` and `currency` are `null` when the
+//! field is empty. `number` is the amount parsed as decimal, `null` if the
+//! amount is empty or not a valid number. `tags` and `links` are sorted and
+//! come from the input file since they can't be edited.
+
+use std::path::Path;
+
+use color_eyre::{eyre::Context, Result};
+use serde::Serialize;
+
+use crate::{
+    beancount::{parse_amount, textarea_content, PostingTui, TransactionTui},
+    utils::write_atomically,
+};
+
+#[derive(Debug, Serialize)]
+pub struct ExportedTransaction {
+    pub date: String,
+    pub flag: String,
+    pub payee: Option<String>,
+    pub narration: Option<String>,
+    pub comment: Option<String>,
+    pub tags: Vec<String>,
+    pub links: Vec<String>,
+    pub postings: Vec<ExportedPosting>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportedPosting {
+    pub flag: Option<String>,
+    pub account: String,
+    pub amount: Option<String>,
+    pub number: Option<String>,
+    pub currency: Option<String>,
+}
+
+/// The trimmed content of the field, `None` if it is empty
+fn non_empty(content: String) -> Option<String> {
+    let content = content.trim();
+    (!content.is_empty()).then(|| content.to_string())
+}
+
+impl From<&TransactionTui<'_>> for ExportedTransaction {
+    fn from(transaction: &TransactionTui) -> Self {
+        let [date, flag, payee, narration, comment] = transaction
+            .metadata_textareas()
+            .each_ref()
+            .map(textarea_content);
+        let mut tags: Vec<String> = transaction
+            .directive
+            .tags
+            .iter()
+            .map(|tag| tag.as_str().to_string())
+            .collect();
+        tags.sort();
+        let mut links: Vec<String> = transaction
+            .directive
+            .links
+            .iter()
+            .map(|link| link.as_str().to_string())
+            .collect();
+        links.sort();
+        Self {
+            date: date.trim().to_string(),
+            flag: flag.trim().to_string(),
+            payee: non_empty(payee),
+            narration: non_empty(narration),
+            comment: non_empty(comment),
+            tags,
+            links,
+            postings: transaction
+                .postings_textareas()
+                .iter()
+                .map(ExportedPosting::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<&PostingTui<'_>> for ExportedPosting {
+    fn from(posting: &PostingTui) -> Self {
+        let amount = non_empty(textarea_content(&posting.amount_textarea));
+        Self {
+            flag: posting.flag.map(String::from),
+            account: textarea_content(&posting.account_textarea)
+                .trim()
+                .to_string(),
+            number: amount
+                .as_deref()
+                .and_then(parse_amount)
+                .map(|number| number.to_string()),
+            amount,
+            currency: non_empty(textarea_content(&posting.currency_textarea)),
+        }
+    }
+}
+
+/// Serializes the transactions as a pretty printed JSON array
+pub fn export_json(transactions: &[TransactionTui]) -> Result<String> {
+    let exported: Vec<ExportedTransaction> = transactions.iter().map(Into::into).collect();
+    Ok(serde_json::to_string_pretty(&exported)?)
+}
+
+/// Writes the JSON export of the transactions to `path`
+pub fn write_json(path: &Path, transactions: &[TransactionTui]) -> Result<()> {
+    write_atomically(path, &export_json(transactions)?)
+        .wrap_err_with(|| format!("couldn't export to {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::beancount::{filter_transactions, parse_ledger};
+
+    fn fixture<'t>() -> Vec<TransactionTui<'t>> {
+        let ledger = parse_ledger(&PathBuf::from("data/test.beancount")).unwrap();
+        filter_transactions(ledger.into_iter().next().unwrap().beancount)
+            .iter()
+            .map(|d| d.try_into().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn export_fixture() {
+        let exported: Value = serde_json::from_str(&export_json(&fixture()).unwrap()).unwrap();
+        let exported = exported.as_array().unwrap();
+        assert_eq!(exported.len(), 4);
+        assert_eq!(
+            exported[0],
+            json!({
+                "date": "2023-10-01",
+                "flag": "*",
+                "payee": "Test Co",
+                "narration": "Foo Bar",
+                "comment": null,
+                "tags": [],
+                "links": [],
+                "postings": [
+                    {"flag": null, "account": "Assets:Test", "amount": "5", "number": "5", "currency": "USD"},
+                    {"flag": null, "account": "Expenses:Test", "amount": null, "number": null, "currency": null}
+                ]
+            })
+        );
+        assert_eq!(
+            exported[1]["postings"][1],
+            json!({"flag": null, "account": "Expenses:Test", "amount": "-5", "number": "-5", "currency": "USD"})
+        );
+        assert_eq!(exported[2]["postings"].as_array().unwrap().len(), 3);
+        assert_eq!(exported[3]["narration"], "Foo Bar3");
+    }
+
+    #[test]
+    fn export_keeps_amount_precision() {
+        let mut transactions = fixture();
+        crate::beancount::set_textarea_content(
+            &mut transactions[0].postings_textareas_mut()[0].amount_textarea,
+            "0.10000000000000000001",
+        );
+        let exported: Value = serde_json::from_str(&export_json(&transactions).unwrap()).unwrap();
+        assert_eq!(
+            exported[0]["postings"][0]["number"],
+            "0.10000000000000000001"
+        );
+    }
+
+    #[test]
+    fn export_tags_and_links_sorted() {
+        let beancount: beancount_parser::BeancountFile<rust_decimal::Decimal> =
+            "2024-01-01 * \"Shop\" \"Food\" #trip #food ^receipt\n    Assets:Cash  -5 EUR\n    Expenses:Food\n"
+                .parse()
+                .unwrap();
+        let transactions: Vec<TransactionTui> = filter_transactions(beancount)
+            .iter()
+            .map(|d| d.try_into().unwrap())
+            .collect();
+        let exported: Value = serde_json::from_str(&export_json(&transactions).unwrap()).unwrap();
+        assert_eq!(exported[0]["tags"], json!(["food", "trip"]));
+        assert_eq!(exported[0]["links"], json!(["receipt"]));
+    }
+}
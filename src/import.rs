@@ -0,0 +1,189 @@
+use std::{path::Path, str::FromStr};
+
+use beancount_parser::{Directive, DirectiveContent};
+use chrono::NaiveDate;
+use color_eyre::{eyre::Context, Result};
+use csv::StringRecord;
+use rust_decimal::Decimal;
+
+use crate::{
+    beancount::{PostingTui, TransactionTui},
+    config::{Column, CsvConfig},
+};
+
+/// Transactions read from a CSV file, together with the rows that were skipped
+#[derive(Debug, Default)]
+pub struct CsvImport<'t> {
+    pub transactions: Vec<TransactionTui<'t>>,
+    pub errors: Vec<String>,
+}
+
+/// Converts every row of the CSV file into a transaction with one posting on the
+/// configured account and an empty counter posting.
+/// Rows that can't be converted are collected in `errors` instead of aborting.
+pub fn import_csv<'t>(
+    path: &Path,
+    config: &CsvConfig,
+    default_currency: Option<&str>,
+) -> Result<CsvImport<'t>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(config.delimiter as u8)
+        .has_headers(uses_headers(config))
+        .flexible(true)
+        .from_path(path)
+        .wrap_err_with(|| format!("couldn't read {}", path.display()))?;
+    let headers = if uses_headers(config) {
+        reader.headers()?.clone()
+    } else {
+        StringRecord::new()
+    };
+    let currency = config
+        .default_currency
+        .as_deref()
+        .or(default_currency)
+        .unwrap_or_default();
+    let mut import = CsvImport::default();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                import.errors.push(err.to_string());
+                continue;
+            }
+        };
+        let line = record.position().map_or(0, |p| p.line());
+        match row_to_transaction(&record, &headers, config, currency, line) {
+            Ok(transaction) => import.transactions.push(transaction),
+            Err(err) => import.errors.push(format!("line {}: {}", line, err)),
+        }
+    }
+    Ok(import)
+}
+
+/// Headers are only expected if a column is referenced by its name
+fn uses_headers(config: &CsvConfig) -> bool {
+    [
+        Some(&config.date_column),
+        Some(&config.amount_column),
+        config.payee_column.as_ref(),
+        config.narration_column.as_ref(),
+    ]
+    .into_iter()
+    .flatten()
+    .any(|column| matches!(column, Column::Name(_)))
+}
+
+fn field<'r>(
+    record: &'r StringRecord,
+    headers: &StringRecord,
+    column: &Column,
+) -> Result<&'r str, String> {
+    let index = match column {
+        Column::Index(index) => Some(*index),
+        Column::Name(name) => headers.iter().position(|header| header.trim() == name),
+    };
+    index
+        .and_then(|index| record.get(index))
+        .map(str::trim)
+        .ok_or_else(|| format!("missing column {:?}", column))
+}
+
+/// Parses an amount like `-1.234,56` with the given decimal separator
+fn parse_csv_amount(amount: &str, decimal_separator: char) -> Option<Decimal> {
+    let thousands_separator = if decimal_separator == ',' { '.' } else { ',' };
+    let normalized: String = amount
+        .chars()
+        .filter(|c| *c != thousands_separator && !c.is_whitespace())
+        .map(|c| if c == decimal_separator { '.' } else { c })
+        .collect();
+    Decimal::from_str(&normalized).ok()
+}
+
+fn row_to_transaction<'t>(
+    record: &StringRecord,
+    headers: &StringRecord,
+    config: &CsvConfig,
+    currency: &str,
+    line: u64,
+) -> Result<TransactionTui<'t>, String> {
+    let date = field(record, headers, &config.date_column)?;
+    let date = NaiveDate::parse_from_str(date, &config.date_format)
+        .map_err(|_| format!("invalid date '{}'", date))?;
+    let amount = field(record, headers, &config.amount_column)?;
+    let amount = parse_csv_amount(amount, config.decimal_separator)
+        .ok_or_else(|| format!("invalid amount '{}'", amount))?;
+    let optional_field = |column: &Option<Column>| match column {
+        Some(column) => field(record, headers, column).map(|f| Some(f.to_string())),
+        None => Ok(None),
+    };
+    let payee = optional_field(&config.payee_column)?;
+    let narration = optional_field(&config.narration_column)?;
+
+    let mut directive: Directive<Decimal> = format!("{} *", date.format("%Y-%m-%d"))
+        .parse()
+        .map_err(|_| format!("invalid date '{}'", date))?;
+    directive.line_number = line as u32;
+    if let DirectiveContent::Transaction(transaction) = &mut directive.content {
+        transaction.payee = payee;
+        transaction.narration = narration;
+    }
+    let mut transaction = TransactionTui::try_from(&directive).map_err(|err| err.to_string())?;
+    *transaction.postings_textareas_mut() = vec![
+        PostingTui::new(&config.account, &amount.to_string(), currency),
+        PostingTui::default(),
+    ];
+    transaction.edited = true;
+    Ok(transaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beancount::format_transaction;
+
+    fn config() -> CsvConfig {
+        toml::from_str(
+            r#"
+            date_column = "Date"
+            date_format = "%d.%m.%Y"
+            amount_column = "Amount"
+            payee_column = "Payee"
+            narration_column = 3
+            account = "Assets:Checking"
+            default_currency = "EUR"
+            decimal_separator = ","
+            delimiter = ";"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn import_rows_and_collect_errors() {
+        let import = import_csv(Path::new("data/test.csv"), &config(), None).unwrap();
+        assert_eq!(import.transactions.len(), 2);
+        assert_eq!(
+            format_transaction(&import.transactions[0]),
+            // the counter posting is left empty and isn't written
+            "2024-03-01 * \"Bakery\" \"Bread\"\n    Assets:Checking  -1234.56 EUR"
+        );
+        assert_eq!(import.transactions[0].postings_textareas().len(), 2);
+        assert_eq!(import.transactions[1].line_number, 4);
+        assert_eq!(import.errors.len(), 2);
+        assert!(import.errors[0].starts_with("line 3: invalid date"));
+        assert!(import.errors[1].starts_with("line 5: invalid amount"));
+    }
+
+    #[test]
+    fn parse_amounts_with_separators() {
+        assert_eq!(
+            parse_csv_amount("-1.234,56", ','),
+            Some(Decimal::new(-123456, 2))
+        );
+        assert_eq!(
+            parse_csv_amount("1,234.5", '.'),
+            Some(Decimal::new(12345, 1))
+        );
+        assert_eq!(parse_csv_amount("12 EUR", '.'), None);
+    }
+}
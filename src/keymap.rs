@@ -0,0 +1,616 @@
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::app::{InputFieldType, PendingAction};
+
+/// Everything a key can be bound to outside of popups and the completion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Quit,
+    Save,
+    NextTransaction,
+    PrevTransaction,
+    FirstTransaction,
+    LastTransaction,
+    PageDown,
+    PageUp,
+    CopyTransaction,
+    Paste,
+    CycleFlag,
+    ToggleMark,
+    NextMark,
+    PrevMark,
+    AcceptRemaining,
+    AcceptAndNext,
+    RejectTransaction,
+    ShowRejected,
+    NextDay,
+    PrevDay,
+    NextMonth,
+    PrevMonth,
+    Today,
+    /// Opens the date picker
+    OpenCalendar,
+    Normalize,
+    ExpandAccount,
+    CompleteAccount,
+    /// Opens the tree of the known accounts
+    BrowseAccounts,
+    ClearField,
+    /// Puts back the parsed content of the focused field
+    RevertField,
+    Undo,
+    /// Moves through all fields in order: metadata, then posting by posting
+    NextField,
+    PrevField,
+    FocusRight,
+    FocusLeft,
+    FocusDown,
+    FocusUp,
+    InsertPostingBelow,
+    InsertPostingAbove,
+    DeletePosting,
+    /// Moves half of the focused posting's amount into a new posting below it
+    SplitPosting,
+    /// Sets the focused amount so the postings in its currency sum up to zero
+    BalancePosting,
+    DeleteTransaction,
+    OpenCommandLine,
+    /// Focuses the account of the N-th posting, 0 focuses the payee
+    JumpToPosting(u8),
+    /// Negates the focused amount
+    FlipSign,
+    /// Negates all amounts of the current transaction
+    FlipAllSigns,
+    /// Trims the fields of the current transaction and collapses spaces
+    CleanTransaction,
+    /// Opens the comment lines above the transaction for editing
+    EditComments,
+    /// Focuses the next empty or invalid field, also in the following transactions
+    NextIssue,
+    /// Shows or hides the `pad`, `balance` and `note` directives before the transaction
+    ToggleContext,
+    /// Bound through the `[keys]` config table
+    SwapPayeeNarration,
+    NarrationToPayee,
+}
+
+/// The fields a command is available in, other fields receive the key as text input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    Any,
+    Date,
+    Account,
+    Amount,
+    Postings,
+}
+
+impl Scope {
+    fn contains(&self, field: InputFieldType) -> bool {
+        match self {
+            Scope::Any => true,
+            Scope::Date => field == InputFieldType::Date,
+            Scope::Account => field == InputFieldType::Account,
+            Scope::Amount => field == InputFieldType::Amount,
+            Scope::Postings => matches!(
+                field,
+                InputFieldType::Account | InputFieldType::Amount | InputFieldType::Currency
+            ),
+        }
+    }
+}
+
+impl Command {
+    /// Short description shown in the instructions line
+    pub fn description(&self) -> &'static str {
+        match self {
+            Command::Quit => "quit",
+            Command::Save => "save",
+            Command::NextTransaction => "next",
+            Command::PrevTransaction => "prev",
+            Command::FirstTransaction => "first",
+            Command::LastTransaction => "last",
+            Command::PageDown => "page down",
+            Command::PageUp => "page up",
+            Command::CopyTransaction => "copy",
+            Command::Paste => "paste",
+            Command::CycleFlag => "flag",
+            Command::ToggleMark => "mark",
+            Command::NextMark => "next mark",
+            Command::PrevMark => "prev mark",
+            Command::AcceptRemaining => "accept rest",
+            Command::AcceptAndNext => "accept",
+            Command::RejectTransaction => "reject",
+            Command::ShowRejected => "rejected",
+            Command::NextDay => "+1 day",
+            Command::PrevDay => "-1 day",
+            Command::NextMonth => "+1 month",
+            Command::PrevMonth => "-1 month",
+            Command::Today => "today",
+            Command::OpenCalendar => "calendar",
+            Command::Normalize => "normalize",
+            Command::ExpandAccount => "expand",
+            Command::CompleteAccount => "complete",
+            Command::BrowseAccounts => "browse",
+            Command::ClearField => "clear",
+            Command::RevertField => "revert field",
+            Command::Undo => "undo",
+            Command::NextField => "next field",
+            Command::PrevField => "prev field",
+            Command::FocusRight => "right",
+            Command::FocusLeft => "left",
+            Command::FocusDown => "down",
+            Command::FocusUp => "up",
+            Command::InsertPostingBelow => "add posting",
+            Command::InsertPostingAbove => "add above",
+            Command::DeletePosting => "delete posting",
+            Command::SplitPosting => "split posting",
+            Command::BalancePosting => "balance here",
+            Command::DeleteTransaction => "delete transaction",
+            Command::OpenCommandLine => "command",
+            Command::JumpToPosting(0) => "payee",
+            Command::JumpToPosting(_) => "jump to posting",
+            Command::FlipSign => "flip sign",
+            Command::FlipAllSigns => "flip all signs",
+            Command::CleanTransaction => "clean",
+            Command::EditComments => "comments",
+            Command::NextIssue => "next issue",
+            Command::ToggleContext => "context",
+            Command::SwapPayeeNarration => "swap payee",
+            Command::NarrationToPayee => "narration to payee",
+        }
+    }
+
+    /// Wether the command changes the transactions or writes them, these are
+    /// disabled in read-only mode
+    pub fn is_mutating(&self) -> bool {
+        !matches!(
+            self,
+            Command::Quit
+                | Command::NextTransaction
+                | Command::PrevTransaction
+                | Command::FirstTransaction
+                | Command::LastTransaction
+                | Command::PageDown
+                | Command::PageUp
+                | Command::CopyTransaction
+                | Command::ToggleMark
+                | Command::NextMark
+                | Command::PrevMark
+                | Command::NextField
+                | Command::PrevField
+                | Command::FocusRight
+                | Command::FocusLeft
+                | Command::FocusDown
+                | Command::FocusUp
+                | Command::OpenCommandLine
+                | Command::JumpToPosting(_)
+                | Command::NextIssue
+                | Command::ToggleContext
+        )
+    }
+
+    fn scope(&self) -> Scope {
+        match self {
+            Command::NextDay
+            | Command::PrevDay
+            | Command::NextMonth
+            | Command::PrevMonth
+            | Command::Today
+            | Command::OpenCalendar => Scope::Date,
+            Command::ExpandAccount | Command::CompleteAccount | Command::BrowseAccounts => {
+                Scope::Account
+            }
+            Command::FlipSign => Scope::Amount,
+            Command::DeletePosting | Command::SplitPosting | Command::BalancePosting => {
+                Scope::Postings
+            }
+            _ => Scope::Any,
+        }
+    }
+}
+
+/// A key combination bound to a command
+#[derive(Debug, Clone, Copy)]
+pub struct Binding {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+    pub command: Command,
+}
+
+const fn bind(code: KeyCode, modifiers: KeyModifiers, command: Command) -> Binding {
+    Binding {
+        code,
+        modifiers,
+        command,
+    }
+}
+
+const CTRL: KeyModifiers = KeyModifiers::CONTROL;
+const ALT: KeyModifiers = KeyModifiers::ALT;
+const NONE: KeyModifiers = KeyModifiers::NONE;
+
+/// All key bindings. The first binding of a command is the one shown in the instructions.
+/// Characters typed with shift are matched as their uppercase version without shift.
+pub const BINDINGS: &[Binding] = &[
+    bind(KeyCode::Esc, NONE, Command::Quit),
+    bind(KeyCode::Char('q'), CTRL, Command::Quit),
+    bind(KeyCode::Char('s'), CTRL, Command::Save),
+    bind(KeyCode::Char('n'), CTRL, Command::NextTransaction),
+    bind(KeyCode::Char('p'), CTRL, Command::PrevTransaction),
+    bind(KeyCode::Home, CTRL, Command::FirstTransaction),
+    bind(KeyCode::End, CTRL, Command::LastTransaction),
+    bind(KeyCode::PageDown, NONE, Command::PageDown),
+    bind(KeyCode::PageUp, NONE, Command::PageUp),
+    bind(KeyCode::Char('y'), CTRL, Command::CopyTransaction),
+    bind(KeyCode::Char('v'), CTRL, Command::Paste),
+    bind(KeyCode::Char('f'), CTRL, Command::CycleFlag),
+    bind(KeyCode::Char('m'), ALT, Command::ToggleMark),
+    bind(KeyCode::Char(']'), ALT, Command::NextMark),
+    bind(KeyCode::Char('['), ALT, Command::PrevMark),
+    bind(KeyCode::Char('a'), ALT, Command::AcceptRemaining),
+    bind(KeyCode::Enter, ALT, Command::AcceptAndNext),
+    // only sent with the kitty keyboard protocol
+    bind(KeyCode::Enter, CTRL, Command::AcceptAndNext),
+    bind(KeyCode::Char('j'), ALT, Command::RejectTransaction),
+    bind(KeyCode::Char('J'), ALT, Command::ShowRejected),
+    bind(KeyCode::Char('c'), ALT, Command::CleanTransaction),
+    bind(KeyCode::Char(';'), ALT, Command::EditComments),
+    bind(KeyCode::Char('.'), CTRL, Command::NextIssue),
+    // most terminals only send Ctrl-. with the kitty keyboard protocol
+    bind(KeyCode::Char('.'), ALT, Command::NextIssue),
+    bind(KeyCode::Char('x'), ALT, Command::ToggleContext),
+    bind(KeyCode::Char('-'), ALT, Command::FlipSign),
+    bind(KeyCode::Char('_'), ALT, Command::FlipAllSigns),
+    bind(KeyCode::Up, CTRL, Command::NextDay),
+    bind(KeyCode::Down, CTRL, Command::PrevDay),
+    // the arrows do nothing else in the single line date field, `+`, `-` and letters
+    // are needed to type relative dates
+    bind(KeyCode::Up, NONE, Command::NextDay),
+    bind(KeyCode::Down, NONE, Command::PrevDay),
+    bind(
+        KeyCode::Up,
+        KeyModifiers::CONTROL.union(KeyModifiers::SHIFT),
+        Command::NextMonth,
+    ),
+    bind(
+        KeyCode::Down,
+        KeyModifiers::CONTROL.union(KeyModifiers::SHIFT),
+        Command::PrevMonth,
+    ),
+    bind(KeyCode::Char('t'), CTRL, Command::Today),
+    bind(KeyCode::Char('d'), CTRL, Command::OpenCalendar),
+    bind(KeyCode::Char('g'), CTRL, Command::Normalize),
+    bind(KeyCode::Char('x'), CTRL, Command::ExpandAccount),
+    bind(KeyCode::Char(' '), CTRL, Command::CompleteAccount),
+    bind(KeyCode::Char('t'), ALT, Command::BrowseAccounts),
+    // takes over the textarea's undo binding
+    bind(KeyCode::Char('u'), CTRL, Command::ClearField),
+    bind(KeyCode::Char('z'), CTRL, Command::Undo),
+    bind(KeyCode::Char('r'), ALT, Command::RevertField),
+    bind(KeyCode::Tab, NONE, Command::NextField),
+    // except in a multi-line narration, see `App::handle_key_event`
+    bind(KeyCode::Enter, NONE, Command::NextField),
+    // terminals report Shift-Tab as BackTab, some of them without the shift
+    bind(KeyCode::BackTab, KeyModifiers::SHIFT, Command::PrevField),
+    bind(KeyCode::BackTab, NONE, Command::PrevField),
+    bind(KeyCode::Char('l'), CTRL, Command::FocusRight),
+    bind(KeyCode::Right, CTRL, Command::FocusRight),
+    bind(KeyCode::Char('h'), CTRL, Command::FocusLeft),
+    bind(KeyCode::Left, CTRL, Command::FocusLeft),
+    bind(KeyCode::Char('j'), CTRL, Command::FocusDown),
+    bind(KeyCode::Char('k'), CTRL, Command::FocusUp),
+    bind(KeyCode::Char('o'), CTRL, Command::InsertPostingBelow),
+    bind(KeyCode::Char('O'), CTRL, Command::InsertPostingAbove),
+    bind(KeyCode::Char('d'), ALT, Command::DeletePosting),
+    bind(KeyCode::Char('D'), ALT, Command::DeleteTransaction),
+    bind(KeyCode::Char('/'), ALT, Command::SplitPosting),
+    bind(KeyCode::Char('b'), ALT, Command::BalancePosting),
+    // a plain `:` is needed to type account names
+    bind(KeyCode::Char(':'), ALT, Command::OpenCommandLine),
+    bind(KeyCode::Char('1'), ALT, Command::JumpToPosting(1)),
+    bind(KeyCode::Char('2'), ALT, Command::JumpToPosting(2)),
+    bind(KeyCode::Char('3'), ALT, Command::JumpToPosting(3)),
+    bind(KeyCode::Char('4'), ALT, Command::JumpToPosting(4)),
+    bind(KeyCode::Char('5'), ALT, Command::JumpToPosting(5)),
+    bind(KeyCode::Char('6'), ALT, Command::JumpToPosting(6)),
+    bind(KeyCode::Char('7'), ALT, Command::JumpToPosting(7)),
+    bind(KeyCode::Char('8'), ALT, Command::JumpToPosting(8)),
+    bind(KeyCode::Char('9'), ALT, Command::JumpToPosting(9)),
+    bind(KeyCode::Char('0'), ALT, Command::JumpToPosting(0)),
+];
+
+/// Normalizes shifted characters to their uppercase version without the shift modifier
+fn normalize(key_event: KeyEvent) -> (KeyCode, KeyModifiers) {
+    let modifiers = key_event.modifiers & (CTRL | ALT | KeyModifiers::SHIFT);
+    match key_event.code {
+        KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => (
+            KeyCode::Char(c.to_ascii_uppercase()),
+            modifiers - KeyModifiers::SHIFT,
+        ),
+        code => (code, modifiers),
+    }
+}
+
+/// The command bound to `key_event` when `field` has the focus
+pub fn lookup(key_event: KeyEvent, field: InputFieldType) -> Option<Command> {
+    let (code, modifiers) = normalize(key_event);
+    BINDINGS
+        .iter()
+        .find(|b| b.code == code && b.modifiers == modifiers && b.command.scope().contains(field))
+        .map(|b| b.command)
+}
+
+/// Parses a key combination written like the labels in the instructions, e.g. `A-s`,
+/// `C-S-o` or `C-Space`
+pub fn parse_key(label: &str) -> Option<(KeyCode, KeyModifiers)> {
+    let mut modifiers = NONE;
+    let mut rest = label;
+    while rest.len() > 2 {
+        let modifier = match rest.get(..2) {
+            Some("C-") => CTRL,
+            Some("A-") => ALT,
+            Some("S-") => KeyModifiers::SHIFT,
+            _ => break,
+        };
+        modifiers |= modifier;
+        rest = &rest[2..];
+    }
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => KeyCode::Char(c),
+        _ => match rest {
+            "Space" => KeyCode::Char(' '),
+            "Enter" => KeyCode::Enter,
+            "Tab" => KeyCode::Tab,
+            "Backspace" => KeyCode::Backspace,
+            "Delete" => KeyCode::Delete,
+            "Insert" => KeyCode::Insert,
+            "Home" => KeyCode::Home,
+            "End" => KeyCode::End,
+            "PageUp" => KeyCode::PageUp,
+            "PageDown" => KeyCode::PageDown,
+            "Up" => KeyCode::Up,
+            "Down" => KeyCode::Down,
+            "Left" => KeyCode::Left,
+            "Right" => KeyCode::Right,
+            _ => KeyCode::F(rest.strip_prefix('F')?.parse().ok()?),
+        },
+    };
+    Some(normalize(KeyEvent::new(code, modifiers)))
+}
+
+/// Whether `key_event` is the key combination `key` returned by [`parse_key`]
+pub fn matches_key(key_event: KeyEvent, key: (KeyCode, KeyModifiers)) -> bool {
+    normalize(key_event) == key
+}
+
+/// Formats a key combination like `C-n`, `A-m` or `C-S-Up`
+fn key_label(binding: &Binding) -> String {
+    let mut label = String::new();
+    if binding.modifiers.contains(CTRL) {
+        label.push_str("C-");
+    }
+    if binding.modifiers.contains(ALT) {
+        label.push_str("A-");
+    }
+    if binding.modifiers.contains(KeyModifiers::SHIFT) {
+        label.push_str("S-");
+    }
+    match binding.code {
+        KeyCode::Char(' ') => label.push_str("Space"),
+        KeyCode::Char(c) if c.is_ascii_uppercase() => {
+            label.push_str("S-");
+            label.push(c.to_ascii_lowercase());
+        }
+        KeyCode::Char(c) => label.push(c),
+        KeyCode::BackTab if binding.modifiers.contains(KeyModifiers::SHIFT) => {
+            label.push_str("Tab")
+        }
+        code => label.push_str(&code.to_string()),
+    }
+    label
+}
+
+/// The label of the first key bound to `command`
+pub fn command_key(command: Command) -> Option<String> {
+    BINDINGS
+        .iter()
+        .find(|b| b.command == command)
+        .map(key_label)
+}
+
+/// The commands worth showing while `field` has the focus, most relevant first
+pub fn context_commands(field: InputFieldType) -> Vec<Command> {
+    let field_specific: &[Command] = match field {
+        InputFieldType::Date => &[
+            Command::OpenCalendar,
+            Command::Today,
+            Command::NextDay,
+            Command::PrevDay,
+        ],
+        InputFieldType::Account => &[
+            Command::InsertPostingBelow,
+            Command::DeletePosting,
+            Command::CompleteAccount,
+            Command::BrowseAccounts,
+            Command::ExpandAccount,
+        ],
+        InputFieldType::Amount => &[
+            Command::FlipSign,
+            Command::BalancePosting,
+            Command::SplitPosting,
+            Command::FlipAllSigns,
+            Command::InsertPostingBelow,
+            Command::DeletePosting,
+        ],
+        InputFieldType::Currency => &[Command::InsertPostingBelow, Command::DeletePosting],
+        InputFieldType::Flag => &[Command::CycleFlag],
+        InputFieldType::Comment => &[Command::EditComments],
+        _ => &[],
+    };
+    let general = [
+        Command::NextTransaction,
+        Command::PrevTransaction,
+        Command::NextIssue,
+        Command::PageDown,
+        Command::PageUp,
+        Command::Save,
+        Command::Quit,
+        Command::AcceptAndNext,
+        Command::OpenCommandLine,
+        Command::NextField,
+        Command::FocusDown,
+        Command::FocusUp,
+        Command::JumpToPosting(1),
+        Command::Undo,
+        Command::RevertField,
+        Command::ToggleMark,
+        Command::CycleFlag,
+        Command::AcceptRemaining,
+        Command::CopyTransaction,
+        Command::RejectTransaction,
+        Command::ShowRejected,
+        Command::ToggleContext,
+    ];
+    let mut commands = field_specific.to_vec();
+    for command in general {
+        if !commands.contains(&command) {
+            commands.push(command);
+        }
+    }
+    commands
+}
+
+/// The keys of the date picker and what they do
+pub const CALENDAR_HINTS: &[(&str, &str)] = &[
+    ("Arrows", "day/week"),
+    ("PageUp/Down", "month"),
+    ("t", "today"),
+    ("Enter", "pick"),
+    ("Esc", "cancel"),
+];
+
+/// The keys of the account tree and what they do
+pub const ACCOUNT_BROWSER_HINTS: &[(&str, &str)] = &[
+    ("j/k", "move"),
+    ("l/h", "open/close"),
+    ("Enter", "insert"),
+    ("Esc", "cancel"),
+];
+
+/// The keys of the problems listed before saving and what they do
+pub const SAVE_REVIEW_HINTS: &[(&str, &str)] = &[
+    ("j/k", "move"),
+    ("Enter", "go to"),
+    ("s", "save anyway"),
+    ("Esc", "back"),
+];
+
+/// The keys of the reason a save was blocked and what they do
+pub const CHECK_FAILURE_HINTS: &[(&str, &str)] = &[("j/k", "scroll"), ("Esc", "close")];
+
+/// The keys of the list of rejected transactions and what they do
+pub const REJECTED_HINTS: &[(&str, &str)] =
+    &[("j/k", "move"), ("Enter", "restore"), ("Esc", "close")];
+
+/// The keys of the comment editor and what they do
+pub const COMMENT_EDITOR_HINTS: &[(&str, &str)] =
+    &[("Enter", "new line"), ("C-s", "accept"), ("Esc", "cancel")];
+
+/// The keys of a popup and what they do
+pub fn popup_hints(action: &PendingAction) -> &'static [(&'static str, &'static str)] {
+    match action {
+        PendingAction::Dismiss => &[("Esc", "dismiss")],
+        PendingAction::ReloadFile { .. } => &[
+            ("r", "reload"),
+            ("k", "reload, keep edits"),
+            ("Esc", "ignore"),
+        ],
+        _ => &[("Enter", "confirm"), ("Esc", "cancel")],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn lookup_respects_scope() {
+        let ctrl_t = key(KeyCode::Char('t'), CTRL);
+        assert_eq!(lookup(ctrl_t, InputFieldType::Date), Some(Command::Today));
+        assert_eq!(lookup(ctrl_t, InputFieldType::Payee), None);
+        let up = key(KeyCode::Up, NONE);
+        assert_eq!(lookup(up, InputFieldType::Date), Some(Command::NextDay));
+        assert_eq!(lookup(up, InputFieldType::Narration), None);
+        let alt_d = key(KeyCode::Char('d'), ALT);
+        assert_eq!(
+            lookup(alt_d, InputFieldType::Amount),
+            Some(Command::DeletePosting)
+        );
+        assert_eq!(lookup(alt_d, InputFieldType::Narration), None);
+    }
+
+    #[test]
+    fn lookup_normalizes_shift() {
+        let ctrl_shift_o = key(KeyCode::Char('o'), CTRL | KeyModifiers::SHIFT);
+        assert_eq!(
+            lookup(ctrl_shift_o, InputFieldType::Account),
+            Some(Command::InsertPostingAbove)
+        );
+        let ctrl_shift_up = key(KeyCode::Up, CTRL | KeyModifiers::SHIFT);
+        assert_eq!(
+            lookup(ctrl_shift_up, InputFieldType::Date),
+            Some(Command::NextMonth)
+        );
+        assert_eq!(
+            lookup(key(KeyCode::Char('n'), NONE), InputFieldType::Payee),
+            None
+        );
+    }
+
+    #[test]
+    fn every_hint_has_a_key() {
+        for field in [
+            InputFieldType::Date,
+            InputFieldType::Account,
+            InputFieldType::Amount,
+            InputFieldType::Payee,
+        ] {
+            for command in context_commands(field) {
+                assert!(command_key(command).is_some(), "{:?} has no key", command);
+            }
+        }
+        assert_eq!(command_key(Command::Quit).unwrap(), "Esc");
+        assert_eq!(command_key(Command::InsertPostingAbove).unwrap(), "C-S-o");
+        assert_eq!(command_key(Command::NextMonth).unwrap(), "C-S-Up");
+        assert_eq!(command_key(Command::JumpToPosting(3)).unwrap(), "A-3");
+        assert_eq!(command_key(Command::PrevField).unwrap(), "S-Tab");
+        assert_eq!(command_key(Command::FlipAllSigns).unwrap(), "A-_");
+    }
+
+    #[test]
+    fn parse_key_labels() {
+        assert_eq!(parse_key("A-s"), Some((KeyCode::Char('s'), ALT)));
+        assert_eq!(parse_key("C-S-o"), Some((KeyCode::Char('O'), CTRL)));
+        assert_eq!(parse_key("C-Space"), Some((KeyCode::Char(' '), CTRL)));
+        assert_eq!(
+            parse_key("C-S-Up"),
+            Some((KeyCode::Up, CTRL | KeyModifiers::SHIFT))
+        );
+        assert_eq!(parse_key("F5"), Some((KeyCode::F(5), NONE)));
+        assert_eq!(parse_key("A--"), Some((KeyCode::Char('-'), ALT)));
+        assert_eq!(parse_key("C-"), None);
+        assert_eq!(parse_key("X-s"), None);
+        assert_eq!(parse_key("Fx"), None);
+        let key = parse_key("A-S-n").unwrap();
+        assert!(matches_key(
+            KeyEvent::new(KeyCode::Char('N'), ALT | KeyModifiers::SHIFT),
+            key
+        ));
+        assert!(!matches_key(KeyEvent::new(KeyCode::Char('n'), ALT), key));
+    }
+}
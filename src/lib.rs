@@ -0,0 +1,47 @@
+//! Reading, editing and formatting beancount transactions.
+//!
+//! [`beancount`] parses ledgers (following includes), converts transactions into
+//! editable form and formats them back into beancount syntax. Formatting works on
+//! the plain [`beancount::PlainTransaction`], so importers don't need the types of
+//! the terminal interface.
+//!
+//! The most used items are re-exported here: [`parse_beancount_file`] reads the
+//! transactions of a ledger, [`format_transaction`] writes one back. [`App`] is the
+//! terminal interface, [`App::run_script`] drives it without a terminal.
+
+pub mod beancount;
+pub mod error;
+pub mod utils;
+
+// the terminal interface, only public for the `beancount-tui` binary
+mod account_browser;
+#[doc(hidden)]
+pub mod app;
+mod calendar;
+#[doc(hidden)]
+pub mod cli;
+mod clipboard;
+mod command;
+mod completion;
+mod config;
+mod export;
+mod import;
+mod keymap;
+#[doc(hidden)]
+pub mod loading;
+mod save_review;
+mod session;
+#[doc(hidden)]
+pub mod terminal;
+mod theme;
+#[doc(hidden)]
+pub mod ui;
+mod verify;
+mod watch;
+mod writeback;
+
+pub use app::App;
+pub use beancount::{
+    filter_transactions, format_transaction, parse_beancount_file, parse_ledger, PostingTui,
+    TransactionTui,
+};
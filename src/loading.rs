@@ -0,0 +1,72 @@
+use std::{
+    sync::mpsc::{self, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use color_eyre::{eyre::eyre, Result};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+
+use crate::{
+    app::App,
+    cli::Args,
+    terminal::{color_support, Tui},
+    theme::Theme,
+    ui,
+};
+
+/// How often the loading screen is redrawn while waiting for the parser
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+enum LoadEvent {
+    Progress(usize),                 // directives parsed so far
+    Done(Box<Result<App<'static>>>), // the loaded app or the error that stopped loading
+}
+
+/// Loads the app in a background thread while showing the progress. Returns `None`
+/// if the user cancelled with Ctrl-c or Esc; the parser thread is left to finish
+/// on its own and dropped with the process.
+pub fn load_app(args: Args, terminal: &mut Tui) -> Result<Option<App<'static>>> {
+    let file = match (args.file.as_slice(), &args.csv) {
+        (_, Some(csv)) => csv.display().to_string(),
+        ([file], None) => file.display().to_string(),
+        ([], None) => "ledger".to_string(),
+        (files, None) => format!("{} files", files.len()),
+    };
+    // the configured theme is read with the ledger, until then the default one is used
+    let theme = Theme::new(color_support(args.color_choice()));
+    let (sender, events) = mpsc::channel();
+    thread::spawn(move || {
+        let progress = |n| {
+            let _ = sender.send(LoadEvent::Progress(n));
+        };
+        let app = App::with_progress(args, &progress);
+        let _ = sender.send(LoadEvent::Done(Box::new(app)));
+    });
+
+    let start = Instant::now();
+    let mut directives = 0;
+    loop {
+        loop {
+            match events.try_recv() {
+                Ok(LoadEvent::Progress(n)) => directives = n,
+                Ok(LoadEvent::Done(app)) => return app.map(Some),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    return Err(eyre!("the parser stopped unexpectedly"))
+                }
+            }
+        }
+        terminal
+            .draw(|frame| ui::draw_loading(frame, &theme, &file, directives, start.elapsed()))?;
+        if event::poll(REDRAW_INTERVAL)? {
+            if let Event::Key(key) = event::read()? {
+                let ctrl_c =
+                    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if key.kind == KeyEventKind::Press && (ctrl_c || key.code == KeyCode::Esc) {
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
@@ -1,29 +1,80 @@
-#[allow(unused)]
-mod app;
-mod beancount;
-mod cli;
-mod error;
-mod terminal;
-mod ui;
-mod utils;
+use std::fs;
 
+use beancount_tui::{app, beancount, cli::Args, loading, terminal};
 use clap::Parser;
-use color_eyre::Result;
-
-use crate::cli::Args;
+use color_eyre::{eyre::Context, Result};
 
 fn main() -> Result<()> {
     color_eyre::install()?;
     let args = Args::parse();
-    // create tui
-    let mut terminal = terminal::init()?;
-    let mut app = app::App::new(args)?;
+    // a wrong path is reported plainly, before the terminal is taken over
+    for file in args.csv.iter().chain(&args.file) {
+        if let Err(err) = beancount::check_readable(file) {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    }
+    if let Some(jobs) = args.jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global()
+            .wrap_err("couldn't start the parser threads")?;
+    }
+    if args.check {
+        let app = app::App::new(args)?;
+        let problems = app.check_report();
+        for line in &problems {
+            eprintln!("{}", line);
+        }
+        eprintln!(
+            "checked {} transactions, found {} problems",
+            app.transactions.len(),
+            problems.len()
+        );
+        if !problems.is_empty() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if let Some(script) = args.script.clone() {
+        let script = fs::read_to_string(&script)
+            .wrap_err_with(|| format!("couldn't read {}", script.display()))?;
+        let mut app = app::App::new(args)?;
+        app.run_script(&script)?;
+        for diff in &app.dry_run_report {
+            print!("{}", diff);
+        }
+        for line in app.file_summary() {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+    // create tui, parsing happens in the background behind a progress screen
+    let mut terminal = terminal::init(args.color_choice())?;
+    let mut app = match loading::load_app(args, &mut terminal) {
+        Ok(Some(app)) => app,
+        // restore the terminal first, so errors are reported on a normal terminal
+        cancelled_or_failed => {
+            restore_terminal();
+            return cancelled_or_failed.map(|_| ());
+        }
+    };
     let app_result = app.run(&mut terminal);
+    restore_terminal();
+    for diff in &app.dry_run_report {
+        print!("{}", diff);
+    }
+    for line in app.file_summary() {
+        println!("{}", line);
+    }
+    app_result
+}
+
+fn restore_terminal() {
     if let Err(err) = terminal::restore() {
         eprintln!(
             "failed to restore terminal. Run `reset` or restart your terminal to recover: {}",
             err
         );
     }
-    app_result
 }
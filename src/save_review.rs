@@ -0,0 +1,120 @@
+/// How serious a problem found before saving is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning, // beancount may reject it, saving anyway is allowed
+    Error,   // the transaction is invalid, saving is blocked
+}
+
+/// A problem of one transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub index: usize, // position of the transaction, starting at 0
+    pub date: String,
+    pub payee: String, // the narration if the transaction has no payee
+    pub issue: String, // e.g. `posting 2 has no account`
+    pub severity: Severity,
+}
+
+/// State of the screen listing the problems found before saving
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveReview {
+    pub problems: Vec<Problem>,
+    pub edited: usize,   // how many transactions differ from the parsed ones
+    pub selected: usize, // index into `problems`
+}
+
+impl SaveReview {
+    pub fn new(problems: Vec<Problem>, edited: usize) -> Self {
+        Self {
+            problems,
+            edited,
+            selected: 0,
+        }
+    }
+
+    /// Wether a problem blocks saving
+    pub fn has_errors(&self) -> bool {
+        self.problems
+            .iter()
+            .any(|problem| problem.severity == Severity::Error)
+    }
+
+    /// Moves the selection down or up, it stays put at the ends
+    pub fn select_next(&mut self, forward: bool) {
+        self.selected = if forward {
+            (self.selected + 1).min(self.problems.len().saturating_sub(1))
+        } else {
+            self.selected.saturating_sub(1)
+        };
+    }
+
+    pub fn selected_problem(&self) -> Option<&Problem> {
+        self.problems.get(self.selected)
+    }
+
+    /// Counts of the transactions, e.g. `3 edited, 2 failing validation, 1 with warnings`
+    pub fn summary(&self) -> String {
+        let transactions = |severity| {
+            let mut indexes: Vec<usize> = self
+                .problems
+                .iter()
+                .filter(|problem| problem.severity == severity)
+                .map(|problem| problem.index)
+                .collect();
+            indexes.dedup();
+            indexes.len()
+        };
+        format!(
+            "{} edited, {} failing validation, {} with warnings",
+            self.edited,
+            transactions(Severity::Error),
+            transactions(Severity::Warning)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problem(index: usize, severity: Severity) -> Problem {
+        Problem {
+            index,
+            date: "2024-01-01".to_string(),
+            payee: "Shop".to_string(),
+            issue: "unbalanced by 5 USD".to_string(),
+            severity,
+        }
+    }
+
+    #[test]
+    fn summary_counts_transactions() {
+        let review = SaveReview::new(
+            vec![
+                problem(0, Severity::Error),
+                problem(0, Severity::Error),
+                problem(3, Severity::Warning),
+            ],
+            4,
+        );
+        assert!(review.has_errors());
+        assert_eq!(
+            review.summary(),
+            "4 edited, 1 failing validation, 1 with warnings"
+        );
+    }
+
+    #[test]
+    fn selection_stays_in_the_list() {
+        let mut review = SaveReview::new(
+            vec![problem(0, Severity::Warning), problem(1, Severity::Warning)],
+            0,
+        );
+        assert!(!review.has_errors());
+        review.select_next(false);
+        assert_eq!(review.selected, 0);
+        review.select_next(true);
+        review.select_next(true);
+        assert_eq!(review.selected_problem().unwrap().index, 1);
+    }
+}
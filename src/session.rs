@@ -0,0 +1,257 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{eyre::Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    beancount::{set_textarea_content, textarea_content, PostingTui, Price, TransactionTui},
+    utils::write_atomically,
+};
+
+/// Review progress that survives restarting the app
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Session {
+    pub content_hash: u64, // hash of the input file the session belongs to
+    #[serde(default)]
+    pub n_transactions: usize, // number of transactions read from the file, 0 in older sessions
+    pub current_index: usize,
+    pub marks: Vec<usize>,
+    pub edited: Vec<EditedTransaction>,
+}
+
+/// The field contents of a transaction that was edited
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EditedTransaction {
+    pub index: usize,
+    /// Source line of the transaction, finds it again even if the transactions were reordered
+    #[serde(default)]
+    pub line_number: u32,
+    /// File the transaction was read from, several files can have one at the same line
+    #[serde(default)]
+    pub source: PathBuf,
+    pub metadata: Vec<String>,
+    pub postings: Vec<[String; 3]>, // account, amount, currency
+    #[serde(default)]
+    pub posting_flags: Vec<Option<char>>,
+    #[serde(default)]
+    pub comments: Vec<String>, // comment lines above the header
+    #[serde(default)]
+    pub posting_details: Vec<PostingDetails>, // empty in older sessions
+}
+
+/// What a posting keeps besides its text fields, none of it can be edited
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PostingDetails {
+    pub cost: Option<String>,
+    pub price: Option<Price>,
+    pub comment: Option<String>,
+    pub lines: Vec<String>,
+}
+
+impl EditedTransaction {
+    pub fn from_transaction(index: usize, transaction: &TransactionTui) -> Self {
+        Self {
+            index,
+            line_number: transaction.line_number,
+            source: transaction.source.clone(),
+            metadata: transaction
+                .metadata_textareas()
+                .iter()
+                .map(textarea_content)
+                .collect(),
+            postings: transaction
+                .postings_textareas()
+                .iter()
+                .map(|p| {
+                    [
+                        textarea_content(&p.account_textarea),
+                        textarea_content(&p.amount_textarea),
+                        textarea_content(&p.currency_textarea),
+                    ]
+                })
+                .collect(),
+            posting_flags: transaction
+                .postings_textareas()
+                .iter()
+                .map(|p| p.flag)
+                .collect(),
+            comments: transaction.comments.clone(),
+            posting_details: transaction
+                .postings_textareas()
+                .iter()
+                .map(|p| PostingDetails {
+                    cost: p.cost.clone(),
+                    price: p.price.clone(),
+                    comment: p.comment.clone(),
+                    lines: p.lines.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Overwrites the fields of `transaction` with the stored contents
+    pub fn apply(&self, transaction: &mut TransactionTui) {
+        for (textarea, content) in transaction
+            .metadata_textareas_mut()
+            .iter_mut()
+            .zip(&self.metadata)
+        {
+            set_textarea_content(textarea, content);
+        }
+        let postings = transaction.postings_textareas_mut();
+        // older sessions don't store them, the parsed posting at the same position is
+        // the best guess then
+        let details = |index: usize| match self.posting_details.get(index) {
+            Some(details) => details.clone(),
+            None => postings
+                .get(index)
+                .map(|posting| PostingDetails {
+                    cost: posting.cost.clone(),
+                    price: posting.price.clone(),
+                    ..PostingDetails::default()
+                })
+                .unwrap_or_default(),
+        };
+        let restored = self
+            .postings
+            .iter()
+            .enumerate()
+            .map(|(index, [account, amount, currency])| {
+                let PostingDetails {
+                    cost,
+                    price,
+                    comment,
+                    lines,
+                } = details(index);
+                PostingTui {
+                    flag: self.posting_flags.get(index).copied().flatten(),
+                    cost,
+                    price,
+                    comment,
+                    lines,
+                    ..PostingTui::new(account, amount, currency)
+                }
+            })
+            .collect();
+        *postings = restored;
+        transaction.comments = self.comments.clone();
+        transaction.dirty = true;
+    }
+}
+
+impl Session {
+    /// Reads the session stored at `path`, `None` if there is none
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path)
+            .wrap_err_with(|| format!("couldn't read session {}", path.display()))?;
+        let session = serde_json::from_str(&content)
+            .wrap_err_with(|| format!("couldn't parse session {}", path.display()))?;
+        Ok(Some(session))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_atomically(path, &serde_json::to_string(self)?)
+            .wrap_err_with(|| format!("couldn't write session {}", path.display()))
+    }
+}
+
+/// Where the session of the given input file is stored, keyed by a hash of its absolute path
+pub fn session_path(file: &Path) -> Option<PathBuf> {
+    let state_dir = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))?;
+    let file = fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+    let key = content_hash(file.as_os_str().as_encoded_bytes());
+    Some(
+        state_dir
+            .join("beancount-tui")
+            .join(format!("{:016x}.json", key)),
+    )
+}
+
+/// 64 bit FNV-1a hash, unlike `DefaultHasher` it is stable between builds
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_hash_is_stable() {
+        assert_eq!(content_hash(b""), 0xcbf29ce484222325);
+        assert_eq!(content_hash(b"a"), 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn session_round_trip() {
+        let path = env::temp_dir().join(format!("beancount-tui-{}.json", std::process::id()));
+        let session = Session {
+            content_hash: 42,
+            n_transactions: 4,
+            current_index: 3,
+            marks: vec![1],
+            edited: vec![EditedTransaction {
+                index: 3,
+                line_number: 12,
+                source: PathBuf::from("main.beancount"),
+                metadata: vec!["2023-10-31".to_string()],
+                postings: vec![[
+                    "Assets:Test".to_string(),
+                    "5".to_string(),
+                    "USD".to_string(),
+                ]],
+                posting_flags: vec![Some('!')],
+                comments: vec!["; source: march.csv".to_string()],
+                posting_details: vec![PostingDetails {
+                    price: Some(Price::new(false, "1.10", "EUR")),
+                    ..PostingDetails::default()
+                }],
+            }],
+        };
+        session.write(&path).unwrap();
+        let loaded = Session::load(&path).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.content_hash, 42);
+        assert_eq!(loaded.current_index, 3);
+        assert_eq!(loaded.edited[0].postings[0][2], "USD");
+        assert_eq!(loaded.edited[0].posting_flags, [Some('!')]);
+        assert_eq!(loaded.edited[0].comments, ["; source: march.csv"]);
+    }
+
+    #[test]
+    fn prices_stay_with_their_posting() {
+        let parse = || -> TransactionTui {
+            let file: beancount_parser::BeancountFile<rust_decimal::Decimal> =
+                "2024-01-01 * \"FX\"\n  Assets:EUR  10 EUR @ 1.10 USD\n  Assets:USD  -11 USD\n"
+                    .parse()
+                    .unwrap();
+            file.directives[0].clone().try_into().unwrap()
+        };
+        let mut transaction = parse();
+        transaction
+            .postings_textareas_mut()
+            .insert(0, PostingTui::new("Expenses:Fees", "1", "USD"));
+        let edited = EditedTransaction::from_transaction(0, &transaction);
+        let mut restored = parse();
+        edited.apply(&mut restored);
+        let prices: Vec<_> = restored
+            .postings_textareas()
+            .iter()
+            .map(|posting| posting.price.clone())
+            .collect();
+        assert_eq!(prices, [None, Some(Price::new(false, "1.10", "USD")), None]);
+    }
+}
@@ -1,23 +1,80 @@
 use ratatui::{
     backend::CrosstermBackend,
     crossterm::{
+        event::{DisableBracketedPaste, EnableBracketedPaste},
         execute,
+        style::{available_color_count, force_color_output},
         terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     },
     Terminal,
 };
-use std::io::{self, stdout, Stdout};
+use std::{
+    env,
+    ffi::OsString,
+    io::{self, stdout, Stdout},
+};
+
+use crate::cli::ColorChoice;
 
 pub type Tui = Terminal<CrosstermBackend<Stdout>>;
 
-/// Initialize the terminal
-pub fn init() -> io::Result<Tui> {
-    execute!(stdout(), EnterAlternateScreen)?;
-    enable_raw_mode()?;
+/// How many colors the terminal can show, from none to 24-bit RGB
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorSupport {
+    None,      // only text attributes like bold and reverse video
+    Basic,     // the 16 ANSI colors
+    Ansi256,   // the 256 color palette
+    TrueColor, // any RGB color
+}
+
+/// The colors to draw with for the `--color` choice, `auto` asks the environment
+pub fn color_support(choice: ColorChoice) -> ColorSupport {
+    match choice {
+        ColorChoice::Always => ColorSupport::TrueColor,
+        ColorChoice::Never => ColorSupport::None,
+        ColorChoice::Auto => detect_color_support(
+            env::var_os("NO_COLOR"),
+            env::var("TERM").ok().as_deref(),
+            available_color_count(),
+        ),
+    }
+}
+
+/// `colors` is the count crossterm derives from COLORTERM and TERM
+fn detect_color_support(
+    no_color: Option<OsString>,
+    term: Option<&str>,
+    colors: u16,
+) -> ColorSupport {
+    if no_color.is_some_and(|value| !value.is_empty()) {
+        return ColorSupport::None;
+    }
+    // serial consoles and many CI runners set no TERM or a dumb one, Windows never sets it
+    if matches!(term, None | Some("" | "dumb")) && !cfg!(windows) {
+        return ColorSupport::None;
+    }
+    match colors {
+        u16::MAX => ColorSupport::TrueColor,
+        256.. => ColorSupport::Ansi256,
+        _ => ColorSupport::Basic,
+    }
+}
+
+/// Initialize the terminal, `--color always` draws colors even with NO_COLOR set
+pub fn init(color: ColorChoice) -> io::Result<Tui> {
     set_panic_hook();
+    if color == ColorChoice::Always {
+        force_color_output(true);
+    }
+    // pasted text arrives as one event instead of keys, so line breaks don't act as Enter
+    execute!(stdout(), EnterAlternateScreen, EnableBracketedPaste)?;
+    enable_raw_mode()?;
     Terminal::new(CrosstermBackend::new(stdout()))
 }
 
+/// Restores the terminal before the panic is reported, otherwise the report ends up
+/// in the alternate screen and the shell stays in raw mode. The previous hook is
+/// the one of `color_eyre`, so its report is printed as usual.
 fn set_panic_hook() {
     let hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |panic_info| {
@@ -27,7 +84,35 @@ fn set_panic_hook() {
 }
 
 pub fn restore() -> io::Result<()> {
-    execute!(stdout(), LeaveAlternateScreen)?;
+    execute!(stdout(), DisableBracketedPaste, LeaveAlternateScreen)?;
     disable_raw_mode()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_from_the_environment() {
+        let detect = |no_color: Option<&str>, term, colors| {
+            detect_color_support(no_color.map(OsString::from), term, colors)
+        };
+        assert_eq!(
+            detect(None, Some("xterm"), u16::MAX),
+            ColorSupport::TrueColor
+        );
+        assert_eq!(
+            detect(None, Some("xterm-256color"), 256),
+            ColorSupport::Ansi256
+        );
+        assert_eq!(detect(None, Some("vt100"), 8), ColorSupport::Basic);
+        assert_eq!(detect(Some("1"), Some("xterm"), 256), ColorSupport::None);
+        // an empty NO_COLOR doesn't count
+        assert_eq!(detect(Some(""), Some("xterm"), 8), ColorSupport::Basic);
+        if !cfg!(windows) {
+            assert_eq!(detect(None, Some("dumb"), 8), ColorSupport::None);
+            assert_eq!(detect(None, None, 8), ColorSupport::None);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use ratatui::style::{Color, Modifier, Style};
+
+use crate::terminal::ColorSupport;
+
+/// The styles used to draw the app
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    /// Border of the focused field
+    pub focused_border: Style,
+    /// Border of the other fields that differ from the parsed transaction
+    pub changed_border: Style,
+    /// Cursor in the focused field
+    pub cursor: Style,
+    /// Key names in the instructions
+    pub key: Style,
+    /// Problems like an unbalanced currency
+    pub error: Style,
+    /// Likely mistakes that beancount would reject, like a posting to a closed account
+    pub warning: Style,
+    /// Things that are fine, like a balanced currency
+    pub ok: Style,
+    /// Less important information
+    pub muted: Style,
+    /// The selected entry of a list
+    pub selected: Style,
+}
+
+impl Theme {
+    pub const COLOR: Self = Self {
+        focused_border: Style::new().fg(Color::Yellow),
+        changed_border: Style::new().fg(Color::Cyan),
+        cursor: Style::new().add_modifier(Modifier::REVERSED),
+        key: Style::new().fg(Color::Blue).add_modifier(Modifier::BOLD),
+        error: Style::new().fg(Color::Red),
+        warning: Style::new().fg(Color::Magenta),
+        ok: Style::new().fg(Color::Green),
+        muted: Style::new().fg(Color::DarkGray),
+        selected: Style::new().add_modifier(Modifier::REVERSED),
+    };
+
+    /// Only uses text attributes, for terminals without colors, `NO_COLOR` and `--color never`
+    pub const MONOCHROME: Self = Self {
+        focused_border: Style::new().add_modifier(Modifier::REVERSED),
+        changed_border: Style::new().add_modifier(Modifier::BOLD),
+        cursor: Style::new().add_modifier(Modifier::REVERSED),
+        key: Style::new().add_modifier(Modifier::BOLD),
+        error: Style::new().add_modifier(Modifier::UNDERLINED),
+        warning: Style::new().add_modifier(Modifier::ITALIC),
+        ok: Style::new(),
+        muted: Style::new(),
+        selected: Style::new().add_modifier(Modifier::REVERSED),
+    };
+
+    /// The colors of `COLOR` as far as the terminal can show them, `MONOCHROME`
+    /// without colors
+    pub fn new(support: ColorSupport) -> Self {
+        if support == ColorSupport::None {
+            return Self::MONOCHROME;
+        }
+        let mut theme = Self::COLOR;
+        for style in theme.styles_mut() {
+            style.fg = style.fg.map(|color| downgrade(color, support));
+            style.bg = style.bg.map(|color| downgrade(color, support));
+        }
+        theme
+    }
+
+    fn styles_mut(&mut self) -> [&mut Style; 9] {
+        [
+            &mut self.focused_border,
+            &mut self.changed_border,
+            &mut self.cursor,
+            &mut self.key,
+            &mut self.error,
+            &mut self.warning,
+            &mut self.ok,
+            &mut self.muted,
+            &mut self.selected,
+        ]
+    }
+}
+
+/// The closest color the terminal can show
+fn downgrade(color: Color, support: ColorSupport) -> Color {
+    match (color, support) {
+        (Color::Rgb(r, g, b), ColorSupport::Ansi256) => {
+            let level = |c: u8| (c as u16 * 5 + 127) / 255;
+            Color::Indexed((16 + 36 * level(r) + 6 * level(g) + level(b)) as u8)
+        }
+        (Color::Rgb(r, g, b), ColorSupport::Basic) => basic_color(r, g, b),
+        (Color::Indexed(index), ColorSupport::Basic) if index >= 16 => {
+            let (r, g, b) = indexed_rgb(index);
+            basic_color(r, g, b)
+        }
+        (color, _) => color,
+    }
+}
+
+/// The RGB value of a color of the 6x6x6 cube or the gray ramp of the 256 color palette
+fn indexed_rgb(index: u8) -> (u8, u8, u8) {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    if index >= 232 {
+        let gray = 8 + (index - 232) * 10;
+        return (gray, gray, gray);
+    }
+    let index = (index - 16) as usize;
+    (LEVELS[index / 36], LEVELS[index / 6 % 6], LEVELS[index % 6])
+}
+
+/// One of the 16 ANSI colors, channels count when they are over half of the brightest one
+fn basic_color(r: u8, g: u8, b: u8) -> Color {
+    let max = r.max(g).max(b);
+    if max < 64 {
+        return Color::Black;
+    }
+    let bright = max > 191;
+    let on = |c: u8| c > max / 2;
+    match (on(r), on(g), on(b), bright) {
+        (true, false, false, false) => Color::Red,
+        (true, false, false, true) => Color::LightRed,
+        (false, true, false, false) => Color::Green,
+        (false, true, false, true) => Color::LightGreen,
+        (true, true, false, false) => Color::Yellow,
+        (true, true, false, true) => Color::LightYellow,
+        (false, false, true, false) => Color::Blue,
+        (false, false, true, true) => Color::LightBlue,
+        (true, false, true, false) => Color::Magenta,
+        (true, false, true, true) => Color::LightMagenta,
+        (false, true, true, false) => Color::Cyan,
+        (false, true, true, true) => Color::LightCyan,
+        (_, _, _, true) => Color::White,
+        _ if max >= 128 => Color::Gray,
+        _ => Color::DarkGray,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downgrade_to_what_the_terminal_shows() {
+        let orange = Color::Rgb(255, 135, 0);
+        assert_eq!(downgrade(orange, ColorSupport::TrueColor), orange);
+        assert_eq!(
+            downgrade(orange, ColorSupport::Ansi256),
+            Color::Indexed(214)
+        );
+        assert_eq!(downgrade(orange, ColorSupport::Basic), Color::LightYellow);
+        assert_eq!(
+            downgrade(Color::Indexed(238), ColorSupport::Basic),
+            Color::DarkGray
+        );
+        assert_eq!(downgrade(Color::Red, ColorSupport::Basic), Color::Red);
+    }
+
+    #[test]
+    fn no_colors_use_attributes() {
+        assert_eq!(Theme::new(ColorSupport::None), Theme::MONOCHROME);
+        assert_eq!(Theme::new(ColorSupport::Basic), Theme::COLOR);
+    }
+}
@@ -1,32 +1,75 @@
-use color_eyre::eyre::{OptionExt, Result};
+use std::{ops::Range, time::Duration};
+
+use chrono::Datelike;
+use color_eyre::eyre::Result;
 use ratatui::{
-    layout::{Constraint, Layout, Rect},
+    layout::{Constraint, Flex, Layout, Rect},
     style::Stylize,
     symbols::border,
-    text::Line,
-    widgets::{Block, Borders},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, List, ListState, Paragraph, Wrap},
     Frame,
 };
+use tui_textarea::TextArea;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::{
+    account_browser::AccountBrowser,
+    app::{App, InputFieldType, METAFIELD_ORDER, POSTING_FIELD_ORDER},
+    beancount::{
+        balance_residual, format_transaction, is_valid_amount, posting_totals, textarea_content,
+        PostingField,
+    },
+    calendar::Calendar,
+    keymap::{
+        command_key, context_commands, popup_hints, ACCOUNT_BROWSER_HINTS, CALENDAR_HINTS,
+        CHECK_FAILURE_HINTS, COMMENT_EDITOR_HINTS, REJECTED_HINTS, SAVE_REVIEW_HINTS,
+    },
+    save_review::{SaveReview, Severity},
+    theme::Theme,
+    verify::CheckFailure,
+};
 
-use crate::app::App;
+/// The smallest terminal size the layout can be drawn in
+const MIN_WIDTH: u16 = 60;
+const MIN_HEIGHT: u16 = 20;
+const MAX_CONTEXT_LINES: u16 = 4;
+const MAX_METADATA_LINES: u16 = 3;
 
 pub fn draw(frame: &mut Frame, app: &App) -> Result<()> {
+    let area = frame.area();
+    if area.width < MIN_WIDTH || area.height < MIN_HEIGHT {
+        draw_too_small(frame, area);
+        return Ok(());
+    }
     let title = Line::from(
         format!(
-            "Beancount importer ({}/{})",
+            "Beancount importer ({}/{}){}{}{}{}{}{}",
             app.current_index + 1,
-            app.transactions.len()
+            app.transactions.len(),
+            app.source_name()
+                .map(|name| format!(" {}", name))
+                .unwrap_or_default(),
+            if app.marks.contains(&app.current_index) {
+                " [marked]"
+            } else {
+                ""
+            },
+            if app.unsaved_changes { " [+]" } else { "" },
+            if app.rejected.is_empty() {
+                String::new()
+            } else {
+                format!(" [{} rejected]", app.rejected.len())
+            },
+            if app.read_only { " [read-only]" } else { "" },
+            app.filter
+                .as_ref()
+                .map(|filter| format!(" [filter: {}]", filter.describe()))
+                .unwrap_or_default()
         )
         .bold(),
     );
-    let instructions = Line::from(vec![
-        " Prev Transaction ".into(),
-        "<Left>".blue().bold(),
-        " Next Transaction ".into(),
-        "<Right>".blue().bold(),
-        " Quit ".into(),
-        "<Q> ".blue().bold(),
-    ]);
+    let instructions = instructions_line(app, frame.area().width.saturating_sub(4));
     let block = Block::default()
         .title(title.centered())
         .title_bottom(instructions.centered())
@@ -34,74 +77,1182 @@ pub fn draw(frame: &mut Frame, app: &App) -> Result<()> {
         .border_set(border::THICK);
     frame.render_widget(&block, frame.area());
     let inner_area = block.inner(frame.area());
-    let vertical_layout = Layout::vertical([Constraint::Length(3), Constraint::Min(10)]);
-    let [metadata_area, postings_area] = vertical_layout.areas(inner_area);
+    let comments = &app.transactions[app.current_index].comments;
+    let metadata = &app.transactions[app.current_index].metadata;
+    let context: Vec<Line> = app
+        .shown_context()
+        .iter()
+        .flat_map(|directive| directive.text.lines())
+        .map(|line| Line::styled(line.to_string(), app.theme.muted))
+        .collect();
+    let vertical_layout = Layout::vertical([
+        // at most a few lines, the transaction needs the space
+        Constraint::Length((context.len() as u16).min(MAX_CONTEXT_LINES)),
+        Constraint::Length(if comments.is_empty() { 0 } else { 1 }),
+        Constraint::Length(3),
+        Constraint::Length((metadata.len() as u16).min(MAX_METADATA_LINES)),
+        Constraint::Min(10),
+        Constraint::Length(1),
+    ]);
+    let [context_area, comments_area, metadata_area, key_values_area, postings_area, status_area] =
+        vertical_layout.areas(inner_area);
+    // the last lines are the closest to the transaction
+    let skipped = context.len().saturating_sub(context_area.height as usize);
+    frame.render_widget(Paragraph::new(context[skipped..].to_vec()), context_area);
 
     // draw_transaction(frame, app, transaction_area);
     // draw_edit(frame, app, edit_area);
+    // read-only here, they are edited in the comment editor
+    frame.render_widget(
+        Line::styled(comments.join("  "), app.theme.muted),
+        comments_area,
+    );
     draw_metadata_fields(frame, app, metadata_area)?;
+    draw_key_values(frame, app, metadata, key_values_area);
     draw_postings(frame, app, postings_area)?;
+    draw_status_bar(frame, app, status_area);
+    if let Some(calendar) = &app.calendar {
+        draw_calendar(frame, app, calendar);
+    }
+    if let Some(browser) = &app.account_browser {
+        draw_account_browser(frame, app, browser);
+    }
+    if let Some(editor) = &app.comment_editor {
+        draw_comment_editor(frame, app, editor);
+    }
+    if let Some(review) = &app.save_review {
+        draw_save_review(frame, app, review);
+    }
+    if let Some(selected) = app.rejected_list {
+        draw_rejected_list(frame, app, selected);
+    }
+    if let Some(failure) = &app.check_failure {
+        draw_check_failure(frame, app, failure);
+    }
+    if app.popup.active {
+        draw_popup(frame, app);
+    }
     Ok(())
 }
 
+/// The `key: value` lines of the parsed directive, read only. Values are cut to the
+/// width and the last line tells how many lines didn't fit.
+fn draw_key_values(frame: &mut Frame, app: &App, metadata: &[(String, String)], area: Rect) {
+    let width = area.width.saturating_sub(2) as usize;
+    let shown = area.height as usize;
+    let mut lines: Vec<Line> = metadata
+        .iter()
+        .take(shown)
+        .map(|(key, value)| {
+            let text = format!("  {}: {}", key, value);
+            Line::styled(cut_to_width(&text, width), app.theme.muted)
+        })
+        .collect();
+    if metadata.len() > shown {
+        if let Some(last) = lines.last_mut() {
+            *last = Line::styled(
+                format!("  … {} more", metadata.len() - shown + 1),
+                app.theme.muted,
+            );
+        }
+    }
+    frame.render_widget(Paragraph::new(lines), area);
+}
+
+/// `text` cut to `width` columns, ending in `…` when it was cut
+fn cut_to_width(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+    let mut cut = String::new();
+    let mut cut_width = 0;
+    for c in text.chars() {
+        cut_width += c.width().unwrap_or(0);
+        // one column is left for the `…`
+        if cut_width + 1 > width {
+            break;
+        }
+        cut.push(c);
+    }
+    cut.push('…');
+    cut
+}
+
+fn draw_too_small(frame: &mut Frame, area: Rect) {
+    let message = format!(
+        "terminal too small (need {}x{}, have {}x{})",
+        MIN_WIDTH, MIN_HEIGHT, area.width, area.height
+    );
+    let [message_area] = Layout::vertical([Constraint::Length(3)])
+        .flex(Flex::Center)
+        .areas(area);
+    frame.render_widget(
+        Paragraph::new(message).centered().wrap(Wrap { trim: true }),
+        message_area,
+    );
+}
+
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Formats a count with thousands separators, like `12,345`
+fn thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(digit);
+    }
+    out
+}
+
+/// Draws the screen shown while the ledger is parsed in the background
+pub fn draw_loading(
+    frame: &mut Frame,
+    theme: &Theme,
+    file: &str,
+    directives: usize,
+    elapsed: Duration,
+) {
+    let spinner = SPINNER[(elapsed.as_millis() / 100) as usize % SPINNER.len()];
+    let lines = vec![
+        Line::from(format!(
+            "{} Parsing {}… {} directives",
+            spinner,
+            file,
+            thousands(directives)
+        )),
+        Line::styled("Ctrl-c to cancel", theme.muted),
+    ];
+    let [area] = Layout::vertical([Constraint::Length(lines.len() as u16)])
+        .flex(Flex::Center)
+        .areas(frame.area());
+    frame.render_widget(
+        Paragraph::new(lines).centered().wrap(Wrap { trim: true }),
+        area,
+    );
+}
+
+/// Returns a rectangle of the given size centered in `area`
+fn centered_rect(area: Rect, width: u16, height: u16) -> Rect {
+    let [area] = Layout::horizontal([Constraint::Length(width)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(area);
+    area
+}
+
+/// Draws the month of the date picker in the middle of the screen
+fn draw_calendar(frame: &mut Frame, app: &App, calendar: &Calendar) {
+    let mut lines = vec![Line::styled("Mo Tu We Th Fr Sa Su", app.theme.muted)];
+    for week in calendar.weeks() {
+        let mut spans = Vec::new();
+        for (weekday, day) in week.iter().enumerate() {
+            if weekday > 0 {
+                spans.push(Span::from(" "));
+            }
+            let span = match day {
+                Some(day) => Span::from(format!("{:>2}", day.day())),
+                None => Span::from("  "),
+            };
+            spans.push(if *day == Some(calendar.selected) {
+                span.style(app.theme.selected)
+            } else {
+                span
+            });
+        }
+        lines.push(Line::from(spans));
+    }
+    // 7 days of 2 cells with a space between, and the borders
+    let area = centered_rect(frame.area(), 22, lines.len() as u16 + 2);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(calendar.title())
+        .border_style(app.theme.focused_border);
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// The most accounts the tree shows at once, it scrolls to the selected one
+const ACCOUNT_BROWSER_ROWS: u16 = 15;
+
+fn draw_account_browser(frame: &mut Frame, app: &App, browser: &AccountBrowser) {
+    let items: Vec<Line> = browser
+        .rows()
+        .into_iter()
+        .map(|row| {
+            let marker = match (row.node.children.is_empty(), row.expanded) {
+                (true, _) => "  ",
+                (false, false) => "▸ ",
+                (false, true) => "▾ ",
+            };
+            Line::from(format!(
+                "{}{}{}",
+                "  ".repeat(row.depth),
+                marker,
+                row.node.name
+            ))
+        })
+        .collect();
+    let height = (items.len() as u16).min(ACCOUNT_BROWSER_ROWS) + 2;
+    let area = centered_rect(frame.area(), POPUP_WIDTH, height);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Accounts")
+        .border_style(app.theme.focused_border);
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(app.theme.selected);
+    let mut state = ListState::default().with_selected(Some(browser.selected));
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+/// The most comment lines the editor shows at once
+const COMMENT_EDITOR_ROWS: u16 = 10;
+
+fn draw_comment_editor(frame: &mut Frame, app: &App, editor: &TextArea) {
+    let height = (editor.lines().len() as u16).clamp(3, COMMENT_EDITOR_ROWS) + 2;
+    let area = centered_rect(frame.area(), POPUP_WIDTH, height);
+    let instructions = hint_line(
+        enabled(COMMENT_EDITOR_HINTS),
+        app,
+        area.width.saturating_sub(2),
+    );
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Comments above the transaction")
+        .title_bottom(instructions.centered())
+        .border_style(app.theme.focused_border);
+    let inner_area = block.inner(area);
+    frame.render_widget(Clear, area);
+    frame.render_widget(block, area);
+    frame.render_widget(editor, inner_area);
+}
+
+/// Lists the rejected transactions by their header line, the selected one can be restored
+fn draw_rejected_list(frame: &mut Frame, app: &App, selected: usize) {
+    let height = (app.rejected.len() as u16 + 2).clamp(5, frame.area().height);
+    let area = centered_rect(frame.area(), frame.area().width.saturating_sub(8), height);
+    let instructions = hint_line(enabled(REJECTED_HINTS), app, area.width.saturating_sub(2));
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(Line::from(format!(" Rejected ({}) ", app.rejected.len())).bold())
+        .title_bottom(instructions.centered());
+    let items: Vec<Line> = app
+        .rejected
+        .iter()
+        .map(|(index, transaction)| {
+            let transaction = format_transaction(transaction);
+            let header = transaction.lines().next().unwrap_or_default();
+            Line::from(vec![
+                Span::styled(format!("{:>5}  ", index + 1), app.theme.muted),
+                Span::from(header.to_string()),
+            ])
+        })
+        .collect();
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(app.theme.selected);
+    let mut state = ListState::default().with_selected(Some(selected));
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+/// Shows why a save was blocked, scrolled to `failure.scroll`
+fn draw_check_failure(frame: &mut Frame, app: &App, failure: &CheckFailure) {
+    let height = (failure.details.len() as u16 + 2).clamp(5, frame.area().height);
+    let area = centered_rect(frame.area(), frame.area().width.saturating_sub(4), height);
+    let instructions = hint_line(
+        enabled(CHECK_FAILURE_HINTS),
+        app,
+        area.width.saturating_sub(2),
+    );
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(Line::from(format!(" Not saved: {} ", failure.summary)).bold())
+        .title_bottom(instructions.centered())
+        .border_style(app.theme.error);
+    let details: Vec<Line> = failure
+        .details
+        .iter()
+        .map(|line| Line::from(line.as_str()))
+        .collect();
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(details)
+            .block(block)
+            .scroll((failure.scroll as u16, 0)),
+        area,
+    );
+}
+
+/// Widest payee the problems before saving show, longer ones are cut
+const REVIEW_PAYEE_WIDTH: usize = 24;
+
+/// Lists the problems found before saving over the whole screen
+fn draw_save_review(frame: &mut Frame, app: &App, review: &SaveReview) {
+    let area = frame.area();
+    let hints = SAVE_REVIEW_HINTS
+        .iter()
+        .map(|&(key, description)| (key, description, key != "s" || !review.has_errors()));
+    let instructions = hint_line(hints, app, area.width.saturating_sub(4));
+    let block = Block::default()
+        .title(Line::from("Review before saving").bold().centered())
+        .title_bottom(instructions.centered())
+        .borders(Borders::ALL)
+        .border_set(border::THICK);
+    let inner_area = block.inner(area);
+    frame.render_widget(Clear, area);
+    frame.render_widget(block, area);
+    let [summary_area, list_area, status_area] = Layout::vertical([
+        Constraint::Length(2),
+        Constraint::Min(0),
+        Constraint::Length(1),
+    ])
+    .areas(inner_area);
+    let mut summary = vec![Span::from(review.summary())];
+    if review.has_errors() {
+        summary.push(Span::styled(
+            " (fix the failing ones to save)",
+            app.theme.error,
+        ));
+    }
+    frame.render_widget(Line::from(summary), summary_area);
+    let items: Vec<Line> = review
+        .problems
+        .iter()
+        .map(|problem| {
+            let style = match problem.severity {
+                Severity::Error => app.theme.error,
+                Severity::Warning => app.theme.warning,
+            };
+            let payee: String = problem.payee.chars().take(REVIEW_PAYEE_WIDTH).collect();
+            Line::from(vec![
+                Span::styled(format!("{:>5}  ", problem.index + 1), app.theme.muted),
+                Span::from(format!(
+                    "{}  {:<width$}  ",
+                    problem.date,
+                    payee,
+                    width = REVIEW_PAYEE_WIDTH
+                )),
+                Span::styled(problem.issue.clone(), style),
+            ])
+        })
+        .collect();
+    let list = List::new(items).highlight_style(app.theme.selected);
+    let mut state = ListState::default().with_selected(Some(review.selected));
+    frame.render_stateful_widget(list, list_area, &mut state);
+    if let Some(message) = &app.status_message {
+        frame.render_widget(Line::from(message.text.as_str()).italic(), status_area);
+    }
+}
+
+const POPUP_WIDTH: u16 = 60;
+
+fn draw_popup(frame: &mut Frame, app: &App) {
+    // the prompt wraps, so its height is measured in terminal cells, with a spare
+    // line because wrapping at word boundaries can need more lines
+    let prompt_width = Line::from(app.popup.prompt.as_str()).width() as u16;
+    let prompt_lines = prompt_width.div_ceil(POPUP_WIDTH - 2).max(1) + 1;
+    let height = (prompt_lines + 2).clamp(5, frame.area().height);
+    let area = centered_rect(frame.area(), POPUP_WIDTH, height);
+    let instructions = hint_line(
+        enabled(popup_hints(&app.popup.on_confirm)),
+        app,
+        area.width.saturating_sub(2),
+    );
+    let block = Block::default()
+        .title_bottom(instructions.centered())
+        .borders(Borders::ALL)
+        .border_set(border::THICK);
+    let popup = Paragraph::new(app.popup.prompt.as_str())
+        .centered()
+        .wrap(Wrap { trim: true })
+        .block(block);
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+fn draw_status_bar(frame: &mut Frame, app: &App, area: Rect) {
+    let mut balance_chips = Vec::new();
+    for (currency, residual) in balance_residual(&app.transactions[app.current_index]) {
+        let chip = match residual {
+            Some(residual) if !residual.is_zero() => {
+                Span::styled(format!(" {} {} ", currency, residual), app.theme.error)
+            }
+            _ => Span::styled(format!(" {} ✓ ", currency), app.theme.ok),
+        };
+        balance_chips.push(chip);
+    }
+    let unopened = app.unopened_postings().len();
+    if unopened > 0 {
+        let chip = format!(
+            " {} unopened account{} ",
+            unopened,
+            if unopened == 1 { "" } else { "s" }
+        );
+        balance_chips.insert(0, Span::styled(chip, app.theme.error));
+    }
+    let inactive = app.inactive_postings().len();
+    if inactive > 0 {
+        let chip = format!(
+            " {} inactive account{} ",
+            inactive,
+            if inactive == 1 { "" } else { "s" }
+        );
+        balance_chips.insert(0, Span::styled(chip, app.theme.warning));
+    }
+    let balance = Line::from(balance_chips).right_aligned();
+    let (row, column) = app.current_field().cursor();
+    let position = Line::from(format!(
+        " {} {}:{} ",
+        app.transactions[app.current_index].location(),
+        row + 1,
+        column + 1
+    ))
+    .style(app.theme.muted);
+    let [message_area, position_area, balance_area] = Layout::horizontal([
+        Constraint::Min(0),
+        Constraint::Length(position.width() as u16),
+        Constraint::Length(balance.width() as u16),
+    ])
+    .areas(area);
+    frame.render_widget(position, position_area);
+    if let Some(command_line) = &app.command_line {
+        let [prompt_area, input_area] =
+            Layout::horizontal([Constraint::Length(1), Constraint::Min(0)]).areas(message_area);
+        frame.render_widget(Span::from(":"), prompt_area);
+        frame.render_widget(command_line, input_area);
+    } else if let Some(message) = &app.status_message {
+        frame.render_widget(Line::from(message.text.as_str()).italic(), message_area);
+    }
+    frame.render_widget(balance, balance_area);
+}
+
 fn draw_metadata_fields(frame: &mut Frame, app: &App, area: Rect) -> Result<()> {
     let horizontal_layout = Layout::horizontal([
         Constraint::Min(10),
         Constraint::Length(5),
         Constraint::Min(10),
         Constraint::Min(10),
+        Constraint::Min(10),
     ]);
-    let [date_area, flag_area, payee_area, narration_area] = horizontal_layout.areas(area);
+    let [date_area, flag_area, payee_area, narration_area, comment_area] =
+        horizontal_layout.areas(area);
+    let areas = [
+        date_area,
+        flag_area,
+        payee_area,
+        narration_area,
+        comment_area,
+    ];
     let current_transaction = &app.transactions[app.current_index];
-    let date_textarea = current_transaction
-        .metadata_textareas
-        .get(0)
-        .ok_or_eyre("No date field initialized!")?;
-    let flag_textarea = current_transaction
-        .metadata_textareas
-        .get(1)
-        .ok_or_eyre("No flag field initialized!")?;
-    let payee_textarea = current_transaction
-        .metadata_textareas
-        .get(2)
-        .ok_or_eyre("No payee field initialized!")?;
-    let narration_textarea = current_transaction
-        .metadata_textareas
-        .get(3)
-        .ok_or_eyre("No narration field initialized!")?;
-    frame.render_widget(date_textarea, date_area);
-    frame.render_widget(flag_textarea, flag_area);
-    frame.render_widget(payee_textarea, payee_area);
-    frame.render_widget(narration_textarea, narration_area);
+    let textareas = current_transaction.metadata_textareas();
+    for (index, (textarea, area)) in textareas.iter().zip(areas).enumerate() {
+        let state = FieldState {
+            focused: app.is_metadata_field_focused(index),
+            invalid: false,
+            warning: false,
+            changed: current_transaction.metadata_changed(index),
+        };
+        draw_field(
+            frame,
+            app,
+            textarea,
+            METAFIELD_ORDER[index].title(),
+            state,
+            area,
+        );
+    }
     Ok(())
 }
 
+/// The title of a field, with a `*` if it was changed since it was parsed
+fn field_title(title: &str, changed: bool) -> String {
+    match (changed, title) {
+        (false, _) => title.to_string(),
+        (true, "") => "*".to_string(),
+        (true, _) => format!("{} *", title),
+    }
+}
+
+/// How a field is drawn
+#[derive(Debug, Clone, Copy)]
+struct FieldState {
+    focused: bool, // has the cursor
+    invalid: bool, // the content can't be parsed
+    warning: bool, // likely rejected by beancount
+    changed: bool, // differs from the parsed value
+}
+
+/// Draws `textarea` inside a border titled `title`. The border of the focused field
+/// is highlighted, unless the content is invalid, and only its cursor is visible.
+/// Changed fields get a `*` and, unless focused, their own border color.
+fn draw_field(
+    frame: &mut Frame,
+    app: &App,
+    textarea: &TextArea,
+    title: &str,
+    state: FieldState,
+    area: Rect,
+) {
+    let mut block = Block::default()
+        .borders(Borders::ALL)
+        .title(field_title(title, state.changed));
+    if state.invalid {
+        block = block.border_style(app.theme.error);
+    } else if state.focused {
+        block = block.border_style(app.theme.focused_border);
+    } else if state.warning {
+        block = block.border_style(app.theme.warning);
+    } else if state.changed {
+        block = block.border_style(app.theme.changed_border);
+    }
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    if state.focused {
+        let mut textarea = textarea.clone();
+        textarea.set_cursor_style(app.theme.cursor);
+        frame.render_widget(&textarea, inner);
+    } else {
+        frame.render_widget(textarea, inner);
+    }
+}
+
 fn draw_postings(frame: &mut Frame, app: &App, area: Rect) -> Result<()> {
     let current_transaction = &app.transactions[app.current_index];
-    let postings = &current_transaction.postings_textareas;
+    let postings = &current_transaction.postings_textareas();
+    let [area, totals_area] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(area);
+    draw_posting_totals(frame, app, totals_area);
+    let visible = visible_postings(postings.len(), app.currently_selected_posting, area.height);
+
+    // only the postings that fit are laid out, a transaction can have hundreds
+    let layout = Layout::vertical(vec![Constraint::Length(3); visible.len()]);
+    let areas = layout.split(area);
+    let unopened = app.unopened_postings();
+    let inactive = app.inactive_postings();
 
-    let layout = Layout::vertical(
-        postings
+    for (i, posting_area) in visible.clone().zip(areas.iter()) {
+        let posting = &postings[i];
+        let [flag_area, account_area, amount_area, currency_area] = posting_columns(*posting_area);
+
+        let flag = posting.flag.map(String::from).unwrap_or_default();
+        frame.render_widget(
+            Paragraph::new(flag).block(Block::default().borders(Borders::ALL)),
+            flag_area,
+        );
+        let fields = [account_area, amount_area, currency_area];
+        let inactive_reason = inactive
             .iter()
-            .map(|_| Constraint::Length(3)) // Each posting gets 3 lines
-            .collect::<Vec<_>>(),
+            .find(|(index, _)| *index == i)
+            .map(|(_, reason)| reason);
+        for (field, area) in POSTING_FIELD_ORDER.into_iter().zip(fields) {
+            let textarea = posting.get_field(&field);
+            let state = FieldState {
+                focused: app.is_posting_field_focused(i, field),
+                invalid: field == PostingField::Amount
+                    && !is_valid_amount(&textarea_content(textarea)),
+                warning: field == PostingField::Account && inactive_reason.is_some(),
+                changed: current_transaction.posting_field_changed(i, &field),
+            };
+            let title = InputFieldType::from(field).title();
+            draw_field(frame, app, textarea, title, state, area);
+        }
+        let conversion = [
+            posting.cost.as_ref().map(|cost| format!("{{{}}}", cost)),
+            posting.price.as_ref().map(|price| price.to_string()),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        if !conversion.is_empty() {
+            // drawn over the bottom border, costs and prices can't be edited
+            let conversion = Line::styled(format!(" {} ", conversion.join(" ")), app.theme.muted)
+                .right_aligned();
+            let border = Rect {
+                x: currency_area.x + 1,
+                y: currency_area.bottom().saturating_sub(1),
+                width: currency_area.width.saturating_sub(2),
+                height: 1,
+            };
+            frame.render_widget(conversion, border);
+        }
+        let warning = if unopened.contains(&i) {
+            Some(Line::styled(" not opened ", app.theme.error))
+        } else {
+            inactive_reason.map(|reason| Line::styled(format!(" {} ", reason), app.theme.warning))
+        };
+        if let Some(warning) = warning {
+            // drawn over the top border so the title stays visible
+            let warning = warning.right_aligned();
+            let border = Rect {
+                x: account_area.x + 1,
+                width: account_area.width.saturating_sub(2),
+                height: 1,
+                ..account_area
+            };
+            frame.render_widget(warning, border);
+        }
+    }
+
+    if app.completion.active && app.focus_on_postings {
+        let index = app.currently_selected_posting.checked_sub(visible.start);
+        if let Some(posting_area) = index.and_then(|index| areas.get(index)) {
+            let [_, account_area, _, _] = posting_columns(*posting_area);
+            draw_completion(frame, app, account_area);
+        }
+    }
+
+    Ok(())
+}
+
+/// The debits and credits of the current transaction per currency, e.g. `EUR +7.50 -7.50`
+fn draw_posting_totals(frame: &mut Frame, app: &App, area: Rect) {
+    let totals = posting_totals(&app.transactions[app.current_index]);
+    if totals.is_empty() {
+        return;
+    }
+    let totals = totals
+        .into_iter()
+        .map(|(currency, (debit, credit))| format!("{} +{} {}", currency, debit, credit))
+        .collect::<Vec<_>>()
+        .join("  ");
+    frame.render_widget(
+        Line::styled(format!(" Totals  {} ", totals), app.theme.muted).right_aligned(),
+        area,
     );
+}
 
-    let areas = layout.split(area);
+/// The postings that fit into `height` rows, scrolled just far enough to show the
+/// selected one
+fn visible_postings(n_postings: usize, selected: usize, height: u16) -> Range<usize> {
+    let n_visible = (height as usize / 3).max(1).min(n_postings);
+    let start = (selected + 1)
+        .saturating_sub(n_visible)
+        .min(n_postings - n_visible);
+    start..start + n_visible
+}
 
-    for (i, posting) in postings.iter().enumerate() {
-        let posting_area = areas[i];
-        let horizontal_layout = Layout::horizontal([
-            Constraint::Percentage(50),
-            Constraint::Percentage(25),
-            Constraint::Percentage(25),
-        ]);
-        let [account_area, amount_area, currency_area] = horizontal_layout.areas(posting_area);
+/// The keys that are useful right now, as many as fit into `max_width`
+fn instructions_line(app: &App, max_width: u16) -> Line<'static> {
+    if app.popup.active {
+        return hint_line(enabled(popup_hints(&app.popup.on_confirm)), app, max_width);
+    }
+    if app.calendar.is_some() {
+        return hint_line(enabled(CALENDAR_HINTS), app, max_width);
+    }
+    if app.account_browser.is_some() {
+        return hint_line(enabled(ACCOUNT_BROWSER_HINTS), app, max_width);
+    }
+    if app.rejected_list.is_some() {
+        return hint_line(enabled(REJECTED_HINTS), app, max_width);
+    }
+    if app.check_failure.is_some() {
+        return hint_line(enabled(CHECK_FAILURE_HINTS), app, max_width);
+    }
+    if app.comment_editor.is_some() {
+        return hint_line(enabled(COMMENT_EDITOR_HINTS), app, max_width);
+    }
+    let hints: Vec<(String, &str, bool)> = context_commands(app.focused_field_type())
+        .into_iter()
+        .filter_map(|command| {
+            let available = !(app.read_only && command.is_mutating());
+            Some((command_key(command)?, command.description(), available))
+        })
+        .collect();
+    hint_line(
+        hints
+            .iter()
+            .map(|(key, description, available)| (key.as_str(), *description, *available)),
+        app,
+        max_width,
+    )
+}
+
+/// Hints that are always available
+fn enabled<'a>(hints: &'a [(&'a str, &'a str)]) -> impl Iterator<Item = (&'a str, &'a str, bool)> {
+    hints
+        .iter()
+        .map(|&(key, description)| (key, description, true))
+}
 
-        frame.render_widget(&posting.account_textarea, account_area);
-        frame.render_widget(&posting.amount_textarea, amount_area);
-        frame.render_widget(&posting.currency_textarea, currency_area);
+/// Formats hints like ` save <C-s>`, dropping the ones that don't fit into `max_width`.
+/// Unavailable hints are dimmed.
+fn hint_line<'a>(
+    hints: impl IntoIterator<Item = (&'a str, &'a str, bool)>,
+    app: &App,
+    max_width: u16,
+) -> Line<'static> {
+    let mut spans = Vec::new();
+    let mut width = 1; // trailing space
+    for (key, description, available) in hints {
+        let mut description = Span::from(format!(" {} ", description));
+        let mut key = Span::styled(format!("<{}>", key), app.theme.key);
+        if !available {
+            description = description.dim();
+            key = key.dim();
+        }
+        let hint_width = (description.width() + key.width()) as u16;
+        if width + hint_width > max_width {
+            // later hints are less relevant, so stop at the first one that doesn't fit
+            break;
+        }
+        width += hint_width;
+        spans.push(description);
+        spans.push(key);
     }
+    spans.push(Span::from(" "));
+    Line::from(spans)
+}
 
-    Ok(())
+/// Splits a posting row into the flag, account, amount and currency columns
+fn posting_columns(area: Rect) -> [Rect; 4] {
+    Layout::horizontal([
+        Constraint::Length(5),
+        Constraint::Percentage(50),
+        Constraint::Percentage(25),
+        Constraint::Percentage(25),
+    ])
+    .areas(area)
+}
+
+/// Draws the completion candidates below the account field in `field_area`
+fn draw_completion(frame: &mut Frame, app: &App, field_area: Rect) {
+    let height = (app.completion.candidates.len() as u16).max(1) + 2;
+    let area = Rect {
+        y: field_area.bottom(),
+        height,
+        ..field_area
+    }
+    .intersection(frame.area());
+    let block = Block::default().borders(Borders::ALL).title("Accounts");
+    let list = if app.completion.candidates.is_empty() {
+        List::new(["no matching account".italic()])
+    } else {
+        List::new(app.completion.candidates.iter().map(String::as_str))
+    }
+    .block(block)
+    .highlight_style(app.theme.selected);
+    let mut state = ListState::default().with_selected(Some(app.completion.selected));
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use ratatui::{backend::TestBackend, style::Color, Terminal};
+
+    use super::*;
+    use ratatui::style::Modifier;
+    use tui_textarea::CursorMove;
+
+    use crate::{
+        beancount::{set_textarea_content, PostingTui},
+        cli::Args,
+        save_review::Problem,
+        theme::Theme,
+    };
+
+    fn render(app: &App, width: u16, height: u16) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|frame| draw(frame, app).unwrap()).unwrap();
+        terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect()
+    }
+
+    fn test_app<'t>() -> App<'t> {
+        App::new(Args {
+            file: vec!["data/test.beancount".into()],
+            no_session: true,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn draw_small_terminal_shows_message() {
+        let app = test_app();
+        let rendered = render(&app, 40, 10);
+        assert!(rendered.contains("terminal too small"));
+        let rendered = render(&app, 80, 24);
+        assert!(!rendered.contains("terminal too small"));
+        assert!(rendered.contains("Beancount importer"));
+    }
+
+    #[test]
+    fn status_bar_shows_source_line_and_cursor() {
+        let mut app = test_app();
+        app.current_index = 1;
+        let rendered = render(&app, 80, 24);
+        // the second transaction starts on line 7 of the fixture
+        assert!(rendered.contains(" test.beancount:7 1:1 "));
+        app.transactions[1].line_number = 0;
+        assert!(render(&app, 80, 24).contains(" new 1:1 "));
+    }
+
+    #[test]
+    fn focused_field_keeps_its_title() {
+        let mut app = test_app();
+        app.theme = Theme::COLOR;
+        let focused_border = |app: &App, title: &str| {
+            let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+            terminal.draw(|frame| draw(frame, app).unwrap()).unwrap();
+            let buffer = terminal.backend().buffer();
+            let (x, y) = (0..30)
+                .flat_map(|y| (1..100).map(move |x| (x, y)))
+                .find(|&(x, y)| {
+                    let text: String = (x..(x + title.len() as u16).min(100))
+                        .map(|x| buffer[(x, y)].symbol())
+                        .collect();
+                    text == title
+                })
+                .expect("title is drawn");
+            // the border right before the title
+            buffer[(x - 1, y)].fg == Color::Yellow
+        };
+        assert!(focused_border(&app, "Payee"));
+        assert!(!focused_border(&app, "Narration"));
+        app.currently_selected_metadata_field += 1;
+        assert!(!focused_border(&app, "Payee"));
+        assert!(focused_border(&app, "Narration"));
+    }
+
+    #[test]
+    fn changed_fields_are_marked() {
+        let mut app = test_app();
+        let rendered = render(&app, 100, 30);
+        assert!(rendered.contains("Payee") && !rendered.contains("Payee *"));
+
+        let transaction = &mut app.transactions[0];
+        set_textarea_content(&mut transaction.metadata_textareas_mut()[2], "Bakery");
+        set_textarea_content(
+            &mut transaction.postings_textareas_mut()[1].amount_textarea,
+            "-5",
+        );
+        transaction
+            .postings_textareas_mut()
+            .push(PostingTui::new("", "", ""));
+        app.theme = Theme::COLOR;
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        terminal.draw(|frame| draw(frame, &app).unwrap()).unwrap();
+        let rendered: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(rendered.contains("Payee *"));
+        assert!(!rendered.contains("Narration *"));
+        // the unfocused amount gets its own border color
+        let start = rendered.find("Amount *").unwrap();
+        let cells = terminal.backend().buffer().content();
+        let border = &cells[rendered[..start].chars().count() - 1];
+        assert_eq!(border.fg, Color::Cyan);
+        assert_eq!(rendered.matches("Amount *").count(), 1);
+        // an empty added posting isn't a change yet
+        assert_eq!(rendered.matches("Account *").count(), 0);
+
+        // changing a field back removes the marker
+        set_textarea_content(
+            &mut app.transactions[0].metadata_textareas_mut()[2],
+            "Test Co",
+        );
+        assert!(!render(&app, 100, 30).contains("Payee *"));
+    }
+
+    #[test]
+    fn calendar_shows_the_selected_month() {
+        let mut app = test_app();
+        app.calendar = Some(Calendar::new(
+            NaiveDate::from_ymd_opt(2023, 10, 17).unwrap(),
+        ));
+        let rendered = render(&app, 100, 30);
+        assert!(rendered.contains("October 2023"));
+        assert!(rendered.contains("Mo Tu We Th Fr Sa Su"));
+        // October 2023 starts on a Sunday
+        assert!(rendered.contains("                   1"));
+        assert!(rendered.contains("pick"));
+    }
+
+    #[test]
+    fn read_only_mode_dims_edits() {
+        let mut app = test_app();
+        app.read_only = true;
+        let mut terminal = Terminal::new(TestBackend::new(160, 30)).unwrap();
+        terminal.draw(|frame| draw(frame, &app).unwrap()).unwrap();
+        let buffer = terminal.backend().buffer();
+        let bottom: String = (0..160).map(|x| buffer[(x, 29)].symbol()).collect();
+        let dimmed = |hint: &str| {
+            let start = bottom.find(hint).expect("hint is shown");
+            let x = bottom[..start].chars().count() as u16;
+            buffer[(x + 1, 29)].modifier.contains(Modifier::DIM)
+        };
+        assert!(dimmed(" save "));
+        assert!(!dimmed(" next "));
+        assert!(render(&app, 100, 30).contains("[read-only]"));
+    }
+
+    #[test]
+    fn account_browser_shows_the_tree() {
+        let mut app = test_app();
+        app.account_browser = Some(AccountBrowser::new(&app.known_accounts, "Expenses:Test"));
+        let rendered = render(&app, 100, 30);
+        assert!(rendered.contains("▸ Assets"));
+        assert!(rendered.contains("▾ Expenses"));
+        assert!(rendered.contains("    Test1"));
+        assert!(rendered.contains("insert"));
+    }
+
+    #[test]
+    fn posting_totals_under_the_postings() {
+        let mut app = test_app();
+        assert!(render(&app, 100, 30).contains(" Totals  USD +5 0 "));
+        app.current_index = 2;
+        assert!(render(&app, 100, 30).contains(" Totals  USD +2 -5 "));
+    }
+
+    #[test]
+    fn comments_above_the_metadata() {
+        let mut app = test_app();
+        assert!(!render(&app, 100, 30).contains("; source"));
+        app.transactions[0].comments = vec!["; source: a.csv".to_string(), "; row 3".to_string()];
+        let rendered = render(&app, 100, 30);
+        assert!(rendered.contains("; source: a.csv  ; row 3"));
+    }
+
+    #[test]
+    fn metadata_below_the_header_is_cut() {
+        let mut app = test_app();
+        app.transactions[0].metadata = vec![
+            ("document".to_string(), format!("\"{}\"", "x".repeat(200))),
+            ("time".to_string(), "\"08:15\"".to_string()),
+        ];
+        let rendered = render(&app, 100, 30);
+        assert!(rendered.contains("  time: \"08:15\""));
+        assert!(rendered.contains("xxx…"));
+        app.transactions[0]
+            .metadata
+            .extend(["a", "b"].map(|key| (key.to_string(), "1".to_string())));
+        assert!(render(&app, 100, 30).contains("… 2 more"));
+    }
+
+    #[test]
+    fn save_review_covers_the_screen() {
+        let mut app = test_app();
+        app.save_review = Some(SaveReview::new(
+            vec![Problem {
+                index: 1,
+                date: "2023-01-11".to_string(),
+                payee: "Test Co".to_string(),
+                issue: "unbalanced by 5 USD".to_string(),
+                severity: Severity::Error,
+            }],
+            1,
+        ));
+        let rendered = render(&app, 100, 30);
+        assert!(rendered.contains("Review before saving"));
+        assert!(rendered.contains("1 edited, 1 failing validation, 0 with warnings"));
+        assert!(rendered.contains("    2  2023-01-11  Test Co"));
+        assert!(rendered.contains("save anyway"));
+        assert!(!rendered.contains("Foo Bar"));
+    }
+
+    #[test]
+    fn warn_about_closed_accounts() {
+        let mut app = App::new(Args {
+            file: vec!["data/closed.beancount".into()],
+            no_session: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let rendered = render(&app, 100, 30);
+        assert!(rendered.contains("closed on 2024-01-31"));
+        assert!(rendered.contains("1 inactive account"));
+
+        // on the closing day the account can still be used
+        set_textarea_content(
+            &mut app.transactions[0].metadata_textareas_mut()[0],
+            "2024-01-31",
+        );
+        assert!(!render(&app, 100, 30).contains("inactive"));
+        set_textarea_content(
+            &mut app.transactions[0].metadata_textareas_mut()[0],
+            "2023-12-24",
+        );
+        assert!(render(&app, 100, 30).contains("opened on 2024-01-01"));
+        // an invalid date isn't checked
+        set_textarea_content(
+            &mut app.transactions[0].metadata_textareas_mut()[0],
+            "2024-13-01",
+        );
+        assert!(!render(&app, 100, 30).contains("inactive"));
+    }
+
+    #[test]
+    fn postings_scroll_to_the_selected_one() {
+        assert_eq!(visible_postings(0, 0, 30), 0..0);
+        assert_eq!(visible_postings(2, 1, 30), 0..2);
+        assert_eq!(visible_postings(500, 0, 30), 0..10);
+        assert_eq!(visible_postings(500, 9, 30), 0..10);
+        assert_eq!(visible_postings(500, 250, 30), 241..251);
+        assert_eq!(visible_postings(500, 499, 30), 490..500);
+        // too small for a whole posting, the selected one is still drawn
+        assert_eq!(visible_postings(500, 250, 2), 250..251);
+
+        let mut app = test_app();
+        let postings = app.transactions[0].postings_textareas_mut();
+        for i in 0..20 {
+            postings.push(PostingTui::new(&format!("Expenses:Split{}", i), "1", "USD"));
+        }
+        app.focus_on_postings = true;
+        app.currently_selected_posting = 21;
+        let rendered = render(&app, 100, 30);
+        assert!(rendered.contains("Expenses:Split19"));
+        assert!(!rendered.contains("Expenses:Test"));
+    }
+
+    #[test]
+    fn no_color_renders_without_colors() {
+        let mut app = App::new(Args {
+            file: vec!["data/test.beancount".into()],
+            no_color: true,
+            no_session: true,
+            ..Default::default()
+        })
+        .unwrap();
+        // an unbalanced transaction shows an error chip
+        app.current_index = 3;
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        terminal.draw(|frame| draw(frame, &app).unwrap()).unwrap();
+        for cell in terminal.backend().buffer().content() {
+            assert_eq!(cell.fg, Color::Reset);
+            assert_eq!(cell.bg, Color::Reset);
+        }
+        // the loading screen shown before the app exists
+        terminal
+            .draw(|frame| {
+                draw_loading(frame, &app.theme, "ledger", 1000, Duration::ZERO);
+            })
+            .unwrap();
+        for cell in terminal.backend().buffer().content() {
+            assert_eq!(cell.fg, Color::Reset);
+            assert_eq!(cell.bg, Color::Reset);
+        }
+    }
+
+    #[test]
+    fn warn_about_unopened_accounts() {
+        let mut app = test_app();
+        set_textarea_content(
+            &mut app.transactions[0].postings_textareas_mut()[1].account_textarea,
+            "Expenses:Unknown",
+        );
+        let rendered = render(&app, 100, 30);
+        assert!(rendered.contains("not opened"));
+        assert!(rendered.contains("1 unopened account"));
+
+        app.open_check = false;
+        let rendered = render(&app, 100, 30);
+        assert!(!rendered.contains("not opened"));
+    }
+
+    #[test]
+    fn instructions_follow_context() {
+        let mut app = test_app();
+        let rendered = render(&app, 120, 30);
+        assert!(rendered.contains("next <C-n>"));
+        assert!(rendered.contains("quit <Esc>"));
+        assert!(!rendered.contains("add posting"));
+
+        app.focus_on_postings = true;
+        let rendered = render(&app, 120, 30);
+        assert!(rendered.contains("add posting <C-o>"));
+        assert!(rendered.contains("delete posting <A-d>"));
+
+        // the least relevant hints are dropped first
+        let narrow = render(&app, 60, 30);
+        assert!(narrow.contains("add posting <C-o>"));
+        assert!(!narrow.contains("copy <C-y>"));
+
+        app.popup.active = true;
+        let rendered = render(&app, 120, 30);
+        assert!(rendered.contains("confirm <Enter>"));
+    }
+
+    #[test]
+    fn popup_fits_wide_prompts() {
+        let mut app = test_app();
+        app.popup.active = true;
+        // each character takes two cells, counting chars would leave out the end
+        app.popup.prompt = format!("{}END", "取引を削除しますか ".repeat(12));
+        let rendered = render(&app, 120, 30);
+        assert!(rendered.contains("END"));
+        assert!(rendered.contains("confirm <Enter>"));
+    }
+
+    #[test]
+    fn loading_screen_shows_directive_count() {
+        assert_eq!(thousands(0), "0");
+        assert_eq!(thousands(999), "999");
+        assert_eq!(thousands(1_234_567), "1,234,567");
+        let mut terminal = Terminal::new(TestBackend::new(80, 10)).unwrap();
+        terminal
+            .draw(|frame| {
+                draw_loading(
+                    frame,
+                    &Theme::COLOR,
+                    "main.beancount",
+                    12345,
+                    Duration::from_millis(250),
+                )
+            })
+            .unwrap();
+        let rendered: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(rendered.contains("⠹ Parsing main.beancount… 12,345 directives"));
+        assert!(rendered.contains("Ctrl-c to cancel"));
+    }
+
+    #[test]
+    fn amounts_are_right_aligned() {
+        let app = test_app();
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        terminal.draw(|frame| draw(frame, &app).unwrap()).unwrap();
+        let buffer = terminal.backend().buffer();
+        let [_, _, amount_area, currency_area] = posting_columns(Rect::new(1, 4, 98, 3));
+        // the amount of the first posting ends right before the border of its field
+        let row = amount_area.y + 1;
+        assert_eq!(buffer[(amount_area.right() - 2, row)].symbol(), "5");
+        assert_eq!(buffer[(currency_area.x + 1, row)].symbol(), "U");
+
+        // while editing, the cursor follows the aligned text
+        let mut app = test_app();
+        app.focus_on_postings = true;
+        app.currently_selected_posting_field = PostingField::Amount;
+        app.transactions[0].postings_textareas_mut()[0]
+            .amount_textarea
+            .move_cursor(CursorMove::End);
+        terminal.draw(|frame| draw(frame, &app).unwrap()).unwrap();
+        let buffer = terminal.backend().buffer();
+        assert_eq!(buffer[(amount_area.right() - 3, row)].symbol(), "5");
+        let cursor = &buffer[(amount_area.right() - 2, row)];
+        assert!(cursor.modifier.contains(Modifier::REVERSED));
+    }
 }
@@ -1,10 +1,35 @@
-use beancount_parser::Date;
+use std::{fs, io, io::Write, path::Path};
 
+use beancount_parser::Date;
+use chrono::{Datelike, Days, Local, Months, NaiveDate, Weekday};
 
+/// Formats a parsed date as `YYYY-MM-DD`
 pub fn format_date(date: &Date) -> String {
     format!("{}-{:02}-{:02}", date.year, date.month, date.day)
 }
 
+/// Parses a date in the `YYYY-MM-DD` format used by beancount
+pub fn parse_date(input: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(input.trim(), "%Y-%m-%d").ok()
+}
+
+/// Formats a date the same way as `format_date`
+pub fn format_naive_date(date: &NaiveDate) -> String {
+    date.format("%Y-%m-%d").to_string()
+}
+
+/// Moves a date by the given number of days or months.
+/// Month steps clamp to the last day of the target month (e.g. Jan 31 + 1 month = Feb 28).
+pub fn shift_date(date: NaiveDate, amount: i32, by_month: bool) -> Option<NaiveDate> {
+    let magnitude = amount.unsigned_abs();
+    match (by_month, amount >= 0) {
+        (false, true) => date.checked_add_days(Days::new(magnitude.into())),
+        (false, false) => date.checked_sub_days(Days::new(magnitude.into())),
+        (true, true) => date.checked_add_months(Months::new(magnitude)),
+        (true, false) => date.checked_sub_months(Months::new(magnitude)),
+    }
+}
+
 // pub fn format_posting_line<'p>(posting: PostingTui, line_width: usize) -> Line<'p> {
 //     let account = Span::from(["    ".to_string(), posting.account].join("")).blue();
 //     let amount = Span::from(
@@ -22,3 +47,50 @@ pub fn format_date(date: &Date) -> String {
 //     let posting_line = Line::from(vec![account, spaces, amount, currency]);
 //     posting_line
 // }
+
+/// Returns the current local date
+pub fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+/// Resolves a date entered relative to `today` into an absolute date.
+///
+/// Accepts absolute `YYYY-MM-DD` dates, `today`/`yesterday`/`tomorrow`,
+/// day offsets like `-3` or `+1` and weekday names (`mon`, `tuesday`, ...),
+/// which resolve to the most recent such day before `today`.
+pub fn parse_relative_date(input: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let input = input.trim().to_lowercase();
+    if let Some(date) = parse_date(&input) {
+        return Some(date);
+    }
+    match input.as_str() {
+        "today" => return Some(today),
+        "yesterday" => return shift_date(today, -1, false),
+        "tomorrow" => return shift_date(today, 1, false),
+        _ => {}
+    }
+    if input.starts_with(['-', '+']) {
+        let offset: i32 = input.parse().ok()?;
+        return shift_date(today, offset, false);
+    }
+    let weekday: Weekday = input.parse().ok()?;
+    let days_back =
+        (today.weekday().num_days_from_monday() + 7 - weekday.num_days_from_monday() - 1) % 7 + 1;
+    shift_date(today, -(days_back as i32), false)
+}
+
+/// Writes `content` to a temporary file next to `path` and renames it over `path`,
+/// so an interrupted write never leaves a half written file behind
+pub fn write_atomically(path: &Path, content: &str) -> io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    let mut tmp_file = fs::File::create(&tmp_path)?;
+    tmp_file.write_all(content.as_bytes())?;
+    tmp_file.sync_all()?;
+    fs::rename(&tmp_path, path)
+}
@@ -0,0 +1,126 @@
+use std::{fs, process::Command};
+
+use beancount_parser::BeancountFile;
+use rust_decimal::Decimal;
+
+use crate::writeback::{line_starts, transaction_span};
+
+/// Why the content about to be written was rejected, shown in a scrollable popup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckFailure {
+    pub summary: String,      // e.g. `the output doesn't parse at line 12`
+    pub details: Vec<String>, // the offending transaction or the output of the command
+    pub scroll: usize,        // first shown line of `details`
+}
+
+impl CheckFailure {
+    fn new(summary: String, details: Vec<String>) -> Self {
+        Self {
+            summary,
+            details,
+            scroll: 0,
+        }
+    }
+
+    /// Scrolls by `lines`, it stays put at the ends
+    pub fn scroll_by(&mut self, lines: isize) {
+        let last = self.details.len().saturating_sub(1);
+        self.scroll = self.scroll.saturating_add_signed(lines).min(last);
+    }
+}
+
+/// Parses `content` again, a formatting bug must not end up in the ledger
+pub fn check_parses(content: &str) -> Result<(), CheckFailure> {
+    let Err(err) = content.parse::<BeancountFile<Decimal>>() else {
+        return Ok(());
+    };
+    let line_number = err.line_number();
+    let lines: Vec<&str> = content.lines().collect();
+    // the header of the transaction containing the line, continuation lines are indented
+    let header = (0..(line_number as usize).min(lines.len()))
+        .rev()
+        .find(|&index| !lines[index].starts_with([' ', '\t']) && !lines[index].trim().is_empty())
+        .map_or(line_number, |index| index as u32 + 1);
+    let span = transaction_span(content, &line_starts(content), header);
+    Err(CheckFailure::new(
+        format!(
+            "the output doesn't parse as beancount at line {}",
+            line_number
+        ),
+        content[span].lines().map(str::to_string).collect(),
+    ))
+}
+
+/// Runs `command` on a temporary file holding `content`. `{}` in the command is replaced
+/// by the path of the file, without it the path is appended.
+pub fn run_check_command(command: &str, content: &str) -> Result<(), CheckFailure> {
+    let path = std::env::temp_dir().join(format!(
+        "beancount-tui-check-{}.beancount",
+        std::process::id()
+    ));
+    let quoted = format!("'{}'", path.display().to_string().replace('\'', r"'\''"));
+    let command_line = if command.contains("{}") {
+        command.replace("{}", &quoted)
+    } else {
+        format!("{} {}", command, quoted)
+    };
+    let output = fs::write(&path, content)
+        .and_then(|()| Command::new("sh").arg("-c").arg(&command_line).output());
+    let _ = fs::remove_file(&path);
+    let output = output.map_err(|err| {
+        CheckFailure::new(format!("couldn't run `{}`: {}", command, err), Vec::new())
+    })?;
+    if output.status.success() {
+        return Ok(());
+    }
+    // bean-check reports on stdout, most other tools on stderr
+    let mut details: Vec<String> = String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .chain(String::from_utf8_lossy(&output.stdout).lines())
+        .map(|line| line.replace(&path.display().to_string(), "<output>"))
+        .collect();
+    details.retain(|line| !line.trim().is_empty());
+    Err(CheckFailure::new(
+        format!("`{}` failed with {}", command, output.status),
+        details,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_failure_shows_the_transaction() {
+        let content = "2024-01-01 * \"Shop\" \"Food\"\n    Assets:Cash  -5 USD\n    Expenses:Food\n\n2024-01-02 * 'Shop' \"Food\"\n    Assets:Cash  -5 USD\n    Expenses:Food\n";
+        let first = content.split("\n\n").next().unwrap();
+        assert_eq!(check_parses(first), Ok(()));
+        let failure = check_parses(content).unwrap_err();
+        assert!(
+            failure.summary.ends_with("at line 5"),
+            "{}",
+            failure.summary
+        );
+        assert_eq!(failure.details[0], "2024-01-02 * 'Shop' \"Food\"");
+        assert_eq!(failure.details.len(), 3);
+    }
+
+    #[test]
+    fn check_command_blocks_on_failure() {
+        assert_eq!(run_check_command("grep -q Food", "Food"), Ok(()));
+        let failure = run_check_command("echo broken {} >&2; false", "Food").unwrap_err();
+        assert!(failure
+            .summary
+            .starts_with("`echo broken {} >&2; false` failed"));
+        assert_eq!(failure.details, ["broken <output>"]);
+    }
+
+    #[test]
+    fn scroll_stays_in_the_details() {
+        let mut failure = CheckFailure::new(String::new(), vec![String::new(); 3]);
+        failure.scroll_by(-1);
+        assert_eq!(failure.scroll, 0);
+        failure.scroll_by(10);
+        assert_eq!(failure.scroll, 2);
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+};
+
+use color_eyre::{eyre::Context, Result};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::session::content_hash;
+
+/// Watches the files of a ledger for changes made by other programs, enabled with `--watch`
+pub struct FileWatcher {
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    hashes: HashMap<PathBuf, u64>, // content hash of every watched file when it was last read
+}
+
+impl std::fmt::Debug for FileWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FileWatcher")
+            .field("files", &self.hashes.keys())
+            .finish()
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn file_hash(path: &Path) -> Option<u64> {
+    fs::read(path).ok().map(|content| content_hash(&content))
+}
+
+impl FileWatcher {
+    pub fn new() -> Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let watcher = notify::recommended_watcher(sender).wrap_err("couldn't watch files")?;
+        Ok(Self {
+            watcher,
+            events,
+            hashes: HashMap::new(),
+        })
+    }
+
+    /// Replaces the watched files. The directories are watched instead of the files,
+    /// so files that editors replace by renaming are still followed.
+    pub fn watch(&mut self, files: impl IntoIterator<Item = PathBuf>) -> Result<()> {
+        for file in self.hashes.keys() {
+            if let Some(dir) = file.parent() {
+                let _ = self.watcher.unwatch(dir);
+            }
+        }
+        self.hashes.clear();
+        for file in files {
+            let file = canonical(&file);
+            if let Some(dir) = file.parent() {
+                self.watcher
+                    .watch(dir, RecursiveMode::NonRecursive)
+                    .wrap_err_with(|| format!("couldn't watch {}", dir.display()))?;
+            }
+            let hash = file_hash(&file).unwrap_or_default();
+            self.hashes.insert(file, hash);
+        }
+        Ok(())
+    }
+
+    /// Records `content` as the current content of `path` after writing it ourselves
+    pub fn remember(&mut self, path: &Path, content: &str) {
+        if let Some(hash) = self.hashes.get_mut(&canonical(path)) {
+            *hash = content_hash(content.as_bytes());
+        }
+    }
+
+    /// The watched files whose content changed since they were last read or written.
+    /// Events for other files in the same directories and writes that didn't change
+    /// the content are ignored.
+    pub fn changed_files(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for event in self.events.try_iter().flatten() {
+            if event.kind.is_access() {
+                continue;
+            }
+            for path in event.paths {
+                let path = canonical(&path);
+                let Some(hash) = self.hashes.get_mut(&path) else {
+                    continue;
+                };
+                let new_hash = file_hash(&path).unwrap_or_default();
+                if new_hash != *hash {
+                    *hash = new_hash;
+                    changed.push(path);
+                }
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+    use crate::utils::write_atomically;
+
+    fn wait_for_changes(watcher: &mut FileWatcher) -> Vec<PathBuf> {
+        for _ in 0..50 {
+            let changed = watcher.changed_files();
+            if !changed.is_empty() {
+                return changed;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        Vec::new()
+    }
+
+    #[test]
+    fn reports_changes_of_other_programs_only() {
+        let dir = std::env::temp_dir().join(format!("beancount-tui-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let main = dir.join("main.beancount");
+        fs::write(&main, "; empty\n").unwrap();
+        fs::write(dir.join("other.txt"), "").unwrap();
+        let mut watcher = FileWatcher::new().unwrap();
+        watcher.watch([main.clone()]).unwrap();
+
+        // replaced by renaming, like many editors do
+        write_atomically(&main, "; changed\n").unwrap();
+        assert_eq!(wait_for_changes(&mut watcher), [canonical(&main)]);
+
+        // our own saves and unrelated files don't count
+        watcher.remember(&main, "; saved\n");
+        write_atomically(&main, "; saved\n").unwrap();
+        fs::write(dir.join("other.txt"), "changed").unwrap();
+        assert!(wait_for_changes(&mut watcher).is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,308 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{
+    eyre::{bail, Context},
+    Result,
+};
+use similar::TextDiff;
+
+use crate::{
+    beancount::{format_transaction_with, FormatOptions, TransactionTui},
+    utils::write_atomically,
+};
+
+/// The new content of a source file after splicing in the edited transactions
+#[derive(Debug)]
+pub struct FileChange {
+    pub path: PathBuf,
+    pub old: String,
+    pub new: String,
+    /// Span of every spliced transaction in `new`, by transaction index
+    pub new_spans: Vec<(usize, Range<usize>)>,
+}
+
+impl FileChange {
+    /// A unified diff between the old and the new content
+    pub fn unified_diff(&self) -> String {
+        let path = self.path.display().to_string();
+        TextDiff::from_lines(&self.old, &self.new)
+            .unified_diff()
+            .header(&path, &path)
+            .to_string()
+    }
+
+    pub fn write(&self) -> Result<()> {
+        write_atomically(&self.path, &self.new)
+            .wrap_err_with(|| format!("couldn't write {}", self.path.display()))
+    }
+}
+
+/// The byte offset at which each line of `content` starts
+pub fn line_starts(content: &str) -> Vec<usize> {
+    let mut starts = vec![0];
+    starts.extend(content.match_indices('\n').map(|(index, _)| index + 1));
+    starts
+}
+
+/// The byte range of the transaction starting at the 1-based `line_number`:
+/// its header line and all following indented lines, without the final line break
+pub fn transaction_span(content: &str, line_starts: &[usize], line_number: u32) -> Range<usize> {
+    let Some(&start) = line_starts.get((line_number as usize).wrapping_sub(1)) else {
+        return content.len()..content.len();
+    };
+    let mut offset = start;
+    let mut end = start;
+    for (index, line) in content[start..].split_inclusive('\n').enumerate() {
+        if index > 0 && (!line.starts_with([' ', '\t']) || line.trim().is_empty()) {
+            break;
+        }
+        end = offset + line.trim_end_matches(['\n', '\r']).len();
+        offset += line.len();
+    }
+    start..end
+}
+
+/// Splices every dirty transaction into its span of its source file. Files whose
+/// content wouldn't change are left out, so they are never touched. Fails if a span no
+/// longer holds the text the transaction was read from.
+pub fn plan_writeback(
+    transactions: &[TransactionTui],
+    options: &FormatOptions,
+) -> Result<Vec<FileChange>> {
+    let mut by_source: BTreeMap<&Path, Vec<usize>> = BTreeMap::new();
+    for (index, transaction) in transactions.iter().enumerate() {
+        if transaction.dirty {
+            by_source
+                .entry(transaction.source.as_path())
+                .or_default()
+                .push(index);
+        }
+    }
+    let mut changes = Vec::new();
+    for (path, mut indexes) in by_source {
+        let old = fs::read_to_string(path)
+            .wrap_err_with(|| format!("couldn't read {}", path.display()))?;
+        indexes.sort_by_key(|&index| transactions[index].span.start);
+        let mut new = String::with_capacity(old.len());
+        let mut new_spans = Vec::new();
+        let mut copied_until = 0;
+        for index in indexes {
+            let span = &transactions[index].span;
+            if span.start < copied_until
+                || old.get(span.clone()) != Some(transactions[index].span_text.as_str())
+            {
+                bail!(
+                    "{} changed since it was read, reload it before saving",
+                    path.display()
+                );
+            }
+            new.push_str(&old[copied_until..span.start]);
+            let start = new.len();
+            new.push_str(&format_transaction_with(&transactions[index], options));
+            new_spans.push((index, start..new.len()));
+            copied_until = span.end;
+        }
+        new.push_str(&old[copied_until..]);
+        if new != old {
+            changes.push(FileChange {
+                path: path.to_path_buf(),
+                old,
+                new,
+                new_spans,
+            });
+        }
+    }
+    Ok(changes)
+}
+
+/// Moves the spans of all transactions in the changed file to their new position
+pub fn update_spans(transactions: &mut [TransactionTui], change: &FileChange) {
+    let mut shifts: Vec<(usize, isize)> = Vec::new(); // old span end, accumulated length change
+    let mut delta = 0;
+    for (index, new_span) in &change.new_spans {
+        let old_span = &transactions[*index].span;
+        delta += new_span.len() as isize - old_span.len() as isize;
+        shifts.push((old_span.end, delta));
+    }
+    let new_spans: BTreeMap<usize, Range<usize>> = change.new_spans.iter().cloned().collect();
+    for (index, transaction) in transactions.iter_mut().enumerate() {
+        if transaction.source != change.path {
+            continue;
+        }
+        if let Some(new_span) = new_spans.get(&index) {
+            transaction.span = new_span.clone();
+            transaction.span_text = change.new[new_span.clone()].to_string();
+            continue;
+        }
+        let shift = shifts
+            .iter()
+            .take_while(|(old_end, _)| *old_end <= transaction.span.start)
+            .last()
+            .map_or(0, |(_, delta)| *delta);
+        let span = &transaction.span;
+        transaction.span =
+            (span.start as isize + shift) as usize..(span.end as isize + shift) as usize;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use beancount_parser::{BeancountFile, DirectiveContent};
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use crate::beancount::{filter_transactions, parse_ledger, set_textarea_content};
+
+    /// Writes `files` to a fresh directory and loads the first one like the app does
+    fn load<'t>(name: &str, files: &[(&str, &str)]) -> (PathBuf, Vec<TransactionTui<'t>>) {
+        let dir = env::temp_dir().join(format!("beancount-tui-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, content) in files {
+            fs::write(dir.join(file), content).unwrap();
+        }
+        let mut transactions = Vec::new();
+        for ledger_file in parse_ledger(&dir.join(files[0].0)).unwrap() {
+            let starts = line_starts(&ledger_file.content);
+            for directive in filter_transactions(ledger_file.beancount) {
+                let mut transaction: TransactionTui = (&directive).try_into().unwrap();
+                transaction.set_source(&ledger_file.path, &ledger_file.content, &starts);
+                transactions.push(transaction);
+            }
+        }
+        (dir, transactions)
+    }
+
+    const LEDGER: &str = "2023-10-01 open Assets:Test USD\n\n2023-10-01 * \"Shop\" \"Food\"\n    Assets:Test  -5 USD\n    Expenses:Food\n\n; a comment\n2023-10-02 * \"Shop\" \"More\"\n    Assets:Test  -1 USD\n    Expenses:Food\n";
+
+    #[test]
+    fn span_covers_header_and_postings() {
+        let starts = line_starts(LEDGER);
+        let span = transaction_span(LEDGER, &starts, 3);
+        assert_eq!(
+            &LEDGER[span],
+            "2023-10-01 * \"Shop\" \"Food\"\n    Assets:Test  -5 USD\n    Expenses:Food"
+        );
+        let span = transaction_span(LEDGER, &starts, 8);
+        assert!(LEDGER[span].ends_with("Expenses:Food"));
+        assert_eq!(
+            transaction_span(LEDGER, &starts, 99),
+            LEDGER.len()..LEDGER.len()
+        );
+    }
+
+    #[test]
+    fn splice_leaves_other_bytes_identical() {
+        let (dir, mut transactions) = load("splice", &[("main.beancount", LEDGER)]);
+        set_textarea_content(
+            &mut transactions[0].metadata_textareas_mut()[3],
+            "Groceries",
+        );
+        transactions[0].dirty = true;
+        let changes = plan_writeback(&transactions, &FormatOptions::default()).unwrap();
+        assert_eq!(changes.len(), 1);
+        let expected = LEDGER.replace("\"Food\"\n", "\"Groceries\"\n");
+        let new = &changes[0].new;
+        assert_eq!(new.replace("  ", " "), expected.replace("  ", " "));
+        assert!(new.starts_with("2023-10-01 open Assets:Test USD\n\n"));
+        assert!(new.ends_with("\n\n; a comment\n2023-10-02 * \"Shop\" \"More\"\n    Assets:Test  -1 USD\n    Expenses:Food\n"));
+        assert!(changes[0]
+            .unified_diff()
+            .contains("+2023-10-01 * \"Shop\" \"Groceries\""));
+
+        update_spans(&mut transactions, &changes[0]);
+        assert!(new[transactions[0].span.clone()].contains("Groceries"));
+        // the span includes the comment lines above the header
+        assert!(new[transactions[1].span.clone()].starts_with("; a comment\n2023-10-02"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn metadata_and_comments_survive_the_splice() {
+        let ledger = "2023-10-01 * \"Shop\" \"Food\"\n    document: \"receipt.pdf\"\n    ; checked\n    Assets:Test  -5 USD ; card\n        time: \"12:00\"\n        ; second line\n    Expenses:Food\n";
+        let (dir, mut transactions) = load("metadata", &[("main.beancount", ledger)]);
+        let amount = &mut transactions[0].postings_textareas_mut()[0].amount_textarea;
+        set_textarea_content(amount, "5");
+        transactions[0].dirty = true;
+        let changes = plan_writeback(&transactions, &FormatOptions::default()).unwrap();
+        assert_eq!(changes[0].new, ledger.replace("-5 USD", "5 USD"));
+        let reparsed: BeancountFile<Decimal> = changes[0].new.parse().unwrap();
+        let directive = &reparsed.directives[0];
+        assert!(directive.metadata.contains_key("document"));
+        let DirectiveContent::Transaction(transaction) = &directive.content else {
+            panic!("not a transaction");
+        };
+        assert!(transaction.postings[0].metadata.contains_key("time"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_to_splice_into_a_changed_file() {
+        let (dir, mut transactions) = load("changed", &[("main.beancount", LEDGER)]);
+        transactions[0].dirty = true;
+        fs::write(
+            dir.join("main.beancount"),
+            format!("; new line\n{}", LEDGER),
+        )
+        .unwrap();
+        let err = plan_writeback(&transactions, &FormatOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("changed since it was read"));
+        // a span that starts inside a multibyte character fails the same way
+        fs::write(dir.join("main.beancount"), format!("é{}", LEDGER)).unwrap();
+        transactions[0].span = 1..transactions[0].span.end;
+        assert!(plan_writeback(&transactions, &FormatOptions::default()).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn comments_above_the_header_are_rewritten_once() {
+        let (dir, mut transactions) = load("comments", &[("main.beancount", LEDGER)]);
+        assert_eq!(transactions[1].comments, ["; a comment"]);
+        transactions[1]
+            .comments
+            .push("; source: march.csv".to_string());
+        transactions[1].dirty = true;
+        let changes = plan_writeback(&transactions, &FormatOptions::default()).unwrap();
+        assert!(changes[0]
+            .new
+            .contains("\n\n; a comment\n; source: march.csv\n2023-10-02 * \"Shop\" \"More\"\n"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_files_with_edits_change() {
+        let main = "include \"other.beancount\"\n\n2023-10-01 * \"Shop\" \"Food\"\n    Assets:Test  -5 USD\n    Expenses:Food\n";
+        let (dir, mut transactions) = load(
+            "includes",
+            &[("main.beancount", main), ("other.beancount", LEDGER)],
+        );
+        assert_eq!(transactions.len(), 3);
+        let other = transactions
+            .iter()
+            .position(|t| t.source.ends_with("other.beancount"))
+            .unwrap();
+        set_textarea_content(
+            &mut transactions[other].metadata_textareas_mut()[2],
+            "Market",
+        );
+        transactions[other].dirty = true;
+        let changes = plan_writeback(&transactions, &FormatOptions::default()).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].path.ends_with("other.beancount"));
+
+        // an edit that formats to the same text doesn't touch the file
+        for transaction in &mut transactions {
+            transaction.dirty = transaction.source.ends_with("main.beancount");
+        }
+        assert!(plan_writeback(&transactions, &FormatOptions::default())
+            .unwrap()
+            .is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,906 @@
+use std::{collections::BTreeSet, fs};
+
+use beancount_parser::BeancountFile;
+use beancount_tui::beancount::*;
+use rust_decimal::Decimal;
+use unicode_width::UnicodeWidthStr;
+
+fn parse_transactions<'t>(input: &str) -> Vec<TransactionTui<'t>> {
+    let beancount: BeancountFile<Decimal> = input.parse().unwrap();
+    filter_transactions(beancount)
+        .iter()
+        .map(|d| d.try_into().unwrap())
+        .collect()
+}
+
+const TRANSACTION: &str = r#"
+2023-10-01 * "Test Co" "Foo Bar"
+    Assets:Test   5 USD
+    Expenses:Test
+"#;
+
+#[test]
+fn parse_ledger_in_include_order() {
+    let dir = std::env::temp_dir().join(format!("beancount-tui-order-{}", std::process::id()));
+    fs::create_dir_all(dir.join("2024")).unwrap();
+    let files = [
+        (
+            "main.beancount",
+            "include \"2024/01.beancount\"\ninclude \"b.beancount\"\n",
+        ),
+        // includes are relative to the including file, the cycle is only read once
+        ("2024/01.beancount", "include \"02.beancount\"\n"),
+        ("2024/02.beancount", "include \"../main.beancount\"\n"),
+        ("b.beancount", "include \"2024/02.beancount\"\n"),
+    ];
+    for (name, content) in files {
+        fs::write(dir.join(name), content).unwrap();
+    }
+    let names: Vec<String> = parse_ledger(&dir.join("main.beancount"))
+        .unwrap()
+        .iter()
+        .map(|file| file.path.strip_prefix(&dir).unwrap().display().to_string())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "main.beancount",
+            "2024/01.beancount",
+            "2024/02.beancount",
+            "b.beancount"
+        ]
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn unreadable_paths_are_explained() {
+    let dir = std::env::temp_dir().join(format!("beancount-tui-paths-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let canonical_dir = fs::canonicalize(&dir).unwrap();
+
+    let missing = dir.join("mian.beancount");
+    let expected = format!(
+        "{} doesn't exist",
+        canonical_dir.join("mian.beancount").display()
+    );
+    assert_eq!(check_readable(&missing).unwrap_err().to_string(), expected);
+    let err = parse_ledger(&missing).unwrap_err();
+    assert_eq!(err.to_string(), expected);
+
+    let expected = format!("{} is a directory, not a file", canonical_dir.display());
+    assert_eq!(check_readable(&dir).unwrap_err().to_string(), expected);
+    assert_eq!(parse_ledger(&dir).unwrap_err().to_string(), expected);
+
+    let file = dir.join("main.beancount");
+    fs::write(&file, "").unwrap();
+    assert!(check_readable(&file).is_ok());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn extension_warning_only_for_other_formats() {
+    assert!(extension_warning("export.CSV".as_ref())
+        .unwrap()
+        .contains("--csv"));
+    assert!(extension_warning("bank.xlsx".as_ref()).is_some());
+    assert!(extension_warning("main.beancount".as_ref()).is_none());
+    assert!(extension_warning("ledger".as_ref()).is_none());
+}
+
+#[test]
+fn balance_residual_per_currency() {
+    let mut transactions = parse_transactions(
+        r#"
+2024-01-01 * "Exchange"
+    Assets:Euro   -10 EUR
+    Expenses:Fees
+    Assets:Dollar   -3.20 USD
+"#,
+    );
+    // an elided posting without currency balances every currency
+    let residual = balance_residual(&transactions[0]);
+    assert_eq!(residual.get("EUR"), Some(&None));
+    assert_eq!(residual.get("USD"), Some(&None));
+
+    // with a currency it only balances that one
+    set_textarea_content(
+        &mut transactions[0].postings_textareas_mut()[1].currency_textarea,
+        "EUR",
+    );
+    let residual = balance_residual(&transactions[0]);
+    assert_eq!(residual.get("EUR"), Some(&None));
+    assert_eq!(residual.get("USD"), Some(&Some(Decimal::new(-320, 2))));
+}
+
+#[test]
+fn prices_and_costs_convert_the_residual() {
+    let transactions = parse_transactions(
+        r#"
+2024-01-01 * "Exchange"
+    Assets:Euro   -10.00 EUR @ 1.10 USD
+    Assets:Dollar   11.00 USD
+
+2024-01-02 * "Exchange"
+    Assets:Euro   -10 EUR @@ 11.00 USD
+    Assets:Dollar   10.00 USD
+
+2024-01-03 * "Shares"
+    Assets:Stocks   2 ACME {50.00 USD, 2023-12-01}
+    Assets:Dollar   -100.00 USD
+"#,
+    );
+    // the priced posting counts in the currency of its price, not its own
+    let residual = balance_residual(&transactions[0]);
+    assert_eq!(residual.len(), 1);
+    assert!(residual["USD"].unwrap().is_zero());
+
+    let residual = balance_residual(&transactions[1]);
+    assert_eq!(residual["USD"], Some(Decimal::new(-100, 2)));
+    assert!(!residual.contains_key("EUR"));
+
+    let residual = balance_residual(&transactions[2]);
+    assert!(residual["USD"].unwrap().is_zero());
+    assert!(validate_transaction(&transactions[2]).is_empty());
+}
+
+#[test]
+fn prices_and_costs_are_written_back() {
+    let input = r#"2024-01-01 * "Exchange" "Euros"
+    Assets:Euro    -10.00 EUR @ 1.10 USD
+    Assets:Dollar   11.00 USD
+
+2024-01-03 * "Shares" "ACME"
+    Assets:Stocks        2 ACME {50.00 USD, 2023-12-01} @@ 104 USD
+    Assets:Dollar  -100.00 USD
+"#;
+    let transactions = parse_transactions(input);
+    assert_eq!(
+        format_transactions(&transactions, &FormatOptions::default()),
+        input
+    );
+}
+
+#[test]
+fn balancing_amount_for_one_posting() {
+    let transactions = parse_transactions(
+        r#"
+2024-01-01 * "Groceries"
+    Assets:Checking   -20.50 EUR
+    Expenses:Food   15 EUR
+    Expenses:Household
+    Assets:Dollar   -3 USD
+"#,
+    );
+    let amount = |index| balancing_amount(&transactions[0], index);
+    assert_eq!(amount(1), Some((Decimal::new(2050, 2), "EUR".to_string())));
+    assert_eq!(amount(3), Some((Decimal::ZERO, "USD".to_string())));
+    // without a currency there are two to choose from
+    assert_eq!(amount(2), None);
+}
+
+#[test]
+fn account_tree_by_component() {
+    let accounts = BTreeSet::from(["Assets:A-B", "Assets:A:Cash", "Expenses"].map(String::from));
+    let tree = account_tree(&accounts);
+    let names = |nodes: &[AccountNode]| nodes.iter().map(|n| n.name.clone()).collect::<Vec<_>>();
+    assert_eq!(names(&tree), ["Assets", "Expenses"]);
+    assert_eq!(names(&tree[0].children), ["A", "A-B"]);
+    assert_eq!(tree[0].children[0].children[0].account, "Assets:A:Cash");
+    assert!(tree[1].children.is_empty());
+}
+
+#[test]
+fn normalize_account_snaps_to_known_accounts() {
+    let known = BTreeSet::from([
+        "Expenses:Food:Restaurants".to_string(),
+        "Assets:DE:ING".to_string(),
+    ]);
+    assert_eq!(
+        normalize_account("expenses:food:restaurants", &known),
+        "Expenses:Food:Restaurants"
+    );
+    assert_eq!(normalize_account("assets:de:ing", &known), "Assets:DE:ING");
+    // unknown components are title cased, known parents keep their spelling
+    assert_eq!(
+        normalize_account("assets:de:savings", &known),
+        "Assets:DE:Savings"
+    );
+    assert_eq!(normalize_account("income:salary", &known), "Income:Salary");
+    assert_eq!(normalize_account("Assets:DE:ING", &known), "Assets:DE:ING");
+
+    // accounts differing only in case are ambiguous
+    let known = BTreeSet::from(["Assets:Ing".to_string(), "Assets:ING".to_string()]);
+    assert_eq!(normalize_account("assets:ing", &known), "assets:ing");
+    assert_eq!(normalize_account("Assets:ING", &known), "Assets:ING");
+}
+
+#[test]
+fn currency_grammar() {
+    for valid in ["USD", "E", "VBMPX", "EUR.CASH", "A1", "HOOL-B"] {
+        assert!(is_valid_currency(valid), "{valid}");
+    }
+    for invalid in ["", "usd", "1USD", "USD-", "U D"] {
+        assert!(!is_valid_currency(invalid), "{invalid}");
+    }
+}
+
+#[test]
+fn validate_transaction_reports_problems() {
+    let mut transactions = parse_transactions(TRANSACTION);
+    assert!(validate_transaction(&transactions[0]).is_empty());
+
+    set_textarea_content(
+        &mut transactions[0].metadata_textareas_mut()[0],
+        "2023-13-01",
+    );
+    let posting = &mut transactions[0].postings_textareas_mut()[1];
+    set_textarea_content(&mut posting.account_textarea, "");
+    set_textarea_content(&mut posting.amount_textarea, "-4");
+    set_textarea_content(&mut posting.currency_textarea, "USD");
+    assert_eq!(
+        validate_transaction(&transactions[0]),
+        [
+            "invalid date '2023-13-01'",
+            "posting 2 has no account",
+            "unbalanced by 1 USD"
+        ]
+    );
+}
+
+#[test]
+fn format_transaction_without_comment() {
+    let transactions = parse_transactions(TRANSACTION);
+    assert_eq!(
+        format_transaction(&transactions[0]),
+        "2023-10-01 * \"Test Co\" \"Foo Bar\"\n    Assets:Test  5 USD\n    Expenses:Test"
+    );
+}
+
+#[test]
+fn transaction_metadata_is_kept_for_display() {
+    let transactions = parse_transactions(
+        "2024-01-03 * \"Bakery\" \"Bread\"\n  time: \"08:15\"\n  document: \"receipts/\\\"a\\\".pdf\"\n  rate: 2.5\n    Assets:Cash  -3 USD\n    Expenses:Food\n",
+    );
+    assert_eq!(
+        transactions[0].metadata,
+        [
+            (
+                "document".to_string(),
+                r#""receipts/\"a\".pdf""#.to_string()
+            ),
+            ("rate".to_string(), "2.5".to_string()),
+            ("time".to_string(), "\"08:15\"".to_string()),
+        ]
+    );
+    assert!(
+        parse_transactions("2024-01-03 * \"Bakery\"\n    Assets:Cash  -3 USD\n")[0]
+            .metadata
+            .is_empty()
+    );
+}
+
+#[test]
+fn output_keeps_metadata_and_comments() {
+    let path = std::path::Path::new("data/metadata.beancount");
+    let mut transactions = parse_beancount_file(path).unwrap();
+    let content = fs::read_to_string(path).unwrap();
+    assert_eq!(
+        format_transactions(&transactions, &FormatOptions::default()),
+        content
+    );
+    // edited transactions are formatted from their fields, the lines come along
+    set_textarea_content(&mut transactions[0].metadata_textareas_mut()[3], "Lunch");
+    assert_eq!(
+        format_transaction(&transactions[0]),
+        content
+            .split("\n\n")
+            .next()
+            .unwrap()
+            .replace("Food\"", "Lunch\"")
+    );
+
+    let mut plain = PlainTransaction::new("2024-02-03", "", "Coffee");
+    plain.lines = vec!["time: \"09:00\"".to_string()];
+    let mut posting = PlainPosting::new("Assets:Cash", "-2", "USD");
+    posting.comment = "cash".to_string();
+    posting.lines = vec!["; a receipt".to_string()];
+    plain.postings = vec![posting, PlainPosting::new("Expenses:Food", "", "")];
+    assert_eq!(
+        format_plain_transaction(&plain),
+        "2024-02-03 * \"Coffee\"\n    time: \"09:00\"\n    Assets:Cash  -2 USD ; cash\n        ; a receipt\n    Expenses:Food"
+    );
+}
+
+#[test]
+fn narration_only_transaction_round_trips() {
+    let text = "2024-01-02 * \"just a narration\"\n    Assets:Cash  -5 USD\n    Expenses:Food";
+    let mut transactions = parse_transactions(text);
+    assert_eq!(format_transaction(&transactions[0]), text);
+
+    set_textarea_content(&mut transactions[0].metadata_textareas_mut()[2], "Bakery");
+    assert!(format_transaction(&transactions[0])
+        .starts_with("2024-01-02 * \"Bakery\" \"just a narration\"\n"));
+}
+
+#[test]
+fn format_transaction_with_comment() {
+    let mut transactions = parse_transactions(TRANSACTION);
+    set_textarea_content(
+        &mut transactions[0].metadata_textareas_mut()[4],
+        "from bank.csv",
+    );
+    let formatted = format_transaction(&transactions[0]);
+    assert_eq!(
+        formatted.lines().next().unwrap(),
+        "2023-10-01 * \"Test Co\" \"Foo Bar\" ; from bank.csv"
+    );
+    let reparsed: BeancountFile<Decimal> = formatted.parse().unwrap();
+    assert_eq!(reparsed.directives.len(), 1);
+}
+
+#[test]
+fn format_aligns_amounts_by_display_width() {
+    let mut transactions = parse_transactions(
+        r#"
+2023-10-01 * "café ☕" "Kaffee"
+    Expenses:Cafe   3.50 EUR
+    Expenses:Food   12 EUR
+    Assets:Cash
+"#,
+    );
+    // the parser only accepts ASCII accounts, but they can be typed in
+    let postings = transactions[0].postings_textareas_mut();
+    set_textarea_content(&mut postings[0].account_textarea, "Expenses:Café");
+    set_textarea_content(&mut postings[1].account_textarea, "Expenses:食品");
+    let formatted = format_transaction(&transactions[0]);
+    assert_eq!(
+            formatted,
+            "2023-10-01 * \"café ☕\" \"Kaffee\"\n    Expenses:Café  3.50 EUR\n    Expenses:食品    12 EUR\n    Assets:Cash"
+        );
+    // the currencies end in the same terminal column
+    let ends: Vec<usize> = formatted
+        .lines()
+        .skip(1)
+        .take(2)
+        .map(|line| line.width())
+        .collect();
+    assert_eq!(ends[0], ends[1]);
+}
+
+#[test]
+fn format_posting_flag() {
+    let mut transactions = parse_transactions(
+        r#"
+2023-10-01 * "Test Co" "Foo Bar"
+    ! Assets:Test   5 USD
+    Expenses:Test
+"#,
+    );
+    assert_eq!(transactions[0].postings_textareas()[0].flag, Some('!'));
+    transactions[0].postings_textareas_mut()[1].flag = Some('*');
+    let formatted = format_transaction(&transactions[0]);
+    assert_eq!(
+        formatted,
+        "2023-10-01 * \"Test Co\" \"Foo Bar\"\n    ! Assets:Test  5 USD\n    * Expenses:Test"
+    );
+    let reparsed = parse_transactions(&formatted);
+    assert_eq!(reparsed[0].postings_textareas()[1].flag, Some('*'));
+}
+
+#[test]
+fn unopened_accounts_respect_sub_account_setting() {
+    let opened = BTreeSet::from(["Assets:Test".to_string(), "Expenses".to_string()]);
+    assert!(is_account_opened("Assets:Test", &opened, false));
+    assert!(!is_account_opened("Expenses:Food", &opened, false));
+    assert!(is_account_opened("Expenses:Food", &opened, true));
+    assert!(!is_account_opened("Assets:Testing", &opened, true));
+
+    let mut transactions = parse_transactions(TRANSACTION);
+    assert_eq!(unopened_postings(&transactions[0], &opened, false), [1]);
+    assert!(unopened_postings(&transactions[0], &opened, true).is_empty());
+    set_textarea_content(
+        &mut transactions[0].postings_textareas_mut()[1].account_textarea,
+        "",
+    );
+    assert!(unopened_postings(&transactions[0], &opened, false).is_empty());
+}
+
+#[test]
+fn clean_normalizes_whitespace_and_currencies() {
+    let mut transaction =
+        PlainTransaction::new("2024-03-01 ", "  AMAZON  EU S.A.R.L  ", "Order\t 123\n");
+    transaction.postings = vec![
+        PlainPosting::new(" Assets:Checking  Account ", " -3.50", "eur "),
+        PlainPosting::new("Expenses:Shopping", "", ""),
+    ];
+    assert!(transaction.clean());
+    assert_eq!(transaction.date, "2024-03-01");
+    assert_eq!(transaction.payee, "AMAZON EU S.A.R.L");
+    assert_eq!(transaction.narration, "Order 123");
+    // inner spaces of accounts are left for the validation to report
+    assert_eq!(transaction.postings[0].account, "Assets:Checking  Account");
+    assert_eq!(transaction.postings[0].amount, "-3.50");
+    assert_eq!(transaction.postings[0].currency, "EUR");
+    assert!(!transaction.clean());
+
+    let mut multiline = PlainTransaction::new("2024-03-01", "", "first  line \nsecond\n");
+    assert!(multiline.clean());
+    assert_eq!(multiline.narration, "first line\nsecond");
+}
+
+#[test]
+fn options_and_plugins_are_reported() {
+    let path = std::env::temp_dir().join(format!(
+        "beancount-tui-options-{}.beancount",
+        std::process::id()
+    ));
+    fs::write(
+        &path,
+        format!(
+            "option \"beancount_version\" \"2\"\nplugin \"beancount.plugins.auto\" \"config\"\n; plugin \"commented\"\n{}",
+            TRANSACTION
+        ),
+    )
+    .unwrap();
+    let ledger = parse_ledger(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(
+        uninterpreted_directives(&ledger[0]),
+        [
+            "option \"beancount_version\"",
+            "plugin \"beancount.plugins.auto\""
+        ]
+    );
+}
+
+#[test]
+fn untouched_flags_are_written_as_parsed() {
+    let content = fs::read_to_string("data/flags.beancount").unwrap();
+    let mut transactions = parse_transactions(&content);
+    assert_eq!(
+        format_transactions(&transactions, &FormatOptions::default()),
+        content
+    );
+    // `txn` is shown like `*`
+    assert_eq!(
+        textarea_content(&transactions[0].metadata_textareas()[1]),
+        "*"
+    );
+
+    set_textarea_content(&mut transactions[0].metadata_textareas_mut()[1], "!");
+    set_textarea_content(&mut transactions[1].metadata_textareas_mut()[1], "!");
+    assert!(format_transaction(&transactions[0]).starts_with("2024-03-01 ! \"Bakery\""));
+    assert!(format_transaction(&transactions[1]).starts_with("2024-03-02 ! \"Grocer\""));
+    set_textarea_content(&mut transactions[0].metadata_textareas_mut()[1], "*");
+    assert!(format_transaction(&transactions[0]).starts_with("2024-03-01 txn "));
+}
+
+#[test]
+fn custom_flags_survive_editing() {
+    let content = fs::read_to_string("data/flags.beancount").unwrap();
+    let mut transactions = parse_transactions(&content);
+    let pending = &mut transactions[3];
+    assert_eq!(textarea_content(&pending.metadata_textareas()[1]), "P");
+    assert!(validate_transaction(pending).is_empty());
+    // an edit formats the transaction from its fields
+    set_textarea_content(&mut pending.metadata_textareas_mut()[3], "Padding");
+    assert!(format_transaction(pending).starts_with("2024-03-04 P \"Bank\" \"Padding\""));
+    for flag in ["S", "#"] {
+        set_textarea_content(&mut pending.metadata_textareas_mut()[1], flag);
+        let formatted = format_transaction(pending);
+        assert!(formatted.starts_with(&format!("2024-03-04 {} ", flag)));
+        let reparsed = parse_transactions(&formatted);
+        assert_eq!(textarea_content(&reparsed[0].metadata_textareas()[1]), flag);
+    }
+}
+
+#[test]
+fn negate_amount_keeps_decimal_places() {
+    assert_eq!(negate_amount("5.00").unwrap(), "-5.00");
+    assert_eq!(negate_amount(" -12.5 ").unwrap(), "12.5");
+    assert_eq!(negate_amount("0.00").unwrap(), "0.00");
+    assert_eq!(negate_amount("12,34"), None);
+    assert_eq!(negate_amount(""), None);
+}
+
+#[test]
+fn split_amount_keeps_the_sum() {
+    let split = |amount| split_amount(amount).unwrap();
+    assert_eq!(split("10.00"), ("5.00".to_string(), "5.00".to_string()));
+    assert_eq!(split("10.01"), ("5.01".to_string(), "5.00".to_string()));
+    assert_eq!(split("-7"), ("-4".to_string(), "-3".to_string()));
+    assert_eq!(split("0.5"), ("0.3".to_string(), "0.2".to_string()));
+    assert_eq!(split_amount("five"), None);
+}
+
+#[test]
+fn format_options_lay_out_postings() {
+    let mut transaction = PlainTransaction::new("2024-03-01", "Bakery", "Bread");
+    transaction.postings = vec![
+        PlainPosting::new("Assets:Checking", "-3.50", "EUR"),
+        PlainPosting::new("Expenses:Food", "3.5", "EUR"),
+        PlainPosting::new("Expenses:Tips", "", ""),
+    ];
+    let format = |options: FormatOptions| {
+        format_plain_transaction_with(&transaction, &options)
+            .lines()
+            .skip(1)
+            .map(String::from)
+            .collect::<Vec<_>>()
+    };
+    // the defaults are the classic layout
+    assert_eq!(
+        format(FormatOptions::default()),
+        [
+            "    Assets:Checking  -3.50 EUR",
+            "    Expenses:Food      3.5 EUR",
+            "    Expenses:Tips"
+        ]
+    );
+    assert_eq!(
+        format(FormatOptions {
+            indent: 2,
+            amount_column: 30,
+            ..FormatOptions::default()
+        }),
+        [
+            "  Assets:Checking        -3.50 EUR",
+            "  Expenses:Food            3.5 EUR",
+            "  Expenses:Tips"
+        ]
+    );
+    // a column left of the longest account doesn't squeeze the gap
+    assert_eq!(
+        format(FormatOptions {
+            amount_column: 10,
+            min_gap: 3,
+            ..FormatOptions::default()
+        })[0],
+        "    Assets:Checking   -3.50 EUR"
+    );
+    assert_eq!(
+        format(FormatOptions {
+            align_currencies: false,
+            ..FormatOptions::default()
+        }),
+        [
+            "    Assets:Checking  -3.50 EUR",
+            "    Expenses:Food  3.5 EUR",
+            "    Expenses:Tips"
+        ]
+    );
+}
+
+#[test]
+fn format_plain_transaction_without_text_areas() {
+    let mut transaction = PlainTransaction::new("2024-03-01", "Bakery", "Bread");
+    transaction.postings = vec![
+        PlainPosting::new("Assets:Checking", "-3.50", "EUR"),
+        PlainPosting::new("Expenses:Food", "", ""),
+    ];
+    assert_eq!(
+        format_plain_transaction(&transaction),
+        "2024-03-01 * \"Bakery\" \"Bread\"\n    Assets:Checking  -3.50 EUR\n    Expenses:Food"
+    );
+    let parsed = parse_transactions(TRANSACTION);
+    assert_eq!(
+        format_plain_transaction(&PlainTransaction::from(&parsed[0])),
+        format_transaction(&parsed[0])
+    );
+}
+
+#[test]
+fn most_common_currency_of_the_postings() {
+    let transactions = parse_transactions(
+        r#"
+2023-10-01 * "Shop"
+    Assets:Cash   -5 EUR
+    Expenses:Food  5 EUR
+
+2023-10-02 * "Shop"
+    Assets:Cash   -5 USD
+    Expenses:Food  5 USD
+"#,
+    );
+    // a tie goes to the alphabetically first currency
+    assert_eq!(most_common_currency(&transactions).as_deref(), Some("EUR"));
+    assert_eq!(
+        most_common_currency(&transactions[1..]).as_deref(),
+        Some("USD")
+    );
+    assert_eq!(most_common_currency(&[]), None);
+}
+
+#[test]
+fn posting_totals_by_currency() {
+    let transactions = parse_transactions(
+        r#"
+2023-10-01 * "Shop"
+    Assets:Cash      -7.50 EUR
+    Expenses:Food     5 EUR
+    Expenses:Drinks   2.50 EUR
+    Assets:Card      -1 USD
+    Expenses:Fees
+"#,
+    );
+    let mut transaction = transactions[0].clone();
+    set_textarea_content(
+        &mut transaction.postings_textareas_mut()[1].amount_textarea,
+        "5x",
+    );
+    let totals = posting_totals(&transaction);
+    assert_eq!(totals["EUR"], (Decimal::new(250, 2), Decimal::new(-750, 2)));
+    assert_eq!(totals["USD"], (Decimal::ZERO, Decimal::new(-1, 0)));
+    assert_eq!(totals.len(), 2);
+}
+
+#[test]
+fn comments_around_the_header_round_trip() {
+    let dir = std::env::temp_dir().join(format!("beancount-tui-comments-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("main.beancount");
+    let transaction = "; source: statement-2024-03.csv\n; row 12\n2024-03-02 * \"Shop; Co\" \"Soap\" ; checked\n    Assets:Cash  -2 USD\n    Expenses:Home";
+    fs::write(
+        &path,
+        format!("2024-01-01 open Assets:Cash\n\n{}\n", transaction),
+    )
+    .unwrap();
+    let transactions = parse_beancount_file(&path).unwrap();
+    fs::remove_dir_all(dir).unwrap();
+    assert_eq!(
+        transactions[0].comments,
+        ["; source: statement-2024-03.csv", "; row 12"]
+    );
+    assert_eq!(
+        textarea_content(&transactions[0].metadata_textareas()[4]),
+        "checked"
+    );
+    assert_eq!(format_transaction(&transactions[0]), transaction);
+    assert!(!transactions[0].changed());
+}
+
+#[test]
+fn group_printed_transactions_by_month() {
+    let mut transactions = parse_transactions(
+        r#"
+2024-02-03 * "Grocer" ""
+    Assets:Cash   -3 USD
+    Expenses:Food
+
+2024-01-20 * "Bakery" ""
+    Assets:Cash   -1 USD
+    Expenses:Food
+
+2024-02-01 * "Rent" ""
+    Assets:Cash   -9 USD
+    Expenses:Rent
+
+2024-01-05 * "Cafe" ""
+    Assets:Cash   -2 USD
+    Expenses:Food
+"#,
+    );
+    set_textarea_content(
+        &mut transactions[3].metadata_textareas_mut()[0],
+        "2024-13-05",
+    );
+    let options = FormatOptions {
+        group_by_month: true,
+        ..FormatOptions::default()
+    };
+    let output = format_transactions(&transactions, &options);
+    let lines: Vec<&str> = output
+        .lines()
+        .filter(|line| line.starts_with(";;") || line.starts_with("20"))
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            ";; January 2024",
+            "2024-01-20 * \"Bakery\" \"\"",
+            ";; February 2024",
+            "2024-02-01 * \"Rent\" \"\"",
+            "2024-02-03 * \"Grocer\" \"\"",
+            UNPARSED_HEADER,
+            "2024-13-05 * \"Cafe\" \"\"",
+        ]
+    );
+    // without the option the order is kept
+    let output = format_transactions(&transactions, &FormatOptions::default());
+    assert!(!output.contains(";;"));
+    assert!(output.starts_with("2024-02-03"));
+}
+
+/// Pins the exact output of `format_transaction`, a change here has to be intentional
+#[test]
+fn format_transaction_snapshots() {
+    let cases = [
+        (
+            "payee and narration",
+            "2024-01-02 * \"Shop\" \"Food\"\n  Assets:Cash  -5.00 USD\n  Expenses:Food",
+            "2024-01-02 * \"Shop\" \"Food\"\n    Assets:Cash  -5.00 USD\n    Expenses:Food",
+        ),
+        (
+            "only a narration",
+            "2024-01-02 * \"Food\"\n  Assets:Cash  -5 USD\n  Expenses:Food",
+            "2024-01-02 * \"Food\"\n    Assets:Cash  -5 USD\n    Expenses:Food",
+        ),
+        (
+            // written like a narration-only transaction, which beancount reads the same
+            "empty payee",
+            "2024-01-02 * \"\" \"Food\"\n  Assets:Cash  -5 USD\n  Expenses:Food",
+            "2024-01-02 * \"Food\"\n    Assets:Cash  -5 USD\n    Expenses:Food",
+        ),
+        (
+            "elided amount",
+            "2024-01-02 * \"Shop\" \"Food\"\n  Expenses:Food  5 USD\n  Assets:Cash",
+            "2024-01-02 * \"Shop\" \"Food\"\n    Expenses:Food  5 USD\n    Assets:Cash",
+        ),
+        (
+            "multiple currencies",
+            "2024-01-02 * \"Shop\" \"Food\"\n  Assets:Cash  -5 USD\n  Assets:Cash  -3 EUR\n  Expenses:Food  5 USD\n  Expenses:Food  3 EUR",
+            "2024-01-02 * \"Shop\" \"Food\"\n    Assets:Cash    -5 USD\n    Assets:Cash    -3 EUR\n    Expenses:Food   5 USD\n    Expenses:Food   3 EUR",
+        ),
+        (
+            "price",
+            "2024-01-02 * \"Shop\" \"Food\"\n  Assets:Cash  -5 USD @ 1.1 EUR\n  Expenses:Food",
+            "2024-01-02 * \"Shop\" \"Food\"\n    Assets:Cash  -5 USD @ 1.1 EUR\n    Expenses:Food",
+        ),
+        (
+            "tags and links",
+            "2024-01-02 * \"Shop\" \"Food\" #trip ^inv-1\n  Assets:Cash  -5 USD\n  Expenses:Food",
+            "2024-01-02 * \"Shop\" \"Food\" #trip ^inv-1\n    Assets:Cash  -5 USD\n    Expenses:Food",
+        ),
+        (
+            "quotes and backslashes",
+            "2024-01-02 * \"Joe \\\"J\\\" Shop\" \"a\\\\b\"\n  Assets:Cash  -5 USD\n  Expenses:Food",
+            "2024-01-02 * \"Joe \\\"J\\\" Shop\" \"a\\\\b\"\n    Assets:Cash  -5 USD\n    Expenses:Food",
+        ),
+    ];
+    for (name, input, expected) in cases {
+        let transactions = parse_transactions(input);
+        assert_eq!(format_transaction(&transactions[0]), expected, "{name}");
+    }
+}
+
+#[test]
+fn quotes_in_the_header_are_escaped() {
+    let text = "2024-01-02 * \"Joe \\\"J\\\" Shop\" \"a\\\\b\"\n    Assets:Cash  -5 USD\n    Expenses:Food";
+    let mut transactions = parse_transactions(text);
+    // the parser reads the escapes, the fields show the plain text
+    let metadata = transactions[0].metadata_textareas();
+    assert_eq!(textarea_content(&metadata[2]), "Joe \"J\" Shop");
+    assert_eq!(textarea_content(&metadata[3]), "a\\b");
+    assert!(!transactions[0].changed());
+
+    set_textarea_content(
+        &mut transactions[0].metadata_textareas_mut()[3],
+        "say \"hi\"",
+    );
+    let formatted = format_transaction(&transactions[0]);
+    assert!(formatted.starts_with("2024-01-02 * \"Joe \\\"J\\\" Shop\" \"say \\\"hi\\\"\"\n"));
+    let reparsed = parse_transactions(&formatted);
+    assert_eq!(
+        textarea_content(&reparsed[0].metadata_textareas()[3]),
+        "say \"hi\""
+    );
+}
+
+#[test]
+fn payee_with_quotes_round_trips() {
+    let mut transactions = parse_transactions(TRANSACTION);
+    set_textarea_content(
+        &mut transactions[0].metadata_textareas_mut()[2],
+        "Bob's \"Best\" Diner",
+    );
+    let formatted = format_transaction(&transactions[0]);
+    assert!(formatted.starts_with("2023-10-01 * \"Bob's \\\"Best\\\" Diner\" \"Foo Bar\"\n"));
+    let reparsed = parse_transactions(&formatted);
+    assert_eq!(
+        textarea_content(&reparsed[0].metadata_textareas()[2]),
+        "Bob's \"Best\" Diner"
+    );
+    assert_eq!(format_transaction(&reparsed[0]), formatted);
+}
+
+#[test]
+fn issue_fields_in_order() {
+    let transactions = parse_transactions(TRANSACTION);
+    assert!(issue_fields(&transactions[0]).is_empty());
+
+    let mut transactions = parse_transactions(
+        r#"
+2023-10-01 ! "Test Co" "Foo Bar"
+    Assets:Test   5 USD
+    Expenses:Test  -3 USD
+    Expenses:Other
+"#,
+    );
+    assert_eq!(
+        issue_fields(&transactions[0]),
+        [TransactionField::Metadata(1)]
+    );
+    let postings = transactions[0].postings_textareas_mut();
+    set_textarea_content(&mut postings[2].account_textarea, "");
+    set_textarea_content(&mut postings[2].currency_textarea, "EUR");
+    set_textarea_content(&mut postings[0].currency_textarea, "");
+    set_textarea_content(
+        &mut transactions[0].metadata_textareas_mut()[0],
+        "2023-13-01",
+    );
+    assert_eq!(
+        issue_fields(&transactions[0]),
+        [
+            TransactionField::Metadata(0),
+            TransactionField::Metadata(1),
+            TransactionField::Posting(0, PostingField::Currency),
+            // USD is off by 3 now that the last posting takes EUR
+            TransactionField::Posting(1, PostingField::Amount),
+            TransactionField::Posting(2, PostingField::Account),
+        ]
+    );
+}
+
+#[test]
+fn unedited_transactions_reparse_equal() {
+    let content = fs::read_to_string("data/test.beancount").unwrap();
+    let transactions = parse_transactions(&content);
+    assert!(!transactions.is_empty());
+    for transaction in &transactions {
+        let formatted = format_transaction(transaction);
+        let reparsed = parse_transactions(&formatted);
+        assert_eq!(reparsed.len(), 1, "{formatted}");
+        // spacing and alignment are normalized, the content stays the same
+        assert_eq!(reparsed[0].date, transaction.date, "{formatted}");
+        assert_eq!(reparsed[0].directive, transaction.directive, "{formatted}");
+    }
+}
+
+#[test]
+fn context_directives_between_transactions() {
+    let files = parse_ledger(std::path::Path::new("data/context.beancount")).unwrap();
+    let context = context_directives(&files[0]);
+    let found: Vec<(u32, u32, &str)> = context
+        .iter()
+        .map(|d| (d.line_number, d.next_transaction, d.text.as_str()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (5, 9, "2024-01-01 pad Assets:Cash Equity:Opening"),
+            (7, 9, "2024-01-02 balance Assets:Cash 10 USD"),
+            (
+                13,
+                16,
+                "2024-01-04 note Assets:Cash \"Called the bank\"\n  source: \"phone\""
+            ),
+            (20, 0, "2024-01-06 balance Assets:Cash 5 USD"),
+        ]
+    );
+}
+
+#[test]
+fn quote_and_currency_position_options() {
+    let transactions = parse_transactions(
+        r#"
+2023-10-01 * "Bob's" "Foo Bar"
+    Assets:Test   -15 USD
+    Expenses:Test  15 USD
+"#,
+    );
+    let options = FormatOptions {
+        quote: '\'',
+        currency_first: true,
+        ..FormatOptions::default()
+    };
+    assert_eq!(
+        format_transaction_with(&transactions[0], &options),
+        "2023-10-01 * 'Bob\\'s' 'Foo Bar'\n    Assets:Test    USD -15\n    Expenses:Test   USD 15"
+    );
+    assert!(options
+        .parse_warning()
+        .unwrap()
+        .contains("format.quote = '''"));
+    assert_eq!(FormatOptions::default().parse_warning(), None);
+}
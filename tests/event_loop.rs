@@ -0,0 +1,115 @@
+use beancount_tui::{
+    cli::{Args, ColorChoice},
+    App,
+};
+use ratatui::{
+    backend::TestBackend,
+    buffer::Buffer,
+    crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers},
+    style::Color,
+    Terminal,
+};
+
+const WIDTH: u16 = 100;
+const HEIGHT: u16 = 30;
+
+fn test_app<'t>() -> App<'t> {
+    App::new(Args {
+        file: vec!["data/test.beancount".into()],
+        no_session: true,
+        color: ColorChoice::Always,
+        ..Default::default()
+    })
+    .unwrap()
+}
+
+fn key(code: KeyCode) -> Event {
+    Event::Key(KeyEvent::from(code))
+}
+
+fn ctrl(c: char) -> Event {
+    Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL))
+}
+
+fn alt(c: char) -> Event {
+    Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::ALT))
+}
+
+fn row(buffer: &Buffer, y: u16) -> String {
+    (0..buffer.area.width)
+        .map(|x| buffer[(x, y)].symbol())
+        .collect()
+}
+
+fn screen(buffer: &Buffer) -> String {
+    (0..buffer.area.height)
+        .map(|y| row(buffer, y))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Runs `events` through the main loop and returns the last frame
+fn run(app: &mut App, events: Vec<Event>) -> Buffer {
+    let mut terminal = Terminal::new(TestBackend::new(WIDTH, HEIGHT)).unwrap();
+    app.run_events(&mut terminal, events).unwrap();
+    terminal.backend().buffer().clone()
+}
+
+/// The color of the top left corner of the field titled `title`
+fn field_border(buffer: &Buffer, title: &str) -> Color {
+    for y in 0..buffer.area.height {
+        let line = row(buffer, y);
+        if let Some(start) = line.find(title) {
+            let x = line[..start].chars().count() as u16;
+            return buffer[(x - 1, y)].fg;
+        }
+    }
+    panic!("no field titled {}", title);
+}
+
+#[test]
+fn title_counts_the_visited_transactions() {
+    let mut app = test_app();
+    let buffer = run(&mut app, vec![]);
+    assert!(row(&buffer, 0).contains("Beancount importer (1/4)"));
+    let buffer = run(&mut app, vec![ctrl('n'), ctrl('n'), ctrl('p')]);
+    assert!(row(&buffer, 0).contains("Beancount importer (2/4)"));
+}
+
+#[test]
+fn focus_moves_the_highlight() {
+    let mut app = test_app();
+    let buffer = run(&mut app, vec![]);
+    assert_eq!(field_border(&buffer, "Payee"), Color::Yellow);
+    assert_eq!(field_border(&buffer, "Narration"), Color::Reset);
+    let buffer = run(&mut app, vec![key(KeyCode::Tab)]);
+    assert_eq!(field_border(&buffer, "Payee"), Color::Reset);
+    assert_eq!(field_border(&buffer, "Narration"), Color::Yellow);
+}
+
+#[test]
+fn typed_text_is_drawn() {
+    let mut app = test_app();
+    let events = "New ".chars().map(|c| key(KeyCode::Char(c))).collect();
+    let buffer = run(&mut app, events);
+    assert!(screen(&buffer).contains("New Test Co"));
+    assert!(row(&buffer, 0).contains("[+]"));
+}
+
+#[test]
+fn popup_appears_and_closes() {
+    let mut app = test_app();
+    let buffer = run(&mut app, vec![alt('D')]);
+    assert!(screen(&buffer).contains("Delete the transaction from 2023-10-01?"));
+    let buffer = run(&mut app, vec![key(KeyCode::Esc)]);
+    assert!(!screen(&buffer).contains("Delete the transaction from 2023-10-01?"));
+    assert!(row(&buffer, 0).contains("(1/4)"));
+}
+
+#[test]
+fn quitting_stops_the_loop() {
+    let mut app = test_app();
+    // the keys after quitting aren't handled anymore
+    let buffer = run(&mut app, vec![key(KeyCode::Esc), ctrl('n')]);
+    assert!(row(&buffer, 0).contains("(1/4)"));
+}
@@ -0,0 +1,48 @@
+use std::path::Path;
+
+use beancount_tui::{cli::Args, format_transaction, parse_beancount_file, App};
+
+#[test]
+fn parse_and_format_without_the_interface() {
+    let transactions = parse_beancount_file(Path::new("data/test.beancount")).unwrap();
+    assert_eq!(transactions.len(), 4);
+    assert_eq!(transactions[0].source, Path::new("data/test.beancount"));
+    assert_eq!(transactions[0].line_number, 4);
+    assert_eq!(
+        format_transaction(&transactions[0]),
+        "2023-10-01 txn \"Test Co\" \"Foo Bar\"\n    Assets:Test  5 USD\n    Expenses:Test"
+    );
+}
+
+#[test]
+fn script_the_app() {
+    let mut app = App::new(Args {
+        file: vec!["data/test.beancount".into()],
+        no_session: true,
+        ..Default::default()
+    })
+    .unwrap();
+    app.execute_command("sort").unwrap();
+    assert_eq!(
+        app.transactions[0].directive.narration.as_deref(),
+        Some("Foo Bar1")
+    );
+}
+
+#[test]
+fn check_reports_every_problem() {
+    let app = App::new(Args {
+        file: vec!["data/test.beancount".into()],
+        check: true,
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(
+        app.check_report(),
+        [
+            "data/test.beancount:7: warning: 2023-01-11 Test Co: posting 1 uses Assets:Test, which was opened on 2023-10-01",
+            "data/test.beancount:7: warning: 2023-01-11 Test Co: posting 2 uses Expenses:Test, which was opened on 2023-10-01",
+            "data/test.beancount:14: error: 2023-10-31 Test Co: unbalanced by 5 USD",
+        ]
+    );
+}
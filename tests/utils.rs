@@ -0,0 +1,91 @@
+use beancount_tui::utils::{parse_date, parse_relative_date, shift_date};
+use chrono::NaiveDate;
+
+fn date(input: &str) -> NaiveDate {
+    parse_date(input).unwrap()
+}
+
+#[test]
+fn shift_date_rolls_over_month_and_year() {
+    assert_eq!(
+        shift_date(date("2023-01-31"), 1, false),
+        Some(date("2023-02-01"))
+    );
+    assert_eq!(
+        shift_date(date("2023-12-31"), 1, false),
+        Some(date("2024-01-01"))
+    );
+    assert_eq!(
+        shift_date(date("2024-01-01"), -1, false),
+        Some(date("2023-12-31"))
+    );
+}
+
+#[test]
+fn shift_date_handles_leap_years() {
+    assert_eq!(
+        shift_date(date("2024-02-28"), 1, false),
+        Some(date("2024-02-29"))
+    );
+    assert_eq!(
+        shift_date(date("2023-02-28"), 1, false),
+        Some(date("2023-03-01"))
+    );
+    assert_eq!(
+        shift_date(date("2024-03-01"), -1, false),
+        Some(date("2024-02-29"))
+    );
+}
+
+#[test]
+fn shift_date_by_month_clamps_day() {
+    assert_eq!(
+        shift_date(date("2024-01-31"), 1, true),
+        Some(date("2024-02-29"))
+    );
+    assert_eq!(
+        shift_date(date("2024-03-31"), -1, true),
+        Some(date("2024-02-29"))
+    );
+    assert_eq!(
+        shift_date(date("2023-12-15"), 1, true),
+        Some(date("2024-01-15"))
+    );
+}
+
+#[test]
+fn parse_relative_date_handles_keywords_and_offsets() {
+    let today = date("2024-03-01");
+    assert_eq!(parse_relative_date("today", today), Some(today));
+    assert_eq!(
+        parse_relative_date("Yesterday", today),
+        Some(date("2024-02-29"))
+    );
+    assert_eq!(parse_relative_date("-3", today), Some(date("2024-02-27")));
+    assert_eq!(parse_relative_date("+1", today), Some(date("2024-03-02")));
+    assert_eq!(
+        parse_relative_date("2023-10-01", today),
+        Some(date("2023-10-01"))
+    );
+    assert_eq!(parse_relative_date("someday", today), None);
+}
+
+#[test]
+fn parse_relative_date_resolves_previous_weekday() {
+    // 2024-01-02 is a Tuesday
+    let today = date("2024-01-02");
+    assert_eq!(parse_relative_date("mon", today), Some(date("2024-01-01")));
+    assert_eq!(parse_relative_date("tue", today), Some(date("2023-12-26")));
+    assert_eq!(
+        parse_relative_date("friday", today),
+        Some(date("2023-12-29"))
+    );
+    assert_eq!(parse_relative_date("wed", today), Some(date("2023-12-27")));
+}
+
+#[test]
+fn parse_date_rejects_invalid_input() {
+    assert_eq!(parse_date("2023-02-30"), None);
+    assert_eq!(parse_date("not a date"), None);
+    assert_eq!(parse_date(" 2023-02-03 "), Some(date("2023-02-03")));
+}